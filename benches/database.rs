@@ -1,12 +1,20 @@
 use cindy::{
     config::HashAlgorithm,
     hash::{BoxHash, DataHasher, Hash},
-    Database, TagFilter,
+    Database, Pool, TagFilter,
 };
 use criterion::*;
 use rand::{thread_rng, Rng};
 use rusqlite::Connection;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
+use tempfile::tempdir;
+use tokio::{runtime::Runtime, sync::Mutex, task::spawn_blocking};
+
+/// How many queries are made at the same time when benchmarking concurrent reads.
+const CONCURRENT_QUERIES: u64 = 32;
+
+/// How many read-only connections the pool has.
+const POOL_READERS: usize = 4;
 
 /// Create empty, migrated database.
 fn database() -> Database {
@@ -133,7 +141,80 @@ fn insertions(c: &mut Criterion) {
     });
 }
 
-fn reading(c: &mut Criterion) {}
+/// Query which is run concurrently by the read benchmarks.
+fn query_tag(database: &Database) {
+    database
+        .query_hashes(&mut [TagFilter::new(Some("tag0"), Some("value0")).exists()].iter())
+        .unwrap();
+}
+
+/// Benchmark concurrent queries, queueing for the single writer connection compared to using a
+/// pool of read-only connections.
+fn reading(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    group.warm_up_time(Duration::from_secs(1));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(CONCURRENT_QUERIES));
+
+    // readers need a database file in WAL mode
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("index.db");
+    database_full(10000, &[("tag0", 3), ("tag1", 7)], &[("half", 0.5)])
+        .execute("VACUUM INTO ?", [path.to_str().unwrap()])
+        .unwrap();
+    let writer: Database = Connection::open(&path).unwrap().into();
+    writer.wal().unwrap();
+    let writer = Arc::new(Mutex::new(writer));
+    let pool = Arc::new(Pool::new(&path, POOL_READERS));
+    let runtime = Runtime::new().unwrap();
+
+    group.bench_function("writer", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let tasks: Vec<_> = (0..CONCURRENT_QUERIES)
+                    .map(|_| {
+                        let writer = writer.clone();
+                        tokio::spawn(async move {
+                            let database = writer.lock_owned().await;
+                            spawn_blocking(move || query_tag(&database)).await.unwrap();
+                        })
+                    })
+                    .collect();
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            });
+        });
+    });
+
+    let pool_queries = || {
+        runtime.block_on(async {
+            let tasks: Vec<_> = (0..CONCURRENT_QUERIES)
+                .map(|_| {
+                    let pool = pool.clone();
+                    tokio::spawn(async move {
+                        let database = pool.get().await.unwrap();
+                        spawn_blocking(move || query_tag(&database)).await.unwrap();
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        });
+    };
+
+    group.bench_function("pool", |b| b.iter(pool_queries));
+
+    // with the writer connection busy, the queries above would not finish at all
+    group.bench_function("pool_during_write", |b| {
+        let mut database = writer.blocking_lock();
+        let transaction = database.transaction().unwrap();
+        transaction.tag_name_create("writing", None).unwrap();
+        b.iter(pool_queries);
+        drop(transaction);
+    });
+}
 
 /// Benchmark queries.
 fn querying(c: &mut Criterion) {
@@ -84,3 +84,22 @@ impl<S: Borrow<str>> DeleteRequest for QueryTagRemove<S> {
 impl<S: Borrow<str>> RequestMethod for QueryTagRemove<S> {
     type Method = Delete<Self>;
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobCancel {
+    pub id: u64,
+}
+
+impl DeleteRequest for JobCancel {
+    type Query = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/jobs/{}", self.id).into()
+    }
+
+    fn query(&self) -> Self::Query {}
+}
+
+impl RequestMethod for JobCancel {
+    type Method = Delete<Self>;
+}
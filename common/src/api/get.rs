@@ -1,8 +1,9 @@
 use crate::{
-    api::query::TagQuery,
+    api::query::{ClipQuery, CropQuery, LabelQuery, TagQuery, TranscodeQuery},
     cache::*,
     tag::{TagNameInfo, TagValueInfo},
-    BoxHash, Hash, Tag, TagPredicate,
+    Attributes, BoxHash, ChangeBatch, Duplicate, Hash, JobInfo, Label, Mutation, Rectangle,
+    Sequence, Tag, TagPredicate, TranscodeProfile, User,
 };
 use bytes::Bytes;
 use restless::{data::Json, methods::Get, query::Qs, GetRequest, RequestMethod};
@@ -43,7 +44,12 @@ impl<H: Borrow<Hash>> RequestMethod for FileContent<H> {
     type Method = Get<Self>;
 }
 
-impl<H: Borrow<Hash>> Invalidatable for FileContent<H> {}
+impl<H: Borrow<Hash>> Invalidatable for FileContent<H> {
+    /// Files never change, so this is never invalidated.
+    fn invalidated_by(&self, _mutation: &Mutation) -> bool {
+        false
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FileTags<H: Borrow<Hash> = BoxHash, S: Borrow<str> = String> {
@@ -69,12 +75,230 @@ impl<H: Borrow<Hash>, S: Borrow<str>> GetRequest for FileTags<H, S> {
     }
 }
 
-impl<H: Borrow<Hash>, S: Borrow<str>> Invalidatable for FileTags<H, S> {}
+impl<H: Borrow<Hash>, S: Borrow<str>> Invalidatable for FileTags<H, S> {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(
+            mutation,
+            Mutation::TagNames { .. } | Mutation::TagValues { .. } | Mutation::FileTags { .. }
+        ) && mutation.affects_file(self.hash.borrow())
+            && mutation.affects_tag(
+                self.name.as_ref().map(Borrow::borrow),
+                self.value.as_ref().map(Borrow::borrow),
+            )
+    }
+}
 
 impl<H: Borrow<Hash>, S: Borrow<str>> RequestMethod for FileTags<H, S> {
     type Method = Get<Self>;
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileLabels<H: Borrow<Hash> = BoxHash, S: Borrow<str> = String> {
+    pub hash: H,
+    pub name: Option<S>,
+    pub value: Option<S>,
+    pub attribute_name: Option<S>,
+    pub attribute_value: Option<S>,
+}
+
+impl<H: Borrow<Hash>, S: Borrow<str>> GetRequest for FileLabels<H, S> {
+    type Response = Json<BTreeSet<(Tag, Label, Attributes)>>;
+    type Query = Qs<LabelQuery<String>>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/labels", self.hash.borrow()).into()
+    }
+
+    fn query(&self) -> Self::Query {
+        LabelQuery {
+            name: self.name.as_ref().map(Borrow::borrow).map(Into::into),
+            value: self.value.as_ref().map(Borrow::borrow).map(Into::into),
+            attribute_name: self
+                .attribute_name
+                .as_ref()
+                .map(Borrow::borrow)
+                .map(Into::into),
+            attribute_value: self
+                .attribute_value
+                .as_ref()
+                .map(Borrow::borrow)
+                .map(Into::into),
+        }
+        .into()
+    }
+}
+
+impl<H: Borrow<Hash>, S: Borrow<str>> Invalidatable for FileLabels<H, S> {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(
+            mutation,
+            Mutation::TagNames { .. } | Mutation::TagValues { .. } | Mutation::Labels { .. }
+        ) && mutation.affects_file(self.hash.borrow())
+            && mutation.affects_tag(
+                self.name.as_ref().map(Borrow::borrow),
+                self.value.as_ref().map(Borrow::borrow),
+            )
+    }
+}
+
+impl<H: Borrow<Hash>, S: Borrow<str>> RequestMethod for FileLabels<H, S> {
+    type Method = Get<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileAttributes<H: Borrow<Hash> = BoxHash> {
+    pub hash: H,
+}
+
+impl<H: Borrow<Hash>> GetRequest for FileAttributes<H> {
+    type Response = Json<Attributes>;
+    type Query = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/attributes", self.hash.borrow()).into()
+    }
+
+    fn query(&self) -> Self::Query {}
+}
+
+impl<H: Borrow<Hash>> Invalidatable for FileAttributes<H> {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(mutation, Mutation::FileAttributes { .. })
+            && mutation.affects_file(self.hash.borrow())
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for FileAttributes<H> {
+    type Method = Get<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileCrop<H: Borrow<Hash> = BoxHash> {
+    pub hash: H,
+    pub rectangle: Rectangle,
+    pub time: Option<u64>,
+}
+
+impl<H: Borrow<Hash>> GetRequest for FileCrop<H> {
+    type Response = Bytes;
+    type Query = Qs<CropQuery>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/crop", self.hash.borrow()).into()
+    }
+
+    fn query(&self) -> Self::Query {
+        CropQuery {
+            x1: self.rectangle.start.x,
+            y1: self.rectangle.start.y,
+            x2: self.rectangle.end.x,
+            y2: self.rectangle.end.y,
+            time: self.time,
+        }
+        .into()
+    }
+}
+
+impl<H: Borrow<Hash>> Invalidatable for FileCrop<H> {
+    fn invalidated_by(&self, _mutation: &Mutation) -> bool {
+        false
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for FileCrop<H> {
+    type Method = Get<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileClip<H: Borrow<Hash> = BoxHash> {
+    pub hash: H,
+    pub sequence: Sequence,
+}
+
+impl<H: Borrow<Hash>> GetRequest for FileClip<H> {
+    type Response = Bytes;
+    type Query = Qs<ClipQuery>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/clip", self.hash.borrow()).into()
+    }
+
+    fn query(&self) -> Self::Query {
+        ClipQuery {
+            start: self.sequence.start,
+            end: self.sequence.end,
+        }
+        .into()
+    }
+}
+
+impl<H: Borrow<Hash>> Invalidatable for FileClip<H> {
+    fn invalidated_by(&self, _mutation: &Mutation) -> bool {
+        false
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for FileClip<H> {
+    type Method = Get<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileTranscode<H: Borrow<Hash> = BoxHash> {
+    pub hash: H,
+    pub profile: TranscodeProfile,
+}
+
+impl<H: Borrow<Hash>> GetRequest for FileTranscode<H> {
+    type Response = Bytes;
+    type Query = Qs<TranscodeQuery>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/transcode", self.hash.borrow()).into()
+    }
+
+    fn query(&self) -> Self::Query {
+        TranscodeQuery {
+            profile: self.profile,
+        }
+        .into()
+    }
+}
+
+impl<H: Borrow<Hash>> Invalidatable for FileTranscode<H> {
+    fn invalidated_by(&self, _mutation: &Mutation) -> bool {
+        false
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for FileTranscode<H> {
+    type Method = Get<Self>;
+}
+
+/// User that is logged in, `None` if authentication is disabled.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CurrentUser;
+
+impl GetRequest for CurrentUser {
+    type Response = Json<Option<User>>;
+    type Query = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/auth/user".into()
+    }
+
+    fn query(&self) -> Self::Query {}
+}
+
+impl Invalidatable for CurrentUser {
+    fn invalidated_by(&self, _mutation: &Mutation) -> bool {
+        false
+    }
+}
+
+impl RequestMethod for CurrentUser {
+    type Method = Get<Self>;
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct QueryFiles<'a> {
     #[serde(default)]
@@ -94,6 +318,18 @@ impl<'a> GetRequest for QueryFiles<'a> {
     }
 }
 
+impl<'a> Invalidatable for QueryFiles<'a> {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(
+            mutation,
+            Mutation::Files
+                | Mutation::TagNames { .. }
+                | Mutation::TagValues { .. }
+                | Mutation::FileTags { .. }
+        )
+    }
+}
+
 impl<'a> RequestMethod for QueryFiles<'a> {
     type Method = Get<Self>;
 }
@@ -112,6 +348,15 @@ impl GetRequest for TagNames {
     fn query(&self) -> Self::Query {}
 }
 
+impl Invalidatable for TagNames {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(
+            mutation,
+            Mutation::TagNames { .. } | Mutation::TagValues { .. }
+        )
+    }
+}
+
 impl RequestMethod for TagNames {
     type Method = Get<Self>;
 }
@@ -143,6 +388,21 @@ where
     }
 }
 
+impl<N: Borrow<str>, V: Borrow<str>> Invalidatable for TagList<N, V> {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        match mutation {
+            Mutation::Files => true,
+            Mutation::TagNames { .. } | Mutation::TagValues { .. } | Mutation::FileTags { .. } => {
+                mutation.affects_tag(
+                    self.name.as_ref().map(Borrow::borrow),
+                    self.value.as_ref().map(Borrow::borrow),
+                )
+            }
+            _ => false,
+        }
+    }
+}
+
 impl<N: Borrow<str>, V: Borrow<str>> RequestMethod for TagList<N, V> {
     type Method = Get<Self>;
 }
@@ -163,6 +423,12 @@ impl<P: Borrow<Path>> GetRequest for FrontendFile<P> {
     fn query(&self) -> Self::Query {}
 }
 
+impl<P: Borrow<Path>> Invalidatable for FrontendFile<P> {
+    fn invalidated_by(&self, _mutation: &Mutation) -> bool {
+        false
+    }
+}
+
 impl<P: Borrow<Path>> RequestMethod for FrontendFile<P> {
     type Method = Get<Self>;
 }
@@ -198,6 +464,167 @@ impl GetRequest for QueryTags {
     }
 }
 
+impl Invalidatable for QueryTags {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(
+            mutation,
+            Mutation::Files
+                | Mutation::TagNames { .. }
+                | Mutation::TagValues { .. }
+                | Mutation::FileTags { .. }
+        )
+    }
+}
+
 impl RequestMethod for QueryTags {
     type Method = Get<Self>;
 }
+
+/// Background jobs, both running and recently finished.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Jobs;
+
+impl GetRequest for Jobs {
+    type Response = Json<Vec<JobInfo>>;
+    type Query = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/jobs".into()
+    }
+
+    fn query(&self) -> Self::Query {}
+}
+
+impl Invalidatable for Jobs {}
+
+impl RequestMethod for Jobs {
+    type Method = Get<Self>;
+}
+
+/// Recent batches of changes to tags and labels, newest first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct History {
+    /// Maximum amount of batches to return, the server picks a default if not set.
+    #[serde(default)]
+    pub limit: Option<u64>,
+    /// Include batches whose changes were all reverted.
+    #[serde(default)]
+    pub reverted: bool,
+}
+
+impl GetRequest for History {
+    type Response = Json<Vec<ChangeBatch>>;
+    type Query = Qs<Self>;
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/history".into()
+    }
+
+    fn query(&self) -> Self::Query {
+        self.clone().into()
+    }
+}
+
+impl Invalidatable for History {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        !matches!(mutation, Mutation::Files | Mutation::FileAttributes { .. })
+    }
+}
+
+impl RequestMethod for History {
+    type Method = Get<Self>;
+}
+
+/// Groups of images which look alike, judged by the Hamming distance of their perceptual hashes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SimilarFiles {
+    /// Most bits in which the perceptual hashes may differ, the server picks a default if not set.
+    #[serde(default)]
+    pub distance: Option<u32>,
+}
+
+impl GetRequest for SimilarFiles {
+    type Response = Json<Vec<Vec<BoxHash>>>;
+    type Query = Qs<Self>;
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/similar".into()
+    }
+
+    fn query(&self) -> Self::Query {
+        self.clone().into()
+    }
+}
+
+impl Invalidatable for SimilarFiles {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(mutation, Mutation::Files)
+    }
+}
+
+impl RequestMethod for SimilarFiles {
+    type Method = Get<Self>;
+}
+
+/// Other files which look like this one, within the same distance as with [`SimilarFiles`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileSimilar<H: Borrow<Hash> = BoxHash> {
+    pub hash: H,
+    /// Most bits in which the perceptual hashes may differ, the server picks a default if not set.
+    pub distance: Option<u32>,
+}
+
+impl<H: Borrow<Hash>> GetRequest for FileSimilar<H> {
+    type Response = Json<Vec<BoxHash>>;
+    type Query = Qs<SimilarFiles>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/similar", self.hash.borrow()).into()
+    }
+
+    fn query(&self) -> Self::Query {
+        SimilarFiles {
+            distance: self.distance,
+        }
+        .into()
+    }
+}
+
+impl<H: Borrow<Hash>> Invalidatable for FileSimilar<H> {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        matches!(mutation, Mutation::Files)
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for FileSimilar<H> {
+    type Method = Get<Self>;
+}
+
+/// Files which were added from more than one path.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duplicates;
+
+impl GetRequest for Duplicates {
+    type Response = Json<Vec<Duplicate>>;
+    type Query = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/duplicates".into()
+    }
+
+    fn query(&self) -> Self::Query {}
+}
+
+impl Invalidatable for Duplicates {
+    fn invalidated_by(&self, mutation: &Mutation) -> bool {
+        match mutation {
+            Mutation::Files => true,
+            Mutation::FileTags { .. } => mutation.affects_tag(Some("path"), None),
+            _ => false,
+        }
+    }
+}
+
+impl RequestMethod for Duplicates {
+    type Method = Get<Self>;
+}
@@ -1,3 +1,4 @@
+use crate::{hash::*, AttributesEdit, Label, Tag};
 use restless::{data::Json, methods::Patch, PatchRequest, RequestMethod};
 use serde::{Deserialize, Serialize};
 use std::borrow::{Borrow, Cow};
@@ -39,3 +40,60 @@ impl<S: Borrow<str>> PatchRequest for TagNameEdit<S> {
 impl<S: Borrow<str>> RequestMethod for TagNameEdit<S> {
     type Method = Patch<Self>;
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileAttributesEdit<H: Borrow<Hash>> {
+    pub hash: H,
+    pub attributes: AttributesEdit,
+}
+
+impl<H: Borrow<Hash>> PatchRequest for FileAttributesEdit<H> {
+    type Request = Json<AttributesEdit>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/attributes", self.hash.borrow()).into()
+    }
+
+    fn body(&self) -> Self::Request {
+        Json(self.attributes.clone())
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for FileAttributesEdit<H> {
+    type Method = Patch<Self>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LabelAttributesEditRequest {
+    pub tag: Tag,
+    pub label: Label,
+    pub attributes: AttributesEdit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LabelAttributesEdit<H: Borrow<Hash>> {
+    pub hash: H,
+    pub tag: Tag,
+    pub label: Label,
+    pub attributes: AttributesEdit,
+}
+
+impl<H: Borrow<Hash>> PatchRequest for LabelAttributesEdit<H> {
+    type Request = Json<LabelAttributesEditRequest>;
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/file/{}/labels/attributes", self.hash.borrow()).into()
+    }
+
+    fn body(&self) -> Self::Request {
+        Json(LabelAttributesEditRequest {
+            tag: self.tag.clone(),
+            label: self.label,
+            attributes: self.attributes.clone(),
+        })
+    }
+}
+
+impl<H: Borrow<Hash>> RequestMethod for LabelAttributesEdit<H> {
+    type Method = Patch<Self>;
+}
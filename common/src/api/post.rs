@@ -1,4 +1,4 @@
-use crate::{hash::*, TagPredicate};
+use crate::{hash::*, BatchOperation, JobStart, TagPredicate};
 use restless::{data::Json, methods::Post, PostRequest, RequestMethod};
 use serde::{Deserialize, Serialize};
 use std::borrow::{Borrow, Cow};
@@ -133,3 +133,133 @@ impl<S: Borrow<str>> PostRequest for QueryTagCreate<S> {
 impl<S: Borrow<str>> RequestMethod for QueryTagCreate<S> {
     type Method = Post<Self>;
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Login<S: Borrow<str> = String> {
+    pub name: S,
+    pub password: S,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LoginBody<'a> {
+    pub name: Cow<'a, str>,
+    pub password: Cow<'a, str>,
+}
+
+impl<S: Borrow<str>> PostRequest for Login<S> {
+    type Request = Json<LoginBody<'static>>;
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/auth/login".into()
+    }
+
+    fn body(&self) -> Self::Request {
+        Json(LoginBody {
+            name: self.name.borrow().to_string().into(),
+            password: self.password.borrow().to_string().into(),
+        })
+    }
+}
+
+impl<S: Borrow<str>> RequestMethod for Login<S> {
+    type Method = Post<Self>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Logout;
+
+impl PostRequest for Logout {
+    type Request = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/auth/logout".into()
+    }
+
+    fn body(&self) -> Self::Request {}
+}
+
+impl RequestMethod for Logout {
+    type Method = Post<Self>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobCreate {
+    pub job: JobStart,
+}
+
+impl PostRequest for JobCreate {
+    type Request = Json<JobStart>;
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/jobs".into()
+    }
+
+    fn body(&self) -> Self::Request {
+        Json(self.job.clone())
+    }
+}
+
+impl RequestMethod for JobCreate {
+    type Method = Post<Self>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Batch {
+    pub operations: Vec<BatchOperation>,
+}
+
+impl PostRequest for Batch {
+    type Request = Json<Vec<BatchOperation>>;
+
+    fn path(&self) -> Cow<'_, str> {
+        "api/v1/batch".into()
+    }
+
+    fn body(&self) -> Self::Request {
+        Json(self.operations.clone())
+    }
+}
+
+impl RequestMethod for Batch {
+    type Method = Post<Self>;
+}
+
+/// Revert all changes of a batch which were not reverted yet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HistoryBatchRevert {
+    pub id: u64,
+}
+
+impl PostRequest for HistoryBatchRevert {
+    type Request = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/history/{}/revert", self.id).into()
+    }
+
+    fn body(&self) -> Self::Request {}
+}
+
+impl RequestMethod for HistoryBatchRevert {
+    type Method = Post<Self>;
+}
+
+/// Revert a single change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HistoryChangeRevert {
+    pub id: u64,
+}
+
+impl PostRequest for HistoryChangeRevert {
+    type Request = ();
+
+    fn path(&self) -> Cow<'_, str> {
+        format!("api/v1/history/changes/{}/revert", self.id).into()
+    }
+
+    fn body(&self) -> Self::Request {}
+}
+
+impl RequestMethod for HistoryChangeRevert {
+    type Method = Post<Self>;
+}
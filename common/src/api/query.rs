@@ -1,3 +1,4 @@
+use crate::{Tag, TranscodeProfile};
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
 
@@ -6,3 +7,45 @@ pub struct TagQuery<S: Borrow<str>> {
     pub name: Option<S>,
     pub value: Option<S>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LabelQuery<S: Borrow<str>> {
+    pub name: Option<S>,
+    pub value: Option<S>,
+    /// Only return labels which have an attribute with this name.
+    pub attribute_name: Option<S>,
+    /// Only return labels which have an attribute with this value.
+    pub attribute_value: Option<S>,
+}
+
+/// Region of a file to crop, optionally at a specific time for videos.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CropQuery {
+    pub x1: u64,
+    pub y1: u64,
+    pub x2: u64,
+    pub y2: u64,
+    /// Time of the video frame to crop, in milliseconds.
+    pub time: Option<u64>,
+}
+
+/// Time range of a video to clip, in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClipQuery {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Profile to transcode a video with.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TranscodeQuery {
+    #[serde(default)]
+    pub profile: TranscodeProfile,
+}
+
+/// Tags to add to an uploaded file, in addition to the ones from scanning it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UploadQuery {
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
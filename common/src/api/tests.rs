@@ -1,5 +1,8 @@
 use super::*;
-use crate::hash::Hash;
+use crate::{
+    hash::{BoxHash, Hash},
+    Mutation, Point, Rectangle, Sequence, TranscodeProfile,
+};
 use restless::*;
 use std::path::Path;
 
@@ -23,6 +26,7 @@ fn test_uri() {
             "index.html",
         ),
         (&TagNames, "api/v1/tags"),
+        (&CurrentUser, "api/v1/auth/user"),
         (
             &FileContent {
                 hash: Hash::new(&[0xab]),
@@ -37,9 +41,346 @@ fn test_uri() {
             },
             "api/v1/file/ab/tags",
         ),
+        (
+            &FileCrop {
+                hash: Hash::new(&[0xab]),
+                rectangle: Rectangle {
+                    start: Point::new(10, 20),
+                    end: Point::new(30, 40),
+                },
+                time: None,
+            },
+            "api/v1/file/ab/crop?x1=10&y1=20&x2=30&y2=40",
+        ),
+        (
+            &FileClip {
+                hash: Hash::new(&[0xab]),
+                sequence: Sequence {
+                    start: 1000,
+                    end: 2500,
+                },
+            },
+            "api/v1/file/ab/clip?start=1000&end=2500",
+        ),
+        (
+            &FileTranscode {
+                hash: Hash::new(&[0xab]),
+                profile: TranscodeProfile::Mp4,
+            },
+            "api/v1/file/ab/transcode?profile=mp4",
+        ),
     ];
 
     for (request, uri) in pairs {
         assert_eq!(&request.uri(), uri);
     }
 }
+
+#[test]
+fn test_invalidated_by() {
+    let file = Hash::new(&[0xab]);
+    let tagged = Mutation::FileTags {
+        file: Some(file.into()),
+        name: Some("name".into()),
+        value: Some("value".into()),
+    };
+    let tags = FileTags::<&Hash, &str> {
+        hash: file,
+        name: None,
+        value: None,
+    };
+    assert!(tags.invalidated_by(&tagged));
+    assert!(!FileTags {
+        hash: file,
+        name: Some("other"),
+        value: None
+    }
+    .invalidated_by(&tagged));
+    assert!(!FileTags::<&Hash, &str> {
+        hash: Hash::new(&[0xcd]),
+        name: None,
+        value: None
+    }
+    .invalidated_by(&tagged));
+    assert!(!tags.invalidated_by(&Mutation::FileAttributes { file: None }));
+    assert!(!FileContent { hash: file }.invalidated_by(&tagged));
+    assert!(QueryFiles {
+        query: Default::default()
+    }
+    .invalidated_by(&tagged));
+    assert!(TagList::<&str, &str> {
+        name: Some("name"),
+        value: None
+    }
+    .invalidated_by(&tagged));
+    assert!(!TagList::<&str, &str> {
+        name: Some("other"),
+        value: None
+    }
+    .invalidated_by(&tagged));
+    assert!(!TagNames.invalidated_by(&tagged));
+    assert!(History::default().invalidated_by(&tagged));
+    assert!(!History::default().invalidated_by(&Mutation::Files));
+    assert!(!SimilarFiles::default().invalidated_by(&tagged));
+    assert!(SimilarFiles::default().invalidated_by(&Mutation::Files));
+    let similar = FileSimilar {
+        hash: file,
+        distance: None,
+    };
+    assert!(!similar.invalidated_by(&tagged));
+    assert!(similar.invalidated_by(&Mutation::Files));
+    assert!(!Duplicates.invalidated_by(&tagged));
+    assert!(Duplicates.invalidated_by(&Mutation::Files));
+    assert!(Duplicates.invalidated_by(&Mutation::FileTags {
+        file: Some(file.into()),
+        name: Some("path".into()),
+        value: None,
+    }));
+}
+
+mod invalidation {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    /// Simplified model of the tag tables, used to determine which responses a change affects.
+    #[derive(Clone, Debug, Default)]
+    struct Model {
+        values: BTreeSet<(String, String)>,
+        file_tags: BTreeSet<(u8, String, String)>,
+    }
+
+    /// Change made through the API, along with the mutation the server publishes for it.
+    #[derive(Clone, Debug)]
+    enum Change {
+        TagValueCreate(String, String),
+        TagValueDelete(Option<String>, Option<String>),
+        FileTagCreate(u8, String, String),
+        FileTagDelete(u8, Option<String>, Option<String>),
+        QueryTagCreate(BTreeSet<u8>, String, String),
+        QueryTagDelete(BTreeSet<u8>, Option<String>, Option<String>),
+    }
+
+    /// Requests whose responses are derived from the model.
+    #[derive(Clone, Debug)]
+    enum Observed {
+        FileTags(u8, Option<String>, Option<String>),
+        TagList(Option<String>, Option<String>),
+        TagNames,
+        QueryTags(Option<String>, Option<String>),
+    }
+
+    fn matches(filter: &Option<String>, value: &str) -> bool {
+        filter
+            .as_deref()
+            .map(|filter| filter == value)
+            .unwrap_or(true)
+    }
+
+    fn hash(file: u8) -> BoxHash {
+        Hash::new(&[file]).into()
+    }
+
+    impl Change {
+        fn apply(&self, model: &mut Model) {
+            match self {
+                Change::TagValueCreate(name, value) => {
+                    model.values.insert((name.clone(), value.clone()));
+                }
+                Change::TagValueDelete(name, value) => {
+                    model
+                        .values
+                        .retain(|(n, v)| !(matches(name, n) && matches(value, v)));
+                    model
+                        .file_tags
+                        .retain(|(_, n, v)| !(matches(name, n) && matches(value, v)));
+                }
+                Change::FileTagCreate(file, name, value) => {
+                    if model.values.contains(&(name.clone(), value.clone())) {
+                        model.file_tags.insert((*file, name.clone(), value.clone()));
+                    }
+                }
+                Change::FileTagDelete(file, name, value) => {
+                    model
+                        .file_tags
+                        .retain(|(f, n, v)| !(f == file && matches(name, n) && matches(value, v)));
+                }
+                Change::QueryTagCreate(files, name, value) => {
+                    for file in files {
+                        Change::FileTagCreate(*file, name.clone(), value.clone()).apply(model);
+                    }
+                }
+                Change::QueryTagDelete(files, name, value) => {
+                    for file in files {
+                        Change::FileTagDelete(*file, name.clone(), value.clone()).apply(model);
+                    }
+                }
+            }
+        }
+
+        fn mutation(&self) -> Mutation {
+            match self {
+                Change::TagValueCreate(name, value) => Mutation::TagValues {
+                    name: Some(name.clone()),
+                    value: Some(value.clone()),
+                },
+                Change::TagValueDelete(name, value) => Mutation::TagValues {
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                Change::FileTagCreate(file, name, value) => Mutation::FileTags {
+                    file: Some(hash(*file)),
+                    name: Some(name.clone()),
+                    value: Some(value.clone()),
+                },
+                Change::FileTagDelete(file, name, value) => Mutation::FileTags {
+                    file: Some(hash(*file)),
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                Change::QueryTagCreate(_, name, value) => Mutation::FileTags {
+                    file: None,
+                    name: Some(name.clone()),
+                    value: Some(value.clone()),
+                },
+                Change::QueryTagDelete(_, name, value) => Mutation::FileTags {
+                    file: None,
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+            }
+        }
+    }
+
+    impl Observed {
+        fn response(&self, model: &Model) -> BTreeMap<(String, String), u64> {
+            match self {
+                Observed::FileTags(file, name, value) => model
+                    .file_tags
+                    .iter()
+                    .filter(|(f, n, v)| f == file && matches(name, n) && matches(value, v))
+                    .map(|(_, n, v)| ((n.clone(), v.clone()), 1))
+                    .collect(),
+                Observed::TagList(name, value) => model
+                    .values
+                    .iter()
+                    .filter(|(n, v)| matches(name, n) && matches(value, v))
+                    .map(|(n, v)| {
+                        let files = model
+                            .file_tags
+                            .iter()
+                            .filter(|(_, fn_, fv)| fn_ == n && fv == v)
+                            .count();
+                        ((n.clone(), v.clone()), files as u64)
+                    })
+                    .collect(),
+                Observed::TagNames => {
+                    let mut names = BTreeMap::new();
+                    for (name, _) in &model.values {
+                        *names.entry((name.clone(), String::new())).or_default() += 1;
+                    }
+                    names
+                }
+                Observed::QueryTags(name, value) => model
+                    .file_tags
+                    .iter()
+                    .filter(|(_, n, v)| matches(name, n) && matches(value, v))
+                    .map(|(_, n, v)| ((n.clone(), v.clone()), 1))
+                    .collect(),
+            }
+        }
+
+        fn invalidated_by(&self, mutation: &Mutation) -> bool {
+            match self {
+                Observed::FileTags(file, name, value) => FileTags {
+                    hash: hash(*file),
+                    name: name.clone(),
+                    value: value.clone(),
+                }
+                .invalidated_by(mutation),
+                Observed::TagList(name, value) => TagList {
+                    name: name.clone(),
+                    value: value.clone(),
+                }
+                .invalidated_by(mutation),
+                Observed::TagNames => TagNames.invalidated_by(mutation),
+                Observed::QueryTags(name, value) => QueryTags {
+                    name: name.clone(),
+                    value: value.clone(),
+                    query: vec![],
+                    mode: QueryTagsMode::Union,
+                }
+                .invalidated_by(mutation),
+            }
+        }
+    }
+
+    fn arb_name() -> impl Strategy<Value = String> {
+        prop_oneof![Just("a".to_string()), Just("b".to_string())]
+    }
+
+    fn arb_value() -> impl Strategy<Value = String> {
+        prop_oneof![Just("x".to_string()), Just("y".to_string())]
+    }
+
+    fn arb_file() -> impl Strategy<Value = u8> {
+        0..3u8
+    }
+
+    fn arb_files() -> impl Strategy<Value = BTreeSet<u8>> {
+        proptest::collection::btree_set(arb_file(), 0..3)
+    }
+
+    fn arb_model() -> impl Strategy<Value = Model> {
+        (
+            proptest::collection::btree_set((arb_name(), arb_value()), 0..4),
+            proptest::collection::btree_set((arb_file(), arb_name(), arb_value()), 0..8),
+        )
+            .prop_map(|(values, file_tags)| Model {
+                values: values
+                    .into_iter()
+                    .chain(file_tags.iter().map(|(_, n, v)| (n.clone(), v.clone())))
+                    .collect(),
+                file_tags,
+            })
+    }
+
+    fn arb_change() -> impl Strategy<Value = Change> {
+        let name = || proptest::option::of(arb_name());
+        let value = || proptest::option::of(arb_value());
+        prop_oneof![
+            (arb_name(), arb_value()).prop_map(|(n, v)| Change::TagValueCreate(n, v)),
+            (name(), value()).prop_map(|(n, v)| Change::TagValueDelete(n, v)),
+            (arb_file(), arb_name(), arb_value())
+                .prop_map(|(f, n, v)| Change::FileTagCreate(f, n, v)),
+            (arb_file(), name(), value()).prop_map(|(f, n, v)| Change::FileTagDelete(f, n, v)),
+            (arb_files(), arb_name(), arb_value())
+                .prop_map(|(f, n, v)| Change::QueryTagCreate(f, n, v)),
+            (arb_files(), name(), value()).prop_map(|(f, n, v)| Change::QueryTagDelete(f, n, v)),
+        ]
+    }
+
+    fn arb_observed() -> impl Strategy<Value = Observed> {
+        let name = || proptest::option::of(arb_name());
+        let value = || proptest::option::of(arb_value());
+        prop_oneof![
+            (arb_file(), name(), value()).prop_map(|(f, n, v)| Observed::FileTags(f, n, v)),
+            (name(), value()).prop_map(|(n, v)| Observed::TagList(n, v)),
+            Just(Observed::TagNames),
+            (name(), value()).prop_map(|(n, v)| Observed::QueryTags(n, v)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn no_stale_entries(model in arb_model(), change in arb_change(), observed in arb_observed()) {
+            let before = observed.response(&model);
+            let mut changed = model.clone();
+            change.apply(&mut changed);
+            let after = observed.response(&changed);
+            if before != after {
+                prop_assert!(observed.invalidated_by(&change.mutation()));
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use std::collections::BTreeMap;
+
+/// Free-form key/value attributes, attached to files and labels.
+///
+/// These are used to record information that does not fit into tags, such as whether a labelled
+/// object is occluded, a confidence value or a comment.
+pub type Attributes = BTreeMap<String, String>;
+
+/// Changes to attributes.
+///
+/// Attributes mapped to a value are set (overwriting any previous value), attributes mapped to
+/// `None` are removed.
+pub type AttributesEdit = BTreeMap<String, Option<String>>;
@@ -0,0 +1,201 @@
+use crate::{BoxHash, Label, Mutation, Tag};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Single change applied as part of a batch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    /// Add a tag to a file.
+    FileTagAdd {
+        file: BoxHash,
+        name: String,
+        value: String,
+    },
+    /// Remove tags from a file, `None` matches any.
+    FileTagRemove {
+        file: BoxHash,
+        name: Option<String>,
+        value: Option<String>,
+    },
+    /// Create a tag name, optionally with a display value.
+    TagNameCreate {
+        name: String,
+        display: Option<String>,
+    },
+    /// Delete a tag name which has no values.
+    TagNameDelete { name: String },
+    /// Rename a tag name.
+    TagNameRename { name: String, new: String },
+    /// Create a tag value, optionally with a display value.
+    TagValueCreate {
+        name: String,
+        value: String,
+        display: Option<String>,
+    },
+    /// Delete a tag value which is not used by any files.
+    TagValueDelete { name: String, value: String },
+    /// Change the display value of a tag name.
+    TagNameDisplay { name: String, display: String },
+    /// Change the display value of a tag value.
+    TagValueDisplay {
+        name: String,
+        value: String,
+        display: String,
+    },
+    /// Add a label to a tag of a file.
+    LabelAdd {
+        file: BoxHash,
+        tag: Tag,
+        label: Label,
+    },
+    /// Remove a label from a tag of a file.
+    LabelRemove {
+        file: BoxHash,
+        tag: Tag,
+        label: Label,
+    },
+}
+
+impl BatchOperation {
+    /// Mutation caused by applying this operation.
+    pub fn mutation(&self) -> Mutation {
+        match self {
+            BatchOperation::FileTagAdd { file, name, value } => Mutation::FileTags {
+                file: Some(file.clone()),
+                name: Some(name.clone()),
+                value: Some(value.clone()),
+            },
+            BatchOperation::FileTagRemove { file, name, value } => Mutation::FileTags {
+                file: Some(file.clone()),
+                name: name.clone(),
+                value: value.clone(),
+            },
+            BatchOperation::TagValueCreate { name, value, .. }
+            | BatchOperation::TagValueDelete { name, value }
+            | BatchOperation::TagValueDisplay { name, value, .. } => Mutation::TagValues {
+                name: Some(name.clone()),
+                value: Some(value.clone()),
+            },
+            BatchOperation::TagNameCreate { name, .. }
+            | BatchOperation::TagNameDelete { name }
+            | BatchOperation::TagNameDisplay { name, .. } => Mutation::TagNames {
+                name: Some(name.clone()),
+            },
+            // renaming changes everything that mentions the tag name
+            BatchOperation::TagNameRename { .. } => Mutation::TagNames { name: None },
+            BatchOperation::LabelAdd { file, tag, .. }
+            | BatchOperation::LabelRemove { file, tag, .. } => Mutation::Labels {
+                file: Some(file.clone()),
+                name: Some(tag.name().into()),
+                value: Some(tag.value().into()),
+            },
+        }
+    }
+
+    /// File this operation applies to, if any.
+    pub fn file_mut(&mut self) -> Option<&mut BoxHash> {
+        match self {
+            BatchOperation::FileTagAdd { file, .. }
+            | BatchOperation::FileTagRemove { file, .. }
+            | BatchOperation::LabelAdd { file, .. }
+            | BatchOperation::LabelRemove { file, .. } => Some(file),
+            _ => None,
+        }
+    }
+}
+
+impl Display for BatchOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let any = |filter: &Option<String>| filter.clone().unwrap_or_else(|| "*".into());
+        match self {
+            BatchOperation::FileTagAdd { file, name, value } => {
+                write!(f, "add tag {name}:{value} to {file}")
+            }
+            BatchOperation::FileTagRemove { file, name, value } => {
+                write!(f, "remove tag {}:{} from {file}", any(name), any(value))
+            }
+            BatchOperation::TagNameCreate { name, .. } => write!(f, "create tag {name}"),
+            BatchOperation::TagNameDelete { name } => write!(f, "delete tag {name}"),
+            BatchOperation::TagNameRename { name, new } => write!(f, "rename tag {name} to {new}"),
+            BatchOperation::TagValueCreate { name, value, .. } => {
+                write!(f, "create tag {name}:{value}")
+            }
+            BatchOperation::TagValueDelete { name, value } => {
+                write!(f, "delete tag {name}:{value}")
+            }
+            BatchOperation::TagNameDisplay { name, display } => {
+                write!(f, "display tag {name} as {display:?}")
+            }
+            BatchOperation::TagValueDisplay {
+                name,
+                value,
+                display,
+            } => write!(f, "display tag {name}:{value} as {display:?}"),
+            BatchOperation::LabelAdd { file, tag, label } => {
+                write!(f, "add label {label:?} to {tag} of {file}")
+            }
+            BatchOperation::LabelRemove { file, tag, label } => {
+                write!(f, "remove label {label:?} from {tag} of {file}")
+            }
+        }
+    }
+}
+
+/// Outcome of a single operation of a batch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum BatchResult {
+    Applied,
+    Failed {
+        error: String,
+    },
+    /// Not attempted because an earlier operation failed.
+    Skipped,
+}
+
+/// Outcome of a batch, which is only committed if every operation succeeded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BatchResponse {
+    pub committed: bool,
+    /// Result of each operation, in the order they were given.
+    pub results: Vec<BatchResult>,
+    /// Mutations caused by the batch, empty unless it was committed.
+    pub mutations: Vec<Mutation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sequence;
+
+    #[test]
+    fn serialize_operation() {
+        let operation = BatchOperation::TagNameDisplay {
+            name: "person".into(),
+            display: "Person".into(),
+        };
+        assert_eq!(
+            serde_json::to_string(&operation).unwrap(),
+            r#"{"op":"tag_name_display","name":"person","display":"Person"}"#
+        );
+    }
+
+    #[test]
+    fn operation_mutation() {
+        let file: BoxHash = (&[1, 2, 3]).into();
+        let operation = BatchOperation::LabelRemove {
+            file: file.clone(),
+            tag: Tag::new("person".into(), "alice".into()),
+            label: Label::Sequence(Sequence { start: 1, end: 2 }),
+        };
+        assert_eq!(
+            operation.mutation(),
+            Mutation::Labels {
+                file: Some(file),
+                name: Some("person".into()),
+                value: Some("alice".into()),
+            }
+        );
+    }
+}
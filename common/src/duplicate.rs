@@ -0,0 +1,27 @@
+use crate::BoxHash;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// File which was added from more than one path.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duplicate {
+    pub hash: BoxHash,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Paths the file was added from, relative to the project root.
+    pub paths: Vec<PathBuf>,
+    /// Bytes taken up by all but one of the copies.
+    pub reclaimable: u64,
+}
+
+impl Duplicate {
+    pub fn new(hash: BoxHash, size: u64, paths: Vec<PathBuf>) -> Self {
+        let reclaimable = size * paths.len().saturating_sub(1) as u64;
+        Self {
+            hash,
+            size,
+            paths,
+            reclaimable,
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::BatchOperation;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+/// Where a change to tags or labels came from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Origin {
+    /// Command-line interface.
+    Cli,
+    /// API, with authentication disabled.
+    Api,
+    /// API, by a logged-in user.
+    User { name: String },
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Cli => write!(f, "cli"),
+            Origin::Api => write!(f, "api"),
+            Origin::User { name } => write!(f, "user {name}"),
+        }
+    }
+}
+
+/// Single recorded change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Change {
+    pub id: u64,
+    pub operation: BatchOperation,
+    /// Operations which undo this change.
+    pub inverse: Vec<BatchOperation>,
+    pub reverted: bool,
+}
+
+/// Changes which were made together, such as by a single request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChangeBatch {
+    pub id: u64,
+    /// UNIX timestamp of when the changes were made.
+    pub time: i64,
+    pub origin: Origin,
+    pub changes: Vec<Change>,
+}
+
+impl ChangeBatch {
+    /// Determines if all changes of this batch were reverted.
+    pub fn reverted(&self) -> bool {
+        self.changes.iter().all(|change| change.reverted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_origin() {
+        assert_eq!(
+            serde_json::to_string(&Origin::Cli).unwrap(),
+            r#"{"kind":"cli"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Origin::User {
+                name: "alice".into()
+            })
+            .unwrap(),
+            r#"{"kind":"user","name":"alice"}"#
+        );
+    }
+
+    #[test]
+    fn batch_reverted() {
+        let change = |reverted| Change {
+            id: 1,
+            operation: BatchOperation::TagNameDelete {
+                name: "person".into(),
+            },
+            inverse: vec![],
+            reverted,
+        };
+        let mut batch = ChangeBatch {
+            id: 1,
+            time: 0,
+            origin: Origin::Cli,
+            changes: vec![change(true), change(false)],
+        };
+        assert!(!batch.reverted());
+        batch.changes.pop();
+        assert!(batch.reverted());
+    }
+}
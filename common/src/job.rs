@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// State of a background job.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    #[default]
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Determines if the job has stopped running.
+    pub fn finished(&self) -> bool {
+        !matches!(self, JobStatus::Running)
+    }
+}
+
+/// Progress of a background job.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct JobInfo {
+    pub id: u64,
+    /// What the job does, for example `add`.
+    pub name: String,
+    pub status: JobStatus,
+    /// Current phase of the job, for example `hashing`.
+    pub phase: String,
+    /// Items done in the current phase.
+    pub done: u64,
+    /// Items in the current phase, zero if not known yet.
+    pub total: u64,
+    /// Errors that did not stop the job, or the one that did.
+    pub errors: Vec<String>,
+}
+
+/// Job to start through the API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobStart {
+    /// Add new or changed files in the project.
+    Rescan,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_finished() {
+        assert!(!JobStatus::Running.finished());
+        assert!(JobStatus::Done.finished());
+        assert!(JobStatus::Failed.finished());
+        assert!(JobStatus::Cancelled.finished());
+    }
+
+    #[test]
+    fn serialize_start() {
+        assert_eq!(
+            serde_json::to_string(&JobStart::Rescan).unwrap(),
+            r#"{"kind":"rescan"}"#
+        );
+    }
+}
@@ -1,16 +1,30 @@
 pub mod api;
+mod attribute;
+mod batch;
 pub mod cache;
+mod duplicate;
 mod error;
 pub mod hash;
+mod history;
+pub mod job;
 pub mod label;
 mod mutation;
 pub mod tag;
+mod transcode;
+mod user;
 
 pub use crate::{
+    attribute::{Attributes, AttributesEdit},
+    batch::{BatchOperation, BatchResponse, BatchResult},
+    duplicate::Duplicate,
     error::ErrorResponse,
     hash::{ArcHash, BoxHash, Hash},
+    history::{Change, ChangeBatch, Origin},
+    job::{JobInfo, JobStart, JobStatus},
     label::{Label, LabelKind, Point, Rectangle, Sequence},
     mutation::Mutation,
     tag::{Tag, TagFilter, TagPredicate},
+    transcode::TranscodeProfile,
+    user::{Role, RoleParseError, User},
 };
 pub use restless;
@@ -1,9 +1,13 @@
-use crate::BoxHash;
+use crate::{BoxHash, Hash};
+use serde::{Deserialize, Serialize};
 
 /// Enumeration of possible mutations.
 ///
 /// These cases should roughly mirror the database tables that Cindy uses. Every mutation might
-/// modify, insert or delete something in a table.
+/// modify, insert or delete something in a table. Fields that are `None` mean that any value
+/// might have been affected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "table", rename_all = "snake_case")]
 pub enum Mutation {
     /// Add or delete file
     Files,
@@ -26,4 +30,112 @@ pub enum Mutation {
         name: Option<String>,
         value: Option<String>,
     },
+    /// Change file attributes
+    FileAttributes { file: Option<BoxHash> },
+}
+
+/// Two filters overlap unless both are set to different values.
+fn overlaps<T: PartialEq<U> + ?Sized, U: ?Sized>(left: Option<&T>, right: Option<&U>) -> bool {
+    match (left, right) {
+        (Some(left), Some(right)) => left == right,
+        _ => true,
+    }
+}
+
+impl Mutation {
+    /// Determines if this mutation might affect the given tag, `None` matches any.
+    pub fn affects_tag(&self, name: Option<&str>, value: Option<&str>) -> bool {
+        match self {
+            Mutation::Files | Mutation::FileAttributes { .. } => false,
+            Mutation::TagNames { name: changed } => overlaps(changed.as_deref(), name),
+            Mutation::TagValues {
+                name: changed_name,
+                value: changed_value,
+            }
+            | Mutation::FileTags {
+                name: changed_name,
+                value: changed_value,
+                ..
+            }
+            | Mutation::Labels {
+                name: changed_name,
+                value: changed_value,
+                ..
+            } => {
+                overlaps(changed_name.as_deref(), name) && overlaps(changed_value.as_deref(), value)
+            }
+        }
+    }
+
+    /// Determines if this mutation might affect the given file.
+    ///
+    /// Changing tag names or values affects all files that are tagged with them.
+    pub fn affects_file(&self, hash: &Hash) -> bool {
+        match self {
+            Mutation::Files | Mutation::TagNames { .. } | Mutation::TagValues { .. } => true,
+            Mutation::FileTags { file, .. }
+            | Mutation::Labels { file, .. }
+            | Mutation::FileAttributes { file } => {
+                overlaps(file.as_ref().map(AsRef::<Hash>::as_ref), Some(hash))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(data: &[u8]) -> BoxHash {
+        data.to_vec().into_boxed_slice().into()
+    }
+
+    #[test]
+    fn serialize_tagged() {
+        let mutation = Mutation::FileTags {
+            file: Some(hash(&[0xab, 0xcd])),
+            name: Some("name".into()),
+            value: None,
+        };
+        let json = serde_json::to_string(&mutation).unwrap();
+        assert_eq!(
+            json,
+            r#"{"table":"file_tags","file":"abcd","name":"name","value":null}"#
+        );
+        assert_eq!(serde_json::from_str::<Mutation>(&json).unwrap(), mutation);
+        assert_eq!(
+            serde_json::to_string(&Mutation::Files).unwrap(),
+            r#"{"table":"files"}"#
+        );
+    }
+
+    #[test]
+    fn affects_tag() {
+        let mutation = Mutation::TagValues {
+            name: Some("name".into()),
+            value: Some("value".into()),
+        };
+        assert!(mutation.affects_tag(None, None));
+        assert!(mutation.affects_tag(Some("name"), None));
+        assert!(mutation.affects_tag(Some("name"), Some("value")));
+        assert!(!mutation.affects_tag(Some("name"), Some("other")));
+        assert!(!mutation.affects_tag(Some("other"), None));
+        assert!(Mutation::TagNames { name: None }.affects_tag(Some("name"), Some("value")));
+        assert!(!Mutation::Files.affects_tag(None, None));
+    }
+
+    #[test]
+    fn affects_file() {
+        let file = hash(&[1, 2]);
+        let other = hash(&[3, 4]);
+        let mutation = Mutation::Labels {
+            file: Some(file.clone()),
+            name: None,
+            value: None,
+        };
+        assert!(mutation.affects_file(&file));
+        assert!(!mutation.affects_file(&other));
+        assert!(Mutation::FileAttributes { file: None }.affects_file(&other));
+        assert!(Mutation::TagNames { name: None }.affects_file(&other));
+    }
 }
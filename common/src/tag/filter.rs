@@ -22,11 +22,16 @@ impl<'a> TagFilter<'a> {
         self.1.as_ref().map(|v| v.borrow())
     }
 
+    /// Determine if a tag matches this filter, where `*` in the name or value matches any
+    /// sequence of characters.
     pub fn matches(&self, tag: &Tag) -> bool {
-        let name_matches = self.name().map(|name| name == tag.name()).unwrap_or(true);
+        let name_matches = self
+            .name()
+            .map(|name| glob_matches(name, tag.name()))
+            .unwrap_or(true);
         let value_matches = self
             .value()
-            .map(|value| value == tag.value())
+            .map(|value| glob_matches(value, tag.value()))
             .unwrap_or(true);
         name_matches && value_matches
     }
@@ -72,6 +77,26 @@ fn parse_glob(input: &str) -> Option<&str> {
     }
 }
 
+/// Match a value against a pattern, where `*` matches any sequence of characters.
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,4 +163,18 @@ mod tests {
         assert!(!TagFilter::new::<&str>(Some("name"), Some("other")).matches(&tag));
         assert!(!TagFilter::new::<&str>(Some("other"), Some("other")).matches(&tag));
     }
+
+    #[test]
+    fn tag_filter_matches_glob() {
+        let tag = Tag::new("mime".into(), "image/png".into());
+
+        assert!(TagFilter::new::<&str>(Some("mime"), Some("image/*")).matches(&tag));
+        assert!(TagFilter::new::<&str>(Some("mi*"), Some("*/png")).matches(&tag));
+        assert!(TagFilter::new::<&str>(Some("mime"), Some("i*e/*g")).matches(&tag));
+        assert!(TagFilter::new::<&str>(Some("*"), Some("image/png*")).matches(&tag));
+
+        assert!(!TagFilter::new::<&str>(Some("mime"), Some("video/*")).matches(&tag));
+        assert!(!TagFilter::new::<&str>(Some("mime"), Some("*/jpeg")).matches(&tag));
+        assert!(!TagFilter::new::<&str>(Some("mime"), Some("image/png*g")).matches(&tag));
+    }
 }
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Browser-compatible format to transcode videos into.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeProfile {
+    /// WebM container with VP9 video and Opus audio.
+    #[default]
+    Webm,
+    /// MP4 container with H.264 video and AAC audio.
+    Mp4,
+}
+
+impl TranscodeProfile {
+    /// Name of this profile, used in cache file names.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TranscodeProfile::Webm => "webm",
+            TranscodeProfile::Mp4 => "mp4",
+        }
+    }
+
+    /// MIME type of transcoded files.
+    pub const fn mime(&self) -> &'static str {
+        match self {
+            TranscodeProfile::Webm => "video/webm",
+            TranscodeProfile::Mp4 => "video/mp4",
+        }
+    }
+}
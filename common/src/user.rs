@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// What a user is allowed to do.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can query and view files, but not change anything.
+    ReadOnly,
+    /// Can also tag, label and edit files.
+    ReadWrite,
+}
+
+impl Role {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read_only",
+            Role::ReadWrite => "read_write",
+        }
+    }
+
+    /// Whether this role allows making changes.
+    pub const fn can_write(&self) -> bool {
+        matches!(self, Role::ReadWrite)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown role {0:?}, expected read_only or read_write")]
+pub struct RoleParseError(String);
+
+impl FromStr for Role {
+    type Err = RoleParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.replace('-', "_").as_str() {
+            "read_only" => Ok(Role::ReadOnly),
+            "read_write" => Ok(Role::ReadWrite),
+            _ => Err(RoleParseError(input.into())),
+        }
+    }
+}
+
+/// User that is logged in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct User {
+    pub name: String,
+    pub role: Role,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_parse() {
+        assert_eq!("read_only".parse(), Ok(Role::ReadOnly));
+        assert_eq!("read-write".parse(), Ok(Role::ReadWrite));
+        assert!("admin".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn role_display() {
+        for role in [Role::ReadOnly, Role::ReadWrite] {
+            assert_eq!(role.to_string().parse(), Ok(role));
+        }
+    }
+
+    #[test]
+    fn role_can_write() {
+        assert!(!Role::ReadOnly.can_write());
+        assert!(Role::ReadWrite.can_write());
+    }
+}
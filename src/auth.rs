@@ -0,0 +1,89 @@
+//! Passwords and tokens used to authenticate users of the web interface.
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use blake2::{Blake2b512, Digest};
+use std::sync::OnceLock;
+
+/// Length of generated tokens, in bytes.
+const TOKEN_LENGTH: usize = 32;
+
+/// Hash a password for storing it in the database.
+pub fn password_hash(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|error| anyhow!("Hashing password: {error}"))?;
+    Ok(hash.to_string())
+}
+
+/// Check a password against a stored hash.
+pub fn password_verify(password: &str, hash: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+/// Hash of a random password, verified against when a user does not exist so that logging in
+/// takes as long for unknown users as for known ones.
+pub fn password_dummy() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| password_hash(&token_generate()).expect("hashing dummy password"))
+}
+
+/// Generate a random token for a session or for API access.
+pub fn token_generate() -> String {
+    let mut token = [0; TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut token);
+    hex::encode(token)
+}
+
+/// Hash of a token, only these are stored so that a leaked database does not leak tokens.
+pub fn token_hash(token: &str) -> Vec<u8> {
+    Blake2b512::digest(token.as_bytes()).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_roundtrip() {
+        let hash = password_hash("secret").unwrap();
+        assert!(password_verify("secret", &hash));
+        assert!(!password_verify("other", &hash));
+        assert!(!password_verify("secret", "invalid"));
+    }
+
+    #[test]
+    fn password_dummy_valid() {
+        assert!(PasswordHash::new(password_dummy()).is_ok());
+        assert_eq!(password_dummy(), password_dummy());
+        assert!(!password_verify("", password_dummy()));
+    }
+
+    #[test]
+    fn password_salted() {
+        assert_ne!(
+            password_hash("secret").unwrap(),
+            password_hash("secret").unwrap()
+        );
+    }
+
+    #[test]
+    fn token_unique() {
+        let token = token_generate();
+        assert_eq!(token.len(), 2 * TOKEN_LENGTH);
+        assert_ne!(token, token_generate());
+        assert_eq!(token_hash(&token), token_hash(&token));
+        assert_ne!(token_hash(&token), token_hash(&token_generate()));
+    }
+}
@@ -1,22 +1,37 @@
 use crate::{
     cli::{Command, Options},
-    config::Config,
-    database::Database,
-    hash::{Digester, Hash},
+    command::{layout_recover, rehash_recover},
+    config::{Config, DataMode},
+    database::{Database, Pool, PoolGuard},
+    hash::{BoxHash, Digester, Hash},
+    job::{JobHandle, Jobs},
+    similar::SimilarLookup,
 };
 use anyhow::{bail, Result};
+use cindy_common::Mutation;
 use rusqlite::Connection;
 use std::{
+    future::Future,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     sync::Arc,
+    thread::available_parallelism,
 };
 use tokio::{
     fs::{create_dir, create_dir_all, read_to_string, try_exists, write},
-    sync::{Mutex, OwnedMutexGuard},
+    sync::{broadcast, Mutex, OwnedMutexGuard, OwnedSemaphorePermit, Semaphore},
+    task::spawn_blocking,
 };
 
 const CINDY_CONFIG: &str = "config.toml";
 const CINDY_FOLDER: &str = ".cindy";
+const CINDY_UPLOADS: &str = "uploads";
+
+/// How many mutations are buffered for slow subscribers before they miss some.
+const MUTATIONS_CAPACITY: usize = 256;
+
+/// Lookup of similar files, along with the version of the perceptual hashes it was built from.
+type SimilarCache = std::sync::Mutex<Option<(u64, Arc<SimilarLookup<BoxHash>>)>>;
 
 #[derive(Clone, Debug)]
 pub struct Cindy {
@@ -26,20 +41,47 @@ pub struct Cindy {
     config: Arc<Config>,
     /// Hasher.
     hasher: Arc<dyn Digester + Send + Sync>,
-    /// Database handle.
+    /// Writer connection, writes queue up for it in order.
     database: Arc<Mutex<Database>>,
+    /// Read-only connections, which are not blocked by writes.
+    readers: Arc<Pool>,
+    /// Limits how many videos are transcoded at the same time.
+    transcodes: Arc<Semaphore>,
+    /// Limits how many crops and clips are generated at the same time, one per CPU.
+    crops: Arc<Semaphore>,
+    /// Stream infos and cache size remembered between HLS requests.
+    #[cfg(feature = "ffmpeg")]
+    hls: Arc<crate::hls::HlsState>,
+    /// Lookup of similar files, built once and reused until perceptual hashes change.
+    similar: Arc<SimilarCache>,
+    /// Publishes changes made through the API.
+    mutations: broadcast::Sender<Mutation>,
+    /// Jobs that are running or have recently finished.
+    jobs: Arc<Jobs>,
+    /// How many files are hashed and scanned at the same time.
+    threads: usize,
 }
 
 impl Cindy {
     /// Create or open Cindy project, depending on command.
     pub async fn new(options: &Options) -> Result<Self> {
-        match &options.command {
+        let cindy = match &options.command {
             Command::Init(command) => {
                 let config = Config::default();
-                Cindy::initialize(&command.path, &config).await
+                Cindy::initialize(&command.path, &config).await?
             }
-            _ => Cindy::discover(&std::env::current_dir()?).await,
-        }
+            _ => Cindy::discover(&std::env::current_dir()?).await?,
+        };
+        Ok(match options.global.threads {
+            Some(threads) => cindy.with_threads(threads as usize),
+            None => cindy,
+        })
+    }
+
+    /// Use the given amount of threads to hash and scan files.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
     }
 
     /// Root of Cindy project.
@@ -68,6 +110,21 @@ impl Cindy {
         self.cindy_folder().join(&self.config.thumbs.path)
     }
 
+    pub fn hls_path(&self) -> PathBuf {
+        self.cindy_folder().join(&self.config.hls.path)
+    }
+
+    /// What is remembered between HLS requests.
+    #[cfg(feature = "ffmpeg")]
+    pub(crate) fn hls(&self) -> &crate::hls::HlsState {
+        &self.hls
+    }
+
+    /// Folder for uploads that are still being received.
+    pub fn uploads_path(&self) -> PathBuf {
+        self.cindy_folder().join(CINDY_UPLOADS)
+    }
+
     /// Config of Cindy.
     pub fn config(&self) -> &Arc<Config> {
         &self.config
@@ -78,16 +135,120 @@ impl Cindy {
         &self.hasher
     }
 
-    /// Get a handle to the database.
+    /// How many files are hashed and scanned at the same time.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Get a handle to the writer connection, waiting for earlier writes to finish.
     pub async fn database(&self) -> OwnedMutexGuard<Database> {
         self.database.clone().lock_owned().await
     }
 
-    /// Given a hash, determine a path.
+    /// Get a read-only connection, which sees the database as of the last commit.
+    pub async fn database_read(&self) -> Result<PoolGuard, rusqlite::Error> {
+        self.readers.get().await
+    }
+
+    /// Wait for a transcoding slot, the permit must be held while transcoding.
+    pub async fn transcode_permit(&self) -> OwnedSemaphorePermit {
+        self.transcodes
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("transcode semaphore is never closed")
+    }
+
+    /// Wait for a slot to generate a crop or clip, the permit must be held while generating it.
+    pub async fn crop_permit(&self) -> OwnedSemaphorePermit {
+        self.crops
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("crop semaphore is never closed")
+    }
+
+    /// Lookup of similar files, only rebuilt when perceptual hashes or fingerprints changed.
+    pub async fn similar_lookup(&self) -> Result<Arc<SimilarLookup<BoxHash>>> {
+        let database = self.database_read().await?;
+        let similar = self.similar.clone();
+        spawn_blocking(move || {
+            let version = database.similar_version()?;
+            // held while building, so that concurrent lookups wait for it instead of building too
+            let mut cached = similar.lock().unwrap();
+            match &*cached {
+                Some((cached_version, lookup)) if *cached_version == version => Ok(lookup.clone()),
+                _ => {
+                    let lookup = Arc::new(SimilarLookup::new(
+                        database.hash_phashes()?,
+                        database.hash_fingerprints()?,
+                    ));
+                    *cached = Some((version, lookup.clone()));
+                    Ok(lookup)
+                }
+            }
+        })
+        .await?
+    }
+
+    /// Let subscribers know that something has changed.
+    pub fn mutation(&self, mutation: Mutation) {
+        // having no subscribers is not an error
+        let _ = self.mutations.send(mutation);
+    }
+
+    /// Subscribe to changes made through the API.
+    pub fn mutations(&self) -> broadcast::Receiver<Mutation> {
+        self.mutations.subscribe()
+    }
+
+    /// Jobs that are running or have recently finished.
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    /// Run a job in the background, returning a handle to follow or cancel it.
+    pub fn job_spawn<F, R>(&self, name: &str, job: F) -> JobHandle
+    where
+        F: FnOnce(JobHandle) -> R,
+        R: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handle = self.jobs.create(name);
+        let future = job(handle.clone());
+        let finish = handle.clone();
+        tokio::spawn(async move {
+            let result = future.await;
+            finish.finish(&result);
+        });
+        handle
+    }
+
+    /// Given a hash, determine its path in the data store.
     pub fn hash_path(&self, hash: &Hash) -> PathBuf {
         self.cindy_folder().join(self.config.data.data_path(hash))
     }
 
+    /// Given a hash, find a path to read the contents of the file from, if there is any.
+    ///
+    /// Unless files are referenced where they were added from, this is in the data store.
+    pub async fn file_path(&self, hash: &Hash) -> Result<Option<PathBuf>> {
+        if self.config.data.mode != DataMode::Reference {
+            return Ok(Some(self.hash_path(hash)));
+        }
+        let database = self.database_read().await?;
+        let (root, hash) = (self.root.clone(), BoxHash::from(hash));
+        spawn_blocking(move || {
+            // the file might have been moved or deleted since it was added
+            let paths = database.file_stat_paths(&hash)?;
+            let path = paths
+                .into_iter()
+                .map(|path| root.join(path))
+                .find(|path| path.is_file());
+            Ok(path)
+        })
+        .await?
+    }
+
     /// Initialize new Cindy project.
     pub async fn initialize(path: &Path, config: &Config) -> Result<Self> {
         if !try_exists(path).await? {
@@ -104,32 +265,41 @@ impl Cindy {
         create_dir_all(cindy_dir.join(&config.data.path)).await?;
         create_dir_all(cindy_dir.join(&config.thumbs.path)).await?;
 
-        let database: Database = Connection::open(cindy_dir.join(&config.index.path))?.into();
-        database.migrate()?;
-
-        Ok(Self {
-            root: path.into(),
-            config: config.clone().into(),
-            hasher: Arc::new(config.data.hash.clone()),
-            database: Arc::new(Mutex::new(database)),
-        })
+        Self::open(path, config).await
     }
 
     /// Load Cindy project, will parse Config file.
     pub async fn load(path: &Path) -> Result<Self> {
-        let config_string = read_to_string(&path.join(CINDY_FOLDER).join(CINDY_CONFIG)).await?;
+        let folder = path.join(CINDY_FOLDER);
+        let config_path = folder.join(CINDY_CONFIG);
+        rehash_recover(&folder, &config_path).await?;
+        layout_recover(&folder, &config_path).await?;
+        let config_string = read_to_string(&config_path).await?;
         let config: Config = toml::from_str(&config_string)?;
         Self::open(path, &config).await
     }
 
     /// Open Cindy project with supplied configuration.
     pub async fn open(path: &Path, config: &Config) -> Result<Self> {
-        let database = Connection::open(path.join(CINDY_FOLDER).join(&config.index.path))?;
+        let database_path = path.join(CINDY_FOLDER).join(&config.index.path);
+        let database: Database = Connection::open(&database_path)?.into();
+        database.wal()?;
+        database.migrate()?;
+        let threads = available_parallelism().map(NonZeroUsize::get).unwrap_or(1);
         Ok(Self {
             root: path.into(),
             config: config.clone().into(),
             hasher: Arc::new(config.data.hash.clone()),
-            database: Arc::new(Mutex::new(database.into())),
+            database: Arc::new(Mutex::new(database)),
+            readers: Arc::new(Pool::new(&database_path, config.index.readers)),
+            transcodes: Arc::new(Semaphore::new(config.transcode.concurrency.max(1))),
+            crops: Arc::new(Semaphore::new(threads)),
+            #[cfg(feature = "ffmpeg")]
+            hls: Default::default(),
+            similar: Default::default(),
+            mutations: broadcast::channel(MUTATIONS_CAPACITY).0,
+            jobs: Default::default(),
+            threads,
         })
     }
 
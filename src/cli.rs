@@ -1,6 +1,12 @@
-use crate::tag::{Tag, TagFilter, TagPredicate};
+use crate::{
+    common::Role,
+    config::HashAlgorithm,
+    similar::DEFAULT_DISTANCE,
+    tag::{Tag, TagFilter, TagPredicate},
+};
 use clap::Parser;
-use std::{net::SocketAddr, path::PathBuf};
+use std::{net::SocketAddr, path::PathBuf, str::FromStr};
+use thiserror::Error;
 
 /// Global options.
 #[derive(Parser, Clone, Debug, Default)]
@@ -16,7 +22,7 @@ pub struct GlobalOptions {
     #[clap(long, short, global = true)]
     pub verbose: bool,
 
-    /// Turn on verbose output.
+    /// Number of threads used to hash and scan files, defaults to the number of CPUs.
     #[clap(long, short = 'j', global = true)]
     pub threads: Option<u64>,
 }
@@ -53,6 +59,10 @@ pub struct AddCommand {
     #[clap(long, short)]
     pub recursive: bool,
 
+    /// Hash every file in full, even ones that look unchanged since they were last hashed.
+    #[clap(long)]
+    pub verify: bool,
+
     #[clap(default_value = ".")]
     pub paths: Vec<PathBuf>,
 }
@@ -143,6 +153,188 @@ pub enum TagsCommand {
     List(TagsListCommand),
 }
 
+#[derive(Parser, Clone, Debug)]
+pub struct HistoryCommand {
+    /// Amount of batches of changes to show, newest first.
+    #[clap(long, short, default_value = "20")]
+    pub limit: u64,
+
+    /// Also show batches which were reverted.
+    #[clap(long, short)]
+    pub all: bool,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct UndoCommand {
+    /// Amount of batches of changes to revert.
+    #[clap(default_value = "1")]
+    pub count: u64,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct RehashCommand {
+    /// Hash algorithm to switch to: blake2b512, blake2s256, blake3 or sha256.
+    pub algorithm: HashAlgorithm,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct FsckCommand {
+    /// Repair problems where possible.
+    #[clap(long)]
+    pub repair: bool,
+
+    /// Only hash as many files as needed to verify all of them when running once a day for this
+    /// many days, starting with the ones verified longest ago.
+    #[clap(long, value_name = "DAYS")]
+    pub scrub: Option<u64>,
+
+    /// Ignore data files changed within this many seconds, they might belong to files being
+    /// added.
+    #[clap(long, default_value_t = 3600, value_name = "SECONDS")]
+    pub grace: u64,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct GcCommand {
+    /// Only report what would be removed.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Keep entries changed within this many seconds, they might belong to files being added.
+    #[clap(long, default_value_t = 3600, value_name = "SECONDS")]
+    pub grace: u64,
+}
+
+/// Which copy of a file added from several paths to keep when removing the others.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// The copy which was modified longest ago.
+    Oldest,
+    /// The copy with the shortest path.
+    Shortest,
+    /// A copy inside this directory, given as `dir:<path>`.
+    Directory(PathBuf),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown keep policy {0:?}, expected oldest, shortest or dir:<path>")]
+pub struct KeepPolicyParseError(String);
+
+impl FromStr for KeepPolicy {
+    type Err = KeepPolicyParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "oldest" => Ok(KeepPolicy::Oldest),
+            "shortest" => Ok(KeepPolicy::Shortest),
+            _ => match input.strip_prefix("dir:") {
+                Some(directory) if !directory.is_empty() => {
+                    Ok(KeepPolicy::Directory(directory.into()))
+                }
+                _ => Err(KeepPolicyParseError(input.into())),
+            },
+        }
+    }
+}
+
+/// List files which were added from several paths with their sizes, or with `--similar` images
+/// and videos which look alike.
+#[derive(Parser, Clone, Debug)]
+pub struct DuplicatesCommand {
+    /// Group images and videos by perceptual hashes, finding resized, re-encoded and trimmed
+    /// copies.
+    #[clap(long)]
+    pub similar: bool,
+
+    /// Most bits in which the perceptual hashes of similar images or video frames may differ, out
+    /// of 64.
+    #[clap(long, default_value_t = DEFAULT_DISTANCE, requires = "similar")]
+    pub distance: u32,
+
+    /// Remove all but one copy of each file from the working tree, keeping the `oldest`, the
+    /// `shortest` path or the one inside a directory given as `dir:<path>`.
+    ///
+    /// Copies which changed since they were added are left alone, the data store keeps the file.
+    #[clap(long, conflicts_with = "similar")]
+    pub keep: Option<KeepPolicy>,
+
+    /// Only report which copies would be removed.
+    #[clap(long, requires = "keep")]
+    pub dry_run: bool,
+}
+
+/// Move the data store and thumbnails to a different layout, keeping what is not given.
+#[derive(Parser, Clone, Debug)]
+pub struct MigrateLayoutCommand {
+    /// Path of the data store, relative to the .cindy folder.
+    #[clap(long)]
+    pub path: Option<PathBuf>,
+
+    /// Lengths of the hash prefixes used as folders in the data store, for example 2,2.
+    #[clap(long, value_delimiter = ',')]
+    pub prefix: Option<Vec<u8>>,
+
+    /// Path of the thumbnails, relative to the .cindy folder.
+    #[clap(long)]
+    pub thumbs: Option<PathBuf>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct UsersAddCommand {
+    pub name: String,
+
+    /// Role of the user, either read_only or read_write.
+    #[clap(long, short, default_value = "read_only")]
+    pub role: Role,
+
+    /// Password of the user.
+    ///
+    /// If not given, it is read from standard input. Users without password can only use tokens.
+    #[clap(long, env = "CINDY_PASSWORD")]
+    pub password: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct UsersPasswordCommand {
+    pub name: String,
+
+    /// New password of the user, read from standard input if not given.
+    #[clap(long, env = "CINDY_PASSWORD")]
+    pub password: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct UsersRemoveCommand {
+    pub name: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct UsersListCommand {}
+
+#[derive(Parser, Clone, Debug)]
+pub struct UsersTokenCommand {
+    pub name: String,
+
+    /// What the token is used for.
+    #[clap(long, default_value = "api")]
+    pub label: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub enum UsersCommand {
+    /// Create a new user.
+    Add(UsersAddCommand),
+    /// Change the password of a user.
+    Password(UsersPasswordCommand),
+    /// Delete a user.
+    #[clap(alias = "rm")]
+    Remove(UsersRemoveCommand),
+    /// List users.
+    List(UsersListCommand),
+    /// Create an API token for a user.
+    Token(UsersTokenCommand),
+}
+
 #[derive(Parser, Clone, Debug)]
 pub enum Command {
     /// Initialize new Cindy project.
@@ -162,6 +354,23 @@ pub enum Command {
     /// Manage tags
     #[clap(subcommand)]
     Tags(TagsCommand),
+    /// Show recent changes to tags and labels.
+    History(HistoryCommand),
+    /// Revert the most recent changes to tags and labels.
+    Undo(UndoCommand),
+    /// Manage users of the web interface.
+    #[clap(subcommand)]
+    Users(UsersCommand),
+    /// Recompute the hashes of all files using a different hash algorithm.
+    Rehash(RehashCommand),
+    /// Check that the data store matches the index.
+    Fsck(FsckCommand),
+    /// Remove data and thumbnails of files which are no longer in the index.
+    Gc(GcCommand),
+    /// Move the data store and thumbnails to a different layout.
+    MigrateLayout(MigrateLayoutCommand),
+    /// Find duplicate files and similar images and videos.
+    Duplicates(DuplicatesCommand),
     /// Serve Cindy UI.
     #[cfg(feature = "server")]
     #[clap(alias = "server")]
@@ -263,9 +472,10 @@ mod tests {
     }
 
     prop_compose! {
-        fn arb_add_command()(recursive in prop::bool::ANY, paths in arb_path_buf_list_or_pwd()) -> AddCommand {
+        fn arb_add_command()(recursive in prop::bool::ANY, verify in prop::bool::ANY, paths in arb_path_buf_list_or_pwd()) -> AddCommand {
             AddCommand {
                 recursive,
+                verify,
                 paths,
             }
         }
@@ -322,6 +532,7 @@ mod tests {
         // add files (recursively)
         Options::try_parse_from(&["cindy", "add", "file1", "file2"]).unwrap();
         Options::try_parse_from(&["cindy", "add", "-r", "folder"]).unwrap();
+        Options::try_parse_from(["cindy", "add", "-r", "--verify", "folder"]).unwrap();
 
         // remove files (recursively)
         Options::try_parse_from(&["cindy", "remove", "file1", "file2"]).unwrap();
@@ -352,5 +563,50 @@ mod tests {
         Options::try_parse_from(&["cindy", "tags", "list"]).unwrap();
         Options::try_parse_from(&["cindy", "tags", "list", "name:*"]).unwrap();
         Options::try_parse_from(&["cindy", "tags", "rename", "name:value", "name:other"]).unwrap();
+
+        Options::try_parse_from(["cindy", "users", "add", "name"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "add", "name", "--role", "read_write"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "add", "name", "--password", "secret"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "password", "name"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "remove", "name"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "list"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "token", "name"]).unwrap();
+        Options::try_parse_from(["cindy", "users", "token", "name", "--label", "script"]).unwrap();
+        assert!(Options::try_parse_from(["cindy", "users", "add", "name", "-r", "admin"]).is_err());
+
+        Options::try_parse_from(["cindy", "fsck"]).unwrap();
+        Options::try_parse_from(["cindy", "fsck", "--repair", "--scrub", "7"]).unwrap();
+        Options::try_parse_from(["cindy", "fsck", "--repair", "--grace", "0"]).unwrap();
+        Options::try_parse_from(["cindy", "gc", "--dry-run", "--grace", "0"]).unwrap();
+        Options::try_parse_from([
+            "cindy",
+            "migrate-layout",
+            "--prefix",
+            "1,1",
+            "--path",
+            "store",
+        ])
+        .unwrap();
+        Options::try_parse_from(["cindy", "duplicates"]).unwrap();
+        Options::try_parse_from(["cindy", "duplicates", "--similar", "--distance", "4"]).unwrap();
+        assert!(Options::try_parse_from(["cindy", "duplicates", "--distance", "4"]).is_err());
+        Options::try_parse_from(["cindy", "duplicates", "--keep", "oldest", "--dry-run"]).unwrap();
+        assert!(Options::try_parse_from(["cindy", "duplicates", "--dry-run"]).is_err());
+        assert!(
+            Options::try_parse_from(["cindy", "duplicates", "--similar", "--keep", "oldest"])
+                .is_err()
+        );
+        assert_eq!("shortest".parse(), Ok(KeepPolicy::Shortest));
+        assert_eq!(
+            "dir:photos/keep".parse(),
+            Ok(KeepPolicy::Directory("photos/keep".into()))
+        );
+        for policy in ["olderst", "photos/keep", "dir:"] {
+            assert_eq!(
+                policy.parse::<KeepPolicy>(),
+                Err(KeepPolicyParseError(policy.into()))
+            );
+        }
+        assert!(Options::try_parse_from(["cindy", "duplicates", "--keep", "newest"]).is_err());
     }
 }
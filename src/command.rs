@@ -1,13 +1,29 @@
-use crate::{cli::Command, Cindy};
+use crate::{cli::Command, job::JobHandle, Cindy};
 use anyhow::Result;
-use std::time::Duration;
+use cindy_common::{JobInfo, JobStatus};
+use std::{
+    future::Future,
+    io::{stdout, Write},
+    time::Duration,
+};
+use tokio::{sync::watch, time::sleep};
 pub const UPDATE_INTERVAL: Duration = Duration::from_millis(30);
 
 mod add;
+mod duplicates;
+pub mod fsck;
+pub mod gc;
+mod history;
+mod layout;
 mod query;
+mod rehash;
 #[cfg(feature = "server")]
 mod serve;
 mod tags;
+mod users;
+
+pub use layout::layout_recover;
+pub use rehash::rehash_recover;
 
 impl Cindy {
     // TODO: use global options (for thread count)
@@ -17,9 +33,66 @@ impl Cindy {
             Command::Add(command) => self.command_add(command).await,
             Command::Query(command) => self.command_query(command).await,
             Command::Tags(command) => self.command_tags(command).await,
+            Command::History(command) => self.command_history(command).await,
+            Command::Undo(command) => self.command_undo(command).await,
+            Command::Users(command) => self.command_users(command).await,
+            Command::Rehash(command) => self.command_rehash(command).await,
+            Command::Fsck(command) => self.command_fsck(command).await,
+            Command::Gc(command) => self.command_gc(command).await,
+            Command::MigrateLayout(command) => self.command_migrate_layout(command).await,
+            Command::Duplicates(command) => self.command_duplicates(command).await,
             #[cfg(feature = "server")]
             Command::Serve(command) => self.command_serve(command).await,
             _ => Ok(()),
         }
     }
 }
+
+fn progress_line(info: &JobInfo) -> String {
+    match info.total {
+        0 => format!("{} {}", info.phase, info.done),
+        total => format!("{} {}/{total}", info.phase, info.done),
+    }
+}
+
+/// Render progress of a job on the terminal, one line per phase, until it has finished.
+async fn render_progress(mut progress: watch::Receiver<JobInfo>) {
+    let mut phase = String::new();
+    loop {
+        let closed = progress.changed().await.is_err();
+        let info = progress.borrow_and_update().clone();
+        if info.phase != phase {
+            if !phase.is_empty() {
+                println!();
+            }
+            phase = info.phase.clone();
+        }
+        if !phase.is_empty() {
+            print!("\r\x1B[2K{}", progress_line(&info));
+            stdout().flush().unwrap();
+        }
+
+        if closed || info.status.finished() {
+            if !phase.is_empty() {
+                println!();
+            }
+            // the error that stopped the job is returned instead
+            if info.status == JobStatus::Done {
+                for error in &info.errors {
+                    eprintln!("{error}");
+                }
+            }
+            break;
+        }
+        sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+/// Run a job in the foreground, rendering its progress on the terminal.
+pub async fn job_render<T>(job: &JobHandle, future: impl Future<Output = Result<T>>) -> Result<T> {
+    let render = tokio::spawn(render_progress(job.subscribe()));
+    let result = future.await;
+    job.finish(&result);
+    render.await?;
+    result
+}
@@ -1,26 +1,24 @@
-use super::UPDATE_INTERVAL;
+use super::job_render;
 use crate::{
     cli::AddCommand,
+    config::DataMode,
     database::{Database, Handle},
     hash::{BoxHash, Hash, ReadDigester},
+    job::JobHandle,
     Cindy, Tag,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use flume::{Receiver, Sender};
 use futures::StreamExt;
 use std::{
-    collections::{BTreeMap, BTreeSet},
     fs::{create_dir_all, hard_link, File, Metadata},
-    io::{stdout, ErrorKind, Write},
+    io::{self, ErrorKind, Seek},
     path::{Path, PathBuf},
-    time::Instant,
-};
-use tokio::{
-    sync::mpsc::channel,
-    task::{spawn_blocking, JoinHandle},
 };
+use tempfile::NamedTempFile;
+use tokio::task::{spawn_blocking, JoinHandle};
 
-fn path_tags(path: &Path) -> impl Iterator<Item = Tag> + '_ {
+pub(super) fn path_tags(path: &Path) -> impl Iterator<Item = Tag> + '_ {
     let path_tag = Tag::new("path".into(), format!("/{}", path.display()));
     let filename_tag = Tag::new(
         "filename".into(),
@@ -39,75 +37,284 @@ fn path_tags(path: &Path) -> impl Iterator<Item = Tag> + '_ {
         .chain(pathprefix_tags)
 }
 
+#[cfg(feature = "reflink")]
+fn reflink_file(from: &Path, to: &Path) -> io::Result<()> {
+    reflink::reflink(from, to)
+}
+
+#[cfg(not(feature = "reflink"))]
+fn reflink_file(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "built without reflink support",
+    ))
+}
+
+/// Detect the MIME type of a file from its magic bytes.
+fn mime_tag(path: &Path) -> Result<Option<Tag>> {
+    let kind = infer::get_from_path(path)?;
+    Ok(kind.map(|kind| Tag::new("mime".into(), kind.mime_type().into())))
+}
+
+/// Add the media info tags of a file, returning the perceptual hash of images and the
+/// fingerprint of videos.
+#[cfg(feature = "ffmpeg")]
+fn scan_media(data: &Path, tags: &mut Vec<Tag>) -> Result<(Option<u64>, Option<Vec<u64>>)> {
+    use crate::{media, MediaInfo};
+    let info = media::media_info(data)?;
+    tags.extend(info.tags());
+    Ok(match info {
+        MediaInfo::Image(_) => (Some(media::perceptual_hash(data)?), None),
+        MediaInfo::Video(_) => (None, Some(media::video_fingerprint(data)?)),
+        MediaInfo::Audio(_) => (None, None),
+    })
+}
+
 fn add_file<H: Handle>(
     database: &Database<H>,
     hash: &Hash,
-    tags: &[Tag],
-    paths: &mut dyn Iterator<Item = &Path>,
+    scan: &Scan,
+    path: &Path,
 ) -> Result<()> {
     database.hash_add(hash)?;
 
-    for tag in tags {
+    for tag in &scan.tags {
         database.tag_value_create(tag.name(), tag.value())?;
         database.hash_tag_add(hash, tag.name(), tag.value())?;
     }
 
-    add_path_tags(database, hash, paths)?;
+    if let Some(phash) = scan.phash {
+        database.hash_phash_set(hash, phash)?;
+    }
+
+    if let Some(fingerprint) = &scan.fingerprint {
+        database.hash_fingerprint_set(hash, fingerprint)?;
+    }
+
+    add_path_tags(database, hash, path)?;
 
     Ok(())
 }
 
-fn add_path_tags<H: Handle>(
-    database: &Database<H>,
-    hash: &Hash,
-    paths: &mut dyn Iterator<Item = &Path>,
-) -> Result<()> {
-    for path in paths {
-        for tag in path_tags(path) {
-            database.tag_value_create(tag.name(), tag.value())?;
-            database.hash_tag_add(hash, tag.name(), tag.value())?;
-        }
+fn add_path_tags<H: Handle>(database: &Database<H>, hash: &Hash, path: &Path) -> Result<()> {
+    for tag in path_tags(path) {
+        database.tag_value_create(tag.name(), tag.value())?;
+        database.hash_tag_add(hash, tag.name(), tag.value())?;
     }
     Ok(())
 }
 
+/// How many files can be queued between two stages of adding files.
+const QUEUE_SIZE: usize = 1024;
+
+/// Most files looked up or committed at once, batches are smaller while the stage keeps up.
+const BATCH_SIZE: usize = 256;
+
+/// File that has been hashed and placed into the data index.
+type Hashed = (PathBuf, Metadata, BoxHash);
+
+/// What scanning the contents of a file found.
+#[derive(Clone, Debug)]
+struct Scan {
+    tags: Vec<Tag>,
+    /// Perceptual hash, only computed for images.
+    phash: Option<u64>,
+    /// Perceptual hashes of frames, only computed for videos.
+    fingerprint: Option<Vec<u64>>,
+}
+
+/// Hashed file with what scanning it found, or none if its hash is already known.
+type Scanned = (PathBuf, Metadata, BoxHash, Option<Scan>);
+
 impl Cindy {
     pub async fn command_add(&self, command: &AddCommand) -> Result<()> {
-        self.add_files(&command.paths, command.recursive).await
+        let job = self.jobs().create("add");
+        job_render(
+            &job,
+            self.add_files(&command.paths, command.recursive, command.verify, &job),
+        )
+        .await
     }
 
-    pub async fn add_files(&self, files: &[PathBuf], recursive: bool) -> Result<()> {
-        let files = self
-            .list_files(files, recursive)
-            .await
-            .context("Listing files")?;
-        let hashes = self.hash_files(files).await.context("Hashing files")?;
-        let hashes = self
-            .skip_known(hashes)
-            .await
-            .context("Skipping known files")?;
-        let hashes = self
-            .scan_metadata(hashes)
+    /// Add files, unless `verify` is set the recorded hash of unchanged files is trusted.
+    ///
+    /// Files are committed in batches as they make it through, so an interrupted run keeps what
+    /// it has added and running it again skips those files unless they were changed.
+    pub async fn add_files(
+        &self,
+        files: &[PathBuf],
+        recursive: bool,
+        verify: bool,
+        job: &JobHandle,
+    ) -> Result<()> {
+        let (listed_sender, listed) = flume::bounded(QUEUE_SIZE);
+        let (unhashed_sender, unhashed) = flume::bounded(QUEUE_SIZE);
+        let (hashed_sender, hashed) = flume::bounded(QUEUE_SIZE);
+
+        let lister = {
+            let cindy = self.clone();
+            let (files, job) = (files.to_vec(), job.clone());
+            tokio::spawn(async move {
+                cindy
+                    .list_files(&files, recursive, listed_sender, &job)
+                    .await
+            })
+        };
+        let lookup = {
+            let cindy = self.clone();
+            tokio::spawn(async move {
+                cindy
+                    .stat_cache_lookup(listed, unhashed_sender, verify)
+                    .await
+            })
+        };
+        let hashers = self.launch_hasher_tasks(unhashed, hashed_sender, self.threads(), job);
+        let added = self.add_hashed(hashed, job).await;
+
+        // a failing stage makes the ones before it fail to send, so the last one has the cause
+        added?;
+        for task in hashers.into_iter() {
+            task.await?.context("Hashing files")?;
+        }
+        lookup.await?.context("Looking up unchanged files")?;
+        lister.await?.context("Listing files")?;
+        self.mime_missing(job)
             .await
-            .context("Scanning metadata")?;
+            .context("Detecting missing MIME types")
+    }
+
+    /// Detect the MIME type of files indexed before it was detected when adding them.
+    ///
+    /// Files whose data cannot be read right now are checked again the next time.
+    async fn mime_missing(&self, job: &JobHandle) -> Result<()> {
+        let database = self.database_read().await?;
+        let hashes = spawn_blocking(move || database.hashes_mime_pending()).await??;
+        if hashes.is_empty() {
+            return Ok(());
+        }
+
+        job.phase("detecting types", hashes.len() as u64);
+        let mut results = futures::stream::iter(hashes)
+            .map(|hash| async move {
+                let detected = match self.file_path(&hash).await? {
+                    Some(path) => spawn_blocking(move || mime_tag(&path)).await?,
+                    None => Err(anyhow!("Data is missing")),
+                };
+                Ok((hash, detected)) as Result<(BoxHash, Result<Option<Tag>>)>
+            })
+            .buffer_unordered(self.threads());
+        let mut detected = vec![];
+        let mut done = 0;
+        while let Some(result) = results.next().await {
+            job.check()?;
+            match result? {
+                (hash, Ok(tag)) => detected.push((hash, tag)),
+                (hash, Err(error)) => job.error(format!("{hash}: {error:#}")),
+            }
+            done += 1;
+            job.progress(done);
+        }
+
         let mut database = self.database().await;
         spawn_blocking(move || {
             let transaction = database.transaction()?;
-            for (hash, (metadata, paths)) in hashes.iter() {
-                add_file(
-                    &transaction,
-                    Hash::new(&hash[..]),
-                    metadata,
-                    &mut paths.iter().map(|p| p.as_path()),
-                )?;
+            for (hash, tag) in &detected {
+                if let Some(tag) = tag {
+                    transaction.tag_value_create(tag.name(), tag.value())?;
+                    transaction.hash_tag_add(hash, tag.name(), tag.value())?;
+                }
+                transaction.hash_mime_checked(hash)?;
             }
             transaction.commit()?;
             Ok(()) as Result<()>
         })
-        .await??;
+        .await?
+    }
+
+    /// Pass on listed files along with the recorded hash of unchanged ones.
+    async fn stat_cache_lookup(
+        &self,
+        listed: Receiver<(PathBuf, Metadata)>,
+        unhashed: Sender<(PathBuf, Metadata, Option<BoxHash>)>,
+        verify: bool,
+    ) -> Result<()> {
+        let mut batches = listed.into_stream().ready_chunks(BATCH_SIZE);
+        while let Some(batch) = batches.next().await {
+            let batch = match verify {
+                true => batch
+                    .into_iter()
+                    .map(|(path, metadata)| (path, metadata, None))
+                    .collect(),
+                false => {
+                    let database = self.database_read().await?;
+                    spawn_blocking(move || {
+                        let mut files = Vec::with_capacity(batch.len());
+                        for (path, metadata) in batch {
+                            let cached = match database.file_stat(&path)? {
+                                Some((recorded, hash)) if recorded == (&metadata).into() => {
+                                    Some(hash)
+                                }
+                                _ => None,
+                            };
+                            files.push((path, metadata, cached));
+                        }
+                        Ok(files) as Result<Vec<_>>
+                    })
+                    .await??
+                }
+            };
+            for file in batch {
+                unhashed.send_async(file).await?;
+            }
+        }
         Ok(())
     }
 
+    /// Add files which have already been hashed and placed into the data index.
+    pub async fn add_hashed(&self, hashed: Receiver<Hashed>, job: &JobHandle) -> Result<()> {
+        job.phase("adding", 0);
+        let (unscanned_sender, unscanned) = flume::bounded(QUEUE_SIZE);
+        let (scanned_sender, scanned) = flume::bounded(QUEUE_SIZE);
+
+        let known = {
+            let cindy = self.clone();
+            let scanned_sender = scanned_sender.clone();
+            tokio::spawn(async move {
+                cindy
+                    .skip_known(hashed, unscanned_sender, scanned_sender)
+                    .await
+            })
+        };
+        let scanners = self.launch_scanner_tasks(unscanned, scanned_sender, self.threads(), job);
+        self.commit_files(scanned, job)
+            .await
+            .context("Committing files")?;
+
+        for task in scanners.into_iter() {
+            task.await?.context("Scanning metadata")?;
+        }
+        known.await?.context("Skipping known files")?;
+        Ok(())
+    }
+
+    /// Add a single file inside the project that has already been hashed.
+    pub async fn add_hashed_file(&self, path: &Path, hash: &Hash, job: &JobHandle) -> Result<()> {
+        let hash = BoxHash::from(hash);
+        let metadata = {
+            let cindy = self.clone();
+            let (path, hash) = (path.to_path_buf(), hash.clone());
+            spawn_blocking(move || {
+                cindy.data_add(&path, &hash)?;
+                Ok(std::fs::metadata(cindy.root().join(&path))?) as Result<Metadata>
+            })
+            .await??
+        };
+        let (sender, receiver) = flume::bounded(1);
+        sender.send((path.to_path_buf(), metadata, hash.clone()))?;
+        drop(sender);
+        self.add_hashed(receiver, job).await
+    }
+
     /// Given a file, compute it's hash.
     pub fn hash_file(&self, path: &Path) -> Result<BoxHash> {
         let mut file = File::open(self.root().join(path))?;
@@ -115,43 +322,67 @@ impl Cindy {
         Ok(hash)
     }
 
-    /// Given a path and a hash, add it to the data index.
+    /// Given a path and a hash, add it to the data store as configured.
     pub fn data_add(&self, file: &Path, hash: &Hash) -> Result<()> {
+        let mode = self.config().data.mode;
         let path = self.hash_path(hash);
-        if !path.exists() {
-            let file = self.root().join(file);
-            create_dir_all(path.parent().unwrap())?;
-
-            // try to reflink first, if enabled
-            #[cfg(feature = "reflink")]
-            match reflink::reflink(&file, &path) {
-                Ok(()) => return Ok(()),
-                Err(err) => println!("Error reflinking: {err}"),
-            }
+        if mode == DataMode::Reference || path.exists() {
+            return Ok(());
+        }
+        let file = self.root().join(file);
+        create_dir_all(path.parent().unwrap())?;
+        let result = match mode {
+            DataMode::Auto => reflink_file(&file, &path).or_else(|_| hard_link(&file, &path)),
+            DataMode::Reflink => reflink_file(&file, &path),
+            DataMode::Hardlink => hard_link(&file, &path),
+            DataMode::Copy => return self.data_copy(&file, &path, hash),
+            DataMode::Reference => unreachable!(),
+        };
+
+        // another task might have added the same contents in the meantime
+        match result {
+            Err(error) if error.kind() != ErrorKind::AlreadyExists => Err(error)
+                .with_context(|| format!("Adding {} to data store using {mode}", file.display())),
+            _ => Ok(()),
+        }
+    }
 
-            // fall back to a hard link
-            hard_link(file, path).or_else(|error| match error {
-                error if error.kind() == ErrorKind::AlreadyExists => Ok(()),
-                error => Err(error),
-            })?
+    /// Copy a file into the data store, only keeping the copy if it has the expected hash.
+    fn data_copy(&self, file: &Path, path: &Path, hash: &Hash) -> Result<()> {
+        let mut copy = NamedTempFile::new_in(path.parent().unwrap())?;
+        io::copy(&mut File::open(file)?, copy.as_file_mut())?;
+        copy.rewind()?;
+        let copied = self.hasher().hash_read(copy.as_file_mut())?;
+        if copied.as_slice() != hash.as_slice() {
+            bail!(
+                "Copy of {} has hash {copied}, expected {hash}",
+                file.display()
+            );
         }
+        copy.persist(path)?;
         Ok(())
     }
 
     fn launch_hasher_tasks(
         &self,
-        files: Receiver<(PathBuf, Metadata)>,
-        hashes: Sender<(PathBuf, Metadata, BoxHash)>,
+        files: Receiver<(PathBuf, Metadata, Option<BoxHash>)>,
+        hashes: Sender<Hashed>,
         tasks: usize,
+        job: &JobHandle,
     ) -> Vec<JoinHandle<Result<()>>> {
         (0..tasks)
             .map(|_| {
                 let files = files.clone();
                 let hashes = hashes.clone();
                 let cindy = self.clone();
+                let job = job.clone();
                 spawn_blocking(move || {
-                    for (path, metadata) in files.iter() {
-                        let hash = cindy.hash_file(&path)?;
+                    for (path, metadata, cached) in files.iter() {
+                        job.check()?;
+                        let hash = match cached {
+                            Some(hash) => hash,
+                            None => cindy.hash_file(&path)?,
+                        };
                         cindy.data_add(&path, &hash)?;
                         hashes.send((path, metadata, hash))?;
                     }
@@ -163,32 +394,45 @@ impl Cindy {
 
     fn launch_scanner_tasks(
         &self,
-        files: Receiver<(BoxHash, Metadata, BTreeSet<PathBuf>)>,
-        hashes: Sender<(BoxHash, Vec<Tag>, BTreeSet<PathBuf>)>,
+        files: Receiver<Hashed>,
+        scanned: Sender<Scanned>,
         tasks: usize,
+        job: &JobHandle,
     ) -> Vec<JoinHandle<Result<()>>> {
         (0..tasks)
             .map(|_| {
                 let files = files.clone();
-                let hashes = hashes.clone();
+                let scanned = scanned.clone();
                 let cindy = self.clone();
+                let job = job.clone();
                 spawn_blocking(move || {
-                    for (hash, metadata, paths) in files.iter() {
+                    for (path, metadata, hash) in files.iter() {
+                        job.check()?;
                         let filesize = Tag::new("filesize".into(), metadata.len().to_string());
                         let mut tags = vec![filesize];
-                        let path = cindy.hash_path(&hash);
+                        let data = match cindy.config().data.mode {
+                            DataMode::Reference => cindy.root().join(&path),
+                            _ => cindy.hash_path(&hash),
+                        };
+                        if let Some(mime) = mime_tag(&data)? {
+                            tags.push(mime);
+                        }
                         #[cfg(feature = "ffmpeg")]
-                        match crate::media::media_info(&path) {
-                            Ok(info) => {
-                                for tag in info.tags() {
-                                    tags.push(tag);
-                                }
-                            }
+                        let (phash, fingerprint) = match scan_media(&data, &mut tags) {
+                            Ok(found) => found,
                             Err(error) => {
-                                println!("{paths:?}: {error:#}");
+                                job.error(format!("{path:?}: {error:#}"));
+                                (None, None)
                             }
-                        }
-                        hashes.send((hash, tags, paths))?;
+                        };
+                        #[cfg(not(feature = "ffmpeg"))]
+                        let (phash, fingerprint) = (None, None);
+                        let scan = Scan {
+                            tags,
+                            phash,
+                            fingerprint,
+                        };
+                        scanned.send((path, metadata, hash, Some(scan)))?;
                     }
                     Ok(()) as Result<()>
                 })
@@ -196,192 +440,77 @@ impl Cindy {
             .collect()
     }
 
-    /// Scan file metadata.
-    pub async fn scan_metadata(
+    /// Pass files whose hash is already known straight on to be committed, and the others on to
+    /// be scanned.
+    async fn skip_known(
         &self,
-        files: BTreeMap<BoxHash, (Metadata, BTreeSet<PathBuf>)>,
-    ) -> Result<BTreeMap<BoxHash, (Vec<Tag>, BTreeSet<PathBuf>)>> {
-        let total_files = files.len();
-
-        // task submitting files to queue
-        let (file_sender, file_receiver) =
-            flume::bounded::<(BoxHash, Metadata, BTreeSet<PathBuf>)>(1024);
-        let sender = tokio::spawn(async move {
-            for (hash, (metadata, paths)) in files.into_iter() {
-                file_sender.send_async((hash, metadata, paths)).await?;
-            }
-            Ok(()) as Result<()>
-        });
-
-        // tasks to pop messages off the queue and generate hashes
-        let (hash_sender, hash_receiver) =
-            flume::bounded::<(BoxHash, Vec<Tag>, BTreeSet<PathBuf>)>(1024);
-        let hasher_tasks = self.launch_scanner_tasks(file_receiver, hash_sender, 16);
-
-        // start collecting hashes
-        let collect = tokio::spawn(async move {
-            let mut stream = hash_receiver.stream();
-            let mut last_update = Instant::now();
-            let mut current_files = 0;
-            let mut files: BTreeMap<BoxHash, (Vec<Tag>, BTreeSet<PathBuf>)> = BTreeMap::new();
-            while let Some((hash, metadata, paths)) = stream.next().await {
-                current_files += 1;
-
-                if Instant::now().duration_since(last_update) > UPDATE_INTERVAL {
-                    last_update = Instant::now();
-                    print!("\r\x1B[2Kscanning {current_files}/{total_files} files)");
-                    stdout().flush().unwrap();
+        hashed: Receiver<Hashed>,
+        unscanned: Sender<Hashed>,
+        scanned: Sender<Scanned>,
+    ) -> Result<()> {
+        let mut batches = hashed.into_stream().ready_chunks(BATCH_SIZE);
+        while let Some(batch) = batches.next().await {
+            let database = self.database_read().await?;
+            let batch = spawn_blocking(move || {
+                let mut files = Vec::with_capacity(batch.len());
+                for file in batch {
+                    let known = database.hash_exists(&file.2)?;
+                    files.push((file, known));
                 }
-
-                files.insert(hash, (metadata, paths));
-            }
-
-            println!("\r\x1B[2Kscanning {current_files}/{total_files} files");
-            Ok(files) as Result<BTreeMap<BoxHash, (Vec<Tag>, BTreeSet<PathBuf>)>>
-        });
-
-        // await for futures
-        for task in hasher_tasks.into_iter() {
-            task.await??;
-        }
-        sender.await??;
-        collect.await?
-    }
-
-    /// Skip known files
-    pub async fn skip_known(
-        &self,
-        mut files: BTreeMap<BoxHash, (Metadata, BTreeSet<PathBuf>)>,
-    ) -> Result<BTreeMap<BoxHash, (Metadata, BTreeSet<PathBuf>)>> {
-        let mut database = self.database().await;
-        spawn_blocking(move || {
-            let mut last_update = Instant::now();
-            let total_files = files.len();
-            let mut current_files = 0;
-
-            // check for existing files
-            let transaction = database.transaction()?;
-            let mut exists = BTreeSet::new();
-            for (hash, (_metadata, paths)) in &files {
-                current_files += 1;
-                if Instant::now().duration_since(last_update) > UPDATE_INTERVAL {
-                    last_update = Instant::now();
-                    print!("\r\x1B[2Kdeduplicating {current_files}/{total_files} files");
-                    stdout().flush().unwrap();
-                }
-
-                // if a file already exists, just save the paths
-                if transaction.hash_exists(hash)? {
-                    exists.insert(hash.clone());
-                    add_path_tags(&transaction, hash, &mut paths.iter().map(PathBuf::as_path))?;
+                Ok(files) as Result<Vec<_>>
+            })
+            .await??;
+            for ((path, metadata, hash), known) in batch {
+                match known {
+                    true => scanned.send_async((path, metadata, hash, None)).await?,
+                    false => unscanned.send_async((path, metadata, hash)).await?,
                 }
             }
-
-            transaction.commit()?;
-
-            // remove files that already exists
-            for hash in exists {
-                files.remove(&hash);
-            }
-
-            println!("\r\x1B[2Kdeduplicating {current_files}/{total_files} files");
-
-            Ok(files) as Result<_>
-        })
-        .await?
+        }
+        Ok(())
     }
 
-    /// Hash files.
-    pub async fn hash_files(
-        &self,
-        files: BTreeMap<PathBuf, Metadata>,
-    ) -> Result<BTreeMap<BoxHash, (Metadata, BTreeSet<PathBuf>)>> {
-        let total_files = files.len();
-        let total_bytes: u64 = files.values().map(|metadata| metadata.len()).sum();
-
-        // task submitting files to queue
-        let (file_sender, file_receiver) = flume::bounded::<(PathBuf, Metadata)>(1024);
-        let sender = tokio::spawn(async move {
-            for (file, metadata) in files.into_iter() {
-                file_sender.send_async((file, metadata)).await?;
-            }
-            Ok(()) as Result<()>
-        });
-
-        // tasks to pop messages off the queue and generate hashes
-        let (hash_sender, hash_receiver) = flume::bounded::<(PathBuf, Metadata, BoxHash)>(1024);
-        let hasher_tasks = self.launch_hasher_tasks(file_receiver, hash_sender, 16);
-
-        // start collecting hashes
-        let collect = tokio::spawn(async move {
-            let mut stream = hash_receiver.stream();
-            let mut last_update = Instant::now();
-            let mut current_files = 0;
-            let mut current_bytes = 0;
-            let mut files: BTreeMap<BoxHash, (Metadata, BTreeSet<PathBuf>)> = BTreeMap::new();
-            while let Some((path, metadata, hash)) = stream.next().await {
-                current_files += 1;
-                current_bytes += metadata.len();
-
-                if Instant::now().duration_since(last_update) > UPDATE_INTERVAL {
-                    last_update = Instant::now();
-                    print!("\r\x1B[2Khashing {current_files}/{total_files} files ({current_bytes}/{total_bytes} bytes)");
-                    stdout().flush().unwrap();
+    /// Commit files in batches, recording their stat info so they are skipped while unchanged.
+    async fn commit_files(&self, scanned: Receiver<Scanned>, job: &JobHandle) -> Result<()> {
+        let mut batches = scanned.into_stream().ready_chunks(BATCH_SIZE);
+        let mut done = 0;
+        while let Some(batch) = batches.next().await {
+            job.check()?;
+            done += batch.len() as u64;
+            let mut database = self.database().await;
+            spawn_blocking(move || {
+                let transaction = database.transaction()?;
+                for (path, metadata, hash, scan) in &batch {
+                    match scan {
+                        Some(scan) => add_file(&transaction, hash, scan, path)?,
+                        None => add_path_tags(&transaction, hash, path)?,
+                    }
+                    transaction.file_stat_set(path, &metadata.into(), hash)?;
                 }
-
-                files
-                    .entry(hash)
-                    .or_insert_with(move || (metadata, BTreeSet::new()))
-                    .1
-                    .insert(path);
-            }
-
-            println!("\r\x1B[2Khashing {current_files}/{total_files} files ({current_bytes}/{total_bytes} bytes)");
-            Ok(files) as Result<BTreeMap<BoxHash, (Metadata, BTreeSet<PathBuf>)>>
-        });
-
-        // await for futures
-        for task in hasher_tasks.into_iter() {
-            task.await??;
+                transaction.commit()?;
+                Ok(()) as Result<()>
+            })
+            .await??;
+            job.progress(done);
         }
-        sender.await??;
-        collect.await?
+        Ok(())
     }
 
-    /// List files (recursively)
+    /// List files (recursively), sending them to `files` as they are found.
     pub async fn list_files(
         &self,
         paths: &[PathBuf],
         recursive: bool,
-    ) -> Result<BTreeMap<PathBuf, Metadata>> {
-        let (sender, mut receiver) = channel::<(PathBuf, Metadata)>(1024);
-        let files = tokio::spawn(async move {
-            let mut files: BTreeMap<PathBuf, Metadata> = BTreeMap::new();
-            let mut last_update = Instant::now();
-            let mut bytes = 0;
-            while let Some((path, metadata)) = receiver.recv().await {
-                if Instant::now().duration_since(last_update) > UPDATE_INTERVAL {
-                    last_update = Instant::now();
-                    print!("\r\x1B[2Klisting {} files ({bytes} bytes)", files.len());
-                    stdout().flush().unwrap();
-                }
-
-                let file_len = metadata.len();
-                if files.insert(path, metadata).is_none() {
-                    bytes += file_len;
-                }
-            }
-
-            println!("\rlisting {} files ({bytes} bytes)", files.len());
-            files
-        });
-
+        files: Sender<(PathBuf, Metadata)>,
+        job: &JobHandle,
+    ) -> Result<()> {
         for path in paths {
             let path = std::fs::canonicalize(path)?;
             if recursive {
-                let sender = sender.clone();
+                let files = files.clone();
                 let root = self.root().to_path_buf();
                 let cindy = self.clone();
+                let job = job.clone();
                 spawn_blocking(move || {
                     // make sure we don't recurse into our own data or thumbs paths
                     let cindy_folder = cindy.cindy_folder();
@@ -395,10 +524,10 @@ impl Cindy {
 
                     // scan files recursively
                     for result in scan_files(&path, &filter) {
+                        job.check()?;
                         let (path, metadata) = result?;
                         let path = path.strip_prefix(&root)?.to_path_buf();
-                        let sender = sender.clone();
-                        sender.blocking_send((path, metadata))?;
+                        files.send((path, metadata))?;
                     }
 
                     Ok(()) as Result<()>
@@ -407,16 +536,14 @@ impl Cindy {
             } else {
                 let metadata = tokio::fs::metadata(&path).await?;
                 let path = path.strip_prefix(self.root())?.to_path_buf();
-                sender.send((path, metadata)).await?;
+                files.send_async((path, metadata)).await?;
             }
         }
-        drop(sender);
-
-        Ok(files.await?)
+        Ok(())
     }
 }
 
-fn scan_files<'a>(
+pub(super) fn scan_files<'a>(
     path: &Path,
     filter: &'a dyn Fn(&Path) -> bool,
 ) -> impl Iterator<Item = Result<(PathBuf, Metadata)>> + 'a {
@@ -443,7 +570,10 @@ fn scan_files<'a>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::{create_dir, write};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        fs::{create_dir, write},
+    };
     use tempfile::tempdir;
 
     #[test]
@@ -466,6 +596,19 @@ mod tests {
         assert!(tags.contains(&Tag::new("ancestor".into(), "/".into())));
     }
 
+    #[test]
+    fn test_mime_tag() {
+        let tag = mime_tag(Path::new("samples/image1.png")).unwrap();
+        assert_eq!(tag, Some(Tag::new("mime".into(), "image/png".into())));
+        let tag = mime_tag(Path::new("samples/video1.mkv")).unwrap();
+        assert_eq!(
+            tag,
+            Some(Tag::new("mime".into(), "video/x-matroska".into()))
+        );
+        let tag = mime_tag(Path::new("samples/textfile.txt")).unwrap();
+        assert_eq!(tag, None);
+    }
+
     #[test]
     fn can_scan_files() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,336 @@
+use super::{add::path_tags, gc::freed, job_render};
+use crate::{
+    cli::{DuplicatesCommand, KeepPolicy},
+    common::Duplicate,
+    database::{Database, Handle},
+    hash::{BoxHash, FileStat, Hash},
+    job::JobHandle,
+    similar::{clusters, fingerprint_clusters, MAX_DISTANCE},
+    Cindy, Tag,
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeSet,
+    fs::{metadata, remove_file, Metadata},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use tokio::task::spawn_blocking;
+
+fn group_hashes(hashes: &[BoxHash], group: Vec<usize>) -> Vec<BoxHash> {
+    group.into_iter().map(|item| hashes[item].clone()).collect()
+}
+
+/// Copies of a duplicate file to remove so that only the one picked by `keep` remains.
+///
+/// Copies which are missing or changed since they were added are neither kept nor removed, and
+/// nothing is removed if no copy satisfies the policy.
+fn redundant_copies<H: Handle>(
+    database: &Database<H>,
+    root: &Path,
+    duplicate: &Duplicate,
+    keep: &KeepPolicy,
+) -> Result<Vec<(PathBuf, Metadata)>> {
+    let mut copies = vec![];
+    for path in &duplicate.paths {
+        let metadata = match metadata(root.join(path)) {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+        match database.file_stat(path)? {
+            Some((stat, hash)) if hash == duplicate.hash && stat == FileStat::from(&metadata) => {
+                copies.push((path.clone(), metadata))
+            }
+            _ => {}
+        }
+    }
+
+    let kept = match keep {
+        KeepPolicy::Oldest => copies
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (_, metadata))| FileStat::from(metadata).mtime),
+        KeepPolicy::Shortest => copies
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (path, _))| path.as_os_str().len()),
+        KeepPolicy::Directory(directory) => copies
+            .iter()
+            .enumerate()
+            .find(|(_, (path, _))| path.starts_with(directory)),
+    };
+    let Some((kept, _)) = kept else {
+        return Ok(vec![]);
+    };
+    copies.remove(kept);
+    Ok(copies)
+}
+
+impl Cindy {
+    pub async fn command_duplicates(&self, command: &DuplicatesCommand) -> Result<()> {
+        if let Some(keep) = &command.keep {
+            return self.command_duplicates_remove(keep, command.dry_run).await;
+        }
+
+        let groups = match command.similar {
+            true => {
+                let job = self.jobs().create("duplicates");
+                let groups = job_render(&job, async {
+                    self.similar_missing(&job).await?;
+                    self.similar_files(command.distance.min(MAX_DISTANCE)).await
+                })
+                .await?;
+                let database = self.database_read().await?;
+                spawn_blocking(move || {
+                    let mut lines = vec![];
+                    for group in groups {
+                        let mut group_lines = vec![];
+                        for hash in group {
+                            let paths = database.file_stat_paths(&hash)?;
+                            group_lines.push(match paths.first() {
+                                Some(path) => format!("{hash} {}", path.display()),
+                                None => hash.to_string(),
+                            });
+                        }
+                        lines.push(group_lines);
+                    }
+                    Ok(lines) as Result<Vec<Vec<String>>>
+                })
+                .await??
+            }
+            false => {
+                let duplicates = self.duplicates().await?;
+                let files = duplicates.len();
+                let reclaimable: u64 = duplicates.iter().map(|file| file.reclaimable).sum();
+                let mut groups: Vec<Vec<String>> = duplicates
+                    .into_iter()
+                    .map(|file| {
+                        file.paths
+                            .iter()
+                            .map(|path| format!("{} {} {}", file.hash, file.size, path.display()))
+                            .collect()
+                    })
+                    .collect();
+                groups.push(vec![format!(
+                    "{files} files added from several paths, {reclaimable} bytes reclaimable"
+                )]);
+                groups
+            }
+        };
+
+        for (index, group) in groups.iter().enumerate() {
+            if index > 0 {
+                println!();
+            }
+            for line in group {
+                println!("{line}");
+            }
+        }
+        Ok(())
+    }
+
+    async fn command_duplicates_remove(&self, keep: &KeepPolicy, dry_run: bool) -> Result<()> {
+        let keep = match keep {
+            KeepPolicy::Directory(directory) => {
+                let directory = directory.canonicalize()?;
+                let directory = directory
+                    .strip_prefix(self.root())
+                    .context("Directory is not inside the project")?;
+                KeepPolicy::Directory(directory.into())
+            }
+            keep => keep.clone(),
+        };
+        let job = self.jobs().create("duplicates");
+        let removed = job_render(&job, self.duplicates_remove(&keep, dry_run, &job)).await?;
+        let verb = match dry_run {
+            true => "Would remove",
+            false => "Removed",
+        };
+        for (path, _) in &removed {
+            println!("{verb} {}", path.display());
+        }
+        let bytes: u64 = removed.iter().map(|(_, bytes)| bytes).sum();
+        println!("{verb} {} copies, reclaiming {bytes} bytes", removed.len());
+        Ok(())
+    }
+
+    /// Files which were added from more than one path.
+    pub async fn duplicates(&self) -> Result<Vec<Duplicate>> {
+        let database = self.database_read().await?;
+        Ok(spawn_blocking(move || database.hash_duplicates()).await??)
+    }
+
+    /// Remove all but one copy of each file added from several paths from the working tree,
+    /// along with their path tags, returning the removed paths and how many bytes that freed.
+    ///
+    /// The directory of [`KeepPolicy::Directory`] is relative to the project root. Data is not
+    /// removed from the data store, where it is kept for the remaining copy.
+    pub async fn duplicates_remove(
+        &self,
+        keep: &KeepPolicy,
+        dry_run: bool,
+        job: &JobHandle,
+    ) -> Result<Vec<(PathBuf, u64)>> {
+        // holding the writer connection keeps files from being added in the meantime
+        let mut database = self.database().await;
+        let (root, keep, job) = (self.root().to_path_buf(), keep.clone(), job.clone());
+        spawn_blocking(move || {
+            let duplicates = database.hash_duplicates()?;
+            job.phase("removing", duplicates.len() as u64);
+            let transaction = database.transaction()?;
+            let mut removed = vec![];
+            for (done, duplicate) in duplicates.iter().enumerate() {
+                job.check()?;
+                let copies = redundant_copies(&transaction, &root, duplicate, &keep)?;
+                let gone: Vec<&PathBuf> = copies.iter().map(|(path, _)| path).collect();
+                if !dry_run {
+                    for path in &gone {
+                        transaction.file_stat_remove(path)?;
+                    }
+
+                    // directory and ancestor tags might still be shared with remaining copies
+                    let remaining: BTreeSet<Tag> = duplicate
+                        .paths
+                        .iter()
+                        .filter(|path| !gone.contains(path))
+                        .flat_map(|path| path_tags(path))
+                        .collect();
+                    let tags = gone.iter().flat_map(|path| path_tags(path));
+                    for tag in tags.filter(|tag| !remaining.contains(tag)) {
+                        transaction.hash_tag_remove(
+                            &duplicate.hash,
+                            Some(tag.name()),
+                            Some(tag.value()),
+                        )?;
+                    }
+                }
+                removed.extend(
+                    copies
+                        .iter()
+                        .map(|(path, metadata)| (path.clone(), freed(metadata))),
+                );
+                job.progress(done as u64 + 1);
+            }
+            transaction.commit()?;
+            if dry_run {
+                return Ok(removed);
+            }
+
+            // only touch the working tree once the index no longer refers to the copies, a
+            // copy which fails to be removed is picked up again by the next scan
+            let mut deleted = vec![];
+            for (path, freed) in removed {
+                match remove_file(root.join(&path)) {
+                    Ok(()) => deleted.push((path, freed)),
+                    Err(error) => job.error(format!("{}: {error}", path.display())),
+                }
+            }
+            Ok(deleted)
+        })
+        .await?
+    }
+
+    /// Compute the perceptual hash of images and the fingerprint of videos which do not have
+    /// one yet.
+    ///
+    /// Files added before these were computed are skipped when added again, since their hash is
+    /// already known.
+    #[cfg(feature = "ffmpeg")]
+    pub async fn similar_missing(&self, job: &JobHandle) -> Result<()> {
+        let database = self.database_read().await?;
+        let images = spawn_blocking(move || database.hashes_without_phash()).await??;
+        job.phase("hashing images", images.len() as u64);
+        let phashes = self
+            .compute_missing(images, crate::media::perceptual_hash, job)
+            .await?;
+
+        let database = self.database_read().await?;
+        let videos = spawn_blocking(move || database.hashes_without_fingerprint()).await??;
+        job.phase("fingerprinting videos", videos.len() as u64);
+        let fingerprints = self
+            .compute_missing(videos, crate::media::video_fingerprint, job)
+            .await?;
+
+        let mut database = self.database().await;
+        spawn_blocking(move || {
+            let transaction = database.transaction()?;
+            for (hash, phash) in &phashes {
+                transaction.hash_phash_set(hash, *phash)?;
+            }
+            for (hash, fingerprint) in &fingerprints {
+                transaction.hash_fingerprint_set(hash, fingerprint)?;
+            }
+            transaction.commit()?;
+            Ok(()) as Result<()>
+        })
+        .await?
+    }
+
+    #[cfg(not(feature = "ffmpeg"))]
+    pub async fn similar_missing(&self, _job: &JobHandle) -> Result<()> {
+        Ok(())
+    }
+
+    /// Compute something from the data of files in parallel, reporting the ones it fails for.
+    #[cfg(feature = "ffmpeg")]
+    async fn compute_missing<T: Send + 'static>(
+        &self,
+        hashes: Vec<BoxHash>,
+        compute: fn(&std::path::Path) -> Result<T>,
+        job: &JobHandle,
+    ) -> Result<Vec<(BoxHash, T)>> {
+        use anyhow::anyhow;
+        use futures::{stream, StreamExt};
+
+        let mut results = stream::iter(hashes)
+            .map(|hash| async move {
+                let computed = match self.file_path(&hash).await? {
+                    Some(path) => spawn_blocking(move || compute(&path)).await?,
+                    None => Err(anyhow!("Data is missing")),
+                };
+                Ok((hash, computed)) as Result<(BoxHash, Result<T>)>
+            })
+            .buffer_unordered(self.threads());
+
+        let mut computed = vec![];
+        let mut done = 0;
+        while let Some(result) = results.next().await {
+            job.check()?;
+            match result? {
+                (hash, Ok(value)) => computed.push((hash, value)),
+                (hash, Err(error)) => job.error(format!("{hash}: {error:#}")),
+            }
+            done += 1;
+            job.progress(done);
+        }
+        Ok(computed)
+    }
+
+    /// Groups of images whose perceptual hashes are at most `distance` bits apart, followed by
+    /// groups of videos whose fingerprints match with frames at most that far apart.
+    pub async fn similar_files(&self, distance: u32) -> Result<Vec<Vec<BoxHash>>> {
+        let database = self.database_read().await?;
+        spawn_blocking(move || {
+            let (images, phashes): (Vec<BoxHash>, Vec<u64>) =
+                database.hash_phashes()?.into_iter().unzip();
+            let (videos, fingerprints): (Vec<BoxHash>, Vec<Vec<u64>>) =
+                database.hash_fingerprints()?.into_iter().unzip();
+            let images = clusters(&phashes, distance)
+                .into_iter()
+                .map(|group| group_hashes(&images, group));
+            let videos = fingerprint_clusters(&fingerprints, distance)
+                .into_iter()
+                .map(|group| group_hashes(&videos, group));
+            Ok(images.chain(videos).collect())
+        })
+        .await?
+    }
+
+    /// Images whose perceptual hash is at most `distance` bits away from that of this file, or
+    /// videos whose fingerprint matches with frames at most that far apart.
+    pub async fn similar_to(&self, hash: &Hash, distance: u32) -> Result<Vec<BoxHash>> {
+        let lookup = self.similar_lookup().await?;
+        Ok(lookup.find(hash, distance).into_iter().cloned().collect())
+    }
+}
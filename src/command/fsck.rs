@@ -0,0 +1,333 @@
+use super::{add::scan_files, gc::changed, job_render};
+use crate::{
+    cli::FsckCommand,
+    config::DataMode,
+    database::{Database, Handle},
+    hash::{BoxHash, Hash, ReadDigester},
+    job::JobHandle,
+    Cindy,
+};
+use anyhow::{bail, Result};
+use futures::{stream, StreamExt};
+use std::{
+    fmt,
+    fs::{remove_file, File},
+    mem::take,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::spawn_blocking;
+
+/// How many files are recorded as verified at once.
+const VERIFIED_BATCH: usize = 256;
+
+/// Problem found when checking a project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// There is no data to read for a file.
+    Missing(BoxHash),
+    /// The data of a file no longer has its hash, for example because of bit rot.
+    Mismatch { hash: BoxHash, actual: BoxHash },
+    /// Data file which does not belong to any file in the index.
+    Orphan(PathBuf),
+    /// Tag value whose tag name no longer exists.
+    TagValue { id: i64, value: String },
+    /// Tag of a file whose file or tag value no longer exists.
+    FileTag(i64),
+    /// Label whose file tag no longer exists.
+    Label { kind: String, id: i64 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing(hash) => write!(f, "Data of {hash} is missing"),
+            Problem::Mismatch { hash, actual } => write!(f, "Data of {hash} has hash {actual}"),
+            Problem::Orphan(path) => write!(f, "Data file {} has no file", path.display()),
+            Problem::TagValue { id, value } => write!(f, "Tag value {id} {value:?} has no tag"),
+            Problem::FileTag(id) => write!(f, "File tag {id} has no file or tag value"),
+            Problem::Label { kind, id } => write!(f, "Label {kind} {id} has no file tag"),
+        }
+    }
+}
+
+/// Problem found when checking a project, and whether it was repaired.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub problem: Problem,
+    pub repaired: bool,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.problem)?;
+        if self.repaired {
+            f.write_str(", repaired")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a file in the data store is a temporary one, such as a copy which is being verified.
+fn temporary(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(".tmp") || name.ends_with(".partial"))
+}
+
+/// Hash a data file is named after, given the path of the data store.
+pub(super) fn data_hash(data: &Path, path: &Path) -> Option<BoxHash> {
+    let name: String = path
+        .strip_prefix(data)
+        .ok()?
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<_>>()?;
+    name.parse().ok()
+}
+
+/// Tag values, file tags and labels which refer to rows that no longer exist.
+fn dangling<H: Handle>(database: &Database<H>) -> Result<Vec<Problem>> {
+    let values = database
+        .dangling_tag_values()?
+        .into_iter()
+        .map(|(id, value)| Problem::TagValue { id, value });
+    let tags = database
+        .dangling_file_tags()?
+        .into_iter()
+        .map(Problem::FileTag);
+    let labels = database
+        .dangling_labels()?
+        .into_iter()
+        .map(|(kind, id)| Problem::Label { kind, id });
+    Ok(values.chain(tags).chain(labels).collect())
+}
+
+impl Cindy {
+    pub async fn command_fsck(&self, command: &FsckCommand) -> Result<()> {
+        let job = self.jobs().create("fsck");
+        let grace = Duration::from_secs(command.grace);
+        let fsck = self.fsck(command.repair, command.scrub, grace, &job);
+        let findings = job_render(&job, fsck).await?;
+        for finding in &findings {
+            println!("{finding}");
+        }
+        let remaining = findings.iter().filter(|finding| !finding.repaired).count();
+        if remaining > 0 {
+            bail!("Found {remaining} problems which were not repaired");
+        }
+        Ok(())
+    }
+
+    /// Check that all files have their data, that the data still has their hash and that the
+    /// index does not refer to rows which no longer exist.
+    ///
+    /// With `scrub`, only as many files are hashed as needed to verify all of them over that many
+    /// runs, starting with the ones verified longest ago. Missing data is checked either way.
+    ///
+    /// Data files changed within the `grace` period are not orphans, they might belong to files
+    /// being added.
+    pub async fn fsck(
+        &self,
+        repair: bool,
+        scrub: Option<u64>,
+        grace: Duration,
+        job: &JobHandle,
+    ) -> Result<Vec<Finding>> {
+        let mut findings = self.fsck_data(repair, scrub, job).await?;
+        findings.extend(self.fsck_orphans(repair, grace, job).await?);
+        findings.extend(self.fsck_index(repair, job).await?);
+        Ok(findings)
+    }
+
+    /// Check the data of all files, hashing the ones that are due.
+    async fn fsck_data(
+        &self,
+        repair: bool,
+        scrub: Option<u64>,
+        job: &JobHandle,
+    ) -> Result<Vec<Finding>> {
+        let database = self.database_read().await?;
+        let hashes = spawn_blocking(move || database.hashes_by_verified()).await??;
+        let due = match scrub {
+            Some(runs) => hashes.len().div_ceil(runs.max(1) as usize),
+            None => hashes.len(),
+        };
+
+        job.phase("verifying", hashes.len() as u64);
+        let mut results = stream::iter(hashes.into_iter().enumerate())
+            .map(|(index, hash)| async move {
+                let hashed = index < due;
+                let problem = self.fsck_file(&hash, hashed).await?;
+                Ok((hash, hashed, problem)) as Result<(BoxHash, bool, Option<Problem>)>
+            })
+            .buffer_unordered(self.threads());
+
+        let mut findings = vec![];
+        let mut verified = vec![];
+        let mut done = 0;
+        while let Some(result) = results.next().await {
+            job.check()?;
+            match result? {
+                (hash, _, Some(problem)) => {
+                    let repaired = repair && self.data_restore(&hash).await?;
+                    findings.push(Finding { problem, repaired });
+                }
+                (hash, true, None) => verified.push(hash),
+                (_, false, None) => {}
+            }
+            if verified.len() >= VERIFIED_BATCH {
+                self.fsck_verified(take(&mut verified)).await?;
+            }
+            done += 1;
+            job.progress(done);
+        }
+        self.fsck_verified(verified).await?;
+        Ok(findings)
+    }
+
+    /// Check that the data of a file exists, and that it has the right hash if `hashed` is set.
+    async fn fsck_file(&self, hash: &BoxHash, hashed: bool) -> Result<Option<Problem>> {
+        let path = self.file_path(hash).await?;
+        let (cindy, hash) = (self.clone(), hash.clone());
+        spawn_blocking(move || {
+            let Some(path) = path.filter(|path| path.is_file()) else {
+                return Ok(Some(Problem::Missing(hash)));
+            };
+            if !hashed {
+                return Ok(None);
+            }
+            let actual = cindy.hasher().hash_read(&mut File::open(path)?)?;
+            Ok((actual != hash).then_some(Problem::Mismatch { hash, actual }))
+        })
+        .await?
+    }
+
+    /// Record that the data of files was verified just now.
+    async fn fsck_verified(&self, hashes: Vec<BoxHash>) -> Result<()> {
+        if hashes.is_empty() {
+            return Ok(());
+        }
+        let time = chrono::Utc::now().timestamp();
+        let mut database = self.database().await;
+        spawn_blocking(move || {
+            let transaction = database.transaction()?;
+            for hash in &hashes {
+                transaction.hash_verified(hash, time)?;
+            }
+            transaction.commit()?;
+            Ok(()) as Result<()>
+        })
+        .await?
+    }
+
+    /// Put the data of a file back into the data store, from a path it was added from which
+    /// still has the same contents.
+    async fn data_restore(&self, hash: &Hash) -> Result<bool> {
+        if self.config().data.mode == DataMode::Reference {
+            return Ok(false);
+        }
+        let database = self.database_read().await?;
+        let (cindy, hash) = (self.clone(), BoxHash::from(hash));
+        spawn_blocking(move || {
+            let paths = database.file_stat_paths(&hash)?;
+            drop(database);
+            for path in paths {
+                let Ok(mut file) = File::open(cindy.root().join(&path)) else {
+                    continue;
+                };
+                if cindy.hasher().hash_read(&mut file)? != hash {
+                    continue;
+                }
+                let data = cindy.hash_path(&hash);
+                if data.exists() {
+                    remove_file(&data)?;
+                }
+                cindy.data_add(&path, &hash)?;
+                return Ok(true);
+            }
+            Ok(false)
+        })
+        .await?
+    }
+
+    /// Find data files which do not belong to any file in the index.
+    async fn fsck_orphans(
+        &self,
+        repair: bool,
+        grace: Duration,
+        job: &JobHandle,
+    ) -> Result<Vec<Finding>> {
+        let data = self.data_path();
+        if !tokio::fs::try_exists(&data).await? {
+            return Ok(vec![]);
+        }
+
+        // holding the writer connection keeps files from being added while repairing
+        let writer = match repair {
+            true => Some(self.database().await),
+            false => None,
+        };
+        let cutoff = SystemTime::now().checked_sub(grace).unwrap_or(UNIX_EPOCH);
+
+        job.phase("scanning data", 0);
+        let database = self.database_read().await?;
+        let (cindy, job) = (self.clone(), job.clone());
+        spawn_blocking(move || {
+            let _writer = writer;
+            let mut findings = vec![];
+            for (index, result) in scan_files(&data, &|_| true).enumerate() {
+                job.check()?;
+                job.progress(index as u64 + 1);
+                let (path, metadata) = result?;
+                if temporary(&path) || changed(&metadata) > cutoff {
+                    continue;
+                }
+                let known = match data_hash(&data, &path) {
+                    Some(hash) => cindy.hash_path(&hash) == path && database.hash_exists(&hash)?,
+                    None => false,
+                };
+                if !known {
+                    if repair {
+                        remove_file(&path)?;
+                    }
+                    findings.push(Finding {
+                        problem: Problem::Orphan(path),
+                        repaired: repair,
+                    });
+                }
+            }
+            Ok(findings)
+        })
+        .await?
+    }
+
+    /// Find rows in the index which refer to rows that no longer exist.
+    async fn fsck_index(&self, repair: bool, job: &JobHandle) -> Result<Vec<Finding>> {
+        job.phase("checking index", 0);
+        let problems = match repair {
+            true => {
+                let mut database = self.database().await;
+                spawn_blocking(move || {
+                    let transaction = database.transaction()?;
+                    let problems = dangling(&transaction)?;
+                    transaction.dangling_remove()?;
+                    transaction.commit()?;
+                    Ok(problems) as Result<_>
+                })
+                .await??
+            }
+            false => {
+                let database = self.database_read().await?;
+                spawn_blocking(move || dangling(&database)).await??
+            }
+        };
+        Ok(problems
+            .into_iter()
+            .map(|problem| Finding {
+                problem,
+                repaired: repair,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,147 @@
+use super::{add::scan_files, fsck::data_hash, job_render};
+use crate::{cli::GcCommand, hash::BoxHash, job::JobHandle, Cindy};
+use anyhow::Result;
+use std::{
+    fs::{remove_dir, remove_file, Metadata},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::spawn_blocking;
+
+/// Entries removed by garbage collection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// How many files were removed.
+    pub files: u64,
+    /// How many of the removed files freed space, the others are still linked elsewhere.
+    pub freed: u64,
+    /// How many bytes were freed by removing them.
+    pub bytes: u64,
+}
+
+/// Finds the hash an entry belongs to, given the folder it is in.
+type EntryHash = fn(&Path, &Path) -> Option<BoxHash>;
+
+/// Hash of a thumbnail, which is named after it followed by what was generated.
+fn thumb_hash(thumbs: &Path, path: &Path) -> Option<BoxHash> {
+    let name = path.strip_prefix(thumbs).ok()?.to_str()?;
+    name.split('-').next()?.parse().ok()
+}
+
+/// Hash of a cached segment, which is kept in a folder named after it.
+fn segment_hash(hls: &Path, path: &Path) -> Option<BoxHash> {
+    let folder = path.strip_prefix(hls).ok()?.iter().next()?;
+    folder.to_str()?.parse().ok()
+}
+
+/// When a file was last modified or linked to, whichever is later.
+#[cfg(unix)]
+pub(super) fn changed(metadata: &Metadata) -> SystemTime {
+    use std::os::unix::fs::MetadataExt;
+    let ctime = UNIX_EPOCH + Duration::new(metadata.ctime() as u64, metadata.ctime_nsec() as u32);
+    metadata
+        .modified()
+        .map_or(ctime, |modified| modified.max(ctime))
+}
+
+#[cfg(not(unix))]
+pub(super) fn changed(metadata: &Metadata) -> SystemTime {
+    metadata.modified().unwrap_or(UNIX_EPOCH)
+}
+
+/// Whether other links to a file remain, so that removing it frees no space.
+#[cfg(unix)]
+fn linked_elsewhere(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn linked_elsewhere(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Space freed by removing a file, which is nothing while other links to it remain.
+pub(super) fn freed(metadata: &Metadata) -> u64 {
+    match linked_elsewhere(metadata) {
+        true => 0,
+        false => metadata.len(),
+    }
+}
+
+impl Cindy {
+    pub async fn command_gc(&self, command: &GcCommand) -> Result<()> {
+        let job = self.jobs().create("gc");
+        let grace = Duration::from_secs(command.grace);
+        let report = job_render(&job, self.gc(command.dry_run, grace, &job)).await?;
+        let verb = match command.dry_run {
+            true => "Would remove",
+            false => "Removed",
+        };
+        println!(
+            "{verb} {} files, reclaiming {} bytes from {} which are not linked elsewhere",
+            report.files, report.bytes, report.freed
+        );
+        Ok(())
+    }
+
+    /// Remove data files, thumbnails and cached segments of files which are no longer in the
+    /// index, unless they were changed within the `grace` period.
+    ///
+    /// Entries whose names are not hashes are left alone, these are reported by fsck.
+    pub async fn gc(&self, dry_run: bool, grace: Duration, job: &JobHandle) -> Result<Report> {
+        let folders: [(PathBuf, EntryHash); 3] = [
+            (self.data_path(), data_hash),
+            (self.thumbs_path(), thumb_hash),
+            (self.hls_path(), segment_hash),
+        ];
+        let cutoff = SystemTime::now().checked_sub(grace).unwrap_or(UNIX_EPOCH);
+
+        job.phase("collecting", 0);
+        let database = self.database_read().await?;
+        let job = job.clone();
+        spawn_blocking(move || {
+            let mut report = Report::default();
+            let mut garbage = vec![];
+            let mut done = 0;
+            for (folder, entry_hash) in &folders {
+                if !folder.exists() {
+                    continue;
+                }
+                for result in scan_files(folder, &|_| true) {
+                    job.check()?;
+                    done += 1;
+                    job.progress(done);
+                    let (path, metadata) = result?;
+                    let Some(hash) = entry_hash(folder, &path) else {
+                        continue;
+                    };
+                    if changed(&metadata) > cutoff || database.hash_exists(&hash)? {
+                        continue;
+                    }
+                    report.files += 1;
+                    if !linked_elsewhere(&metadata) {
+                        report.freed += 1;
+                        report.bytes += metadata.len();
+                    }
+                    garbage.push((folder, path));
+                }
+            }
+
+            if !dry_run {
+                for (folder, path) in garbage {
+                    remove_file(&path)?;
+
+                    // clean up folders which are left empty, up to the one being collected
+                    for parent in path.ancestors().skip(1) {
+                        if parent == folder.as_path() || remove_dir(parent).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(report)
+        })
+        .await?
+    }
+}
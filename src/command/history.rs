@@ -0,0 +1,60 @@
+use crate::{
+    cli::{HistoryCommand, UndoCommand},
+    history::revert,
+    Cindy,
+};
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use cindy_common::ChangeBatch;
+use tokio::task::spawn_blocking;
+
+fn print_batch(batch: &ChangeBatch) {
+    let time = Local
+        .timestamp_opt(batch.time, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    println!("#{} {time} {}", batch.id, batch.origin);
+    for change in &batch.changes {
+        match change.reverted {
+            true => println!("  {} (reverted)", change.operation),
+            false => println!("  {}", change.operation),
+        }
+    }
+}
+
+impl Cindy {
+    pub async fn command_history(&self, command: &HistoryCommand) -> Result<()> {
+        let database = self.database_read().await?;
+        let command = command.clone();
+        let batches =
+            spawn_blocking(move || database.journal_batches(command.limit, command.all)).await??;
+        for batch in &batches {
+            print_batch(batch);
+        }
+        Ok(())
+    }
+
+    pub async fn command_undo(&self, command: &UndoCommand) -> Result<()> {
+        let mut database = self.database().await;
+        let count = command.count;
+        let batches = spawn_blocking(move || {
+            let transaction = database.transaction()?;
+            let batches = transaction.journal_batches(count, false)?;
+            for batch in &batches {
+                revert(&transaction, &batch.changes)?;
+            }
+            transaction.commit()?;
+            Ok::<_, anyhow::Error>(batches)
+        })
+        .await??;
+        if batches.is_empty() {
+            println!("Nothing to undo");
+        }
+        for batch in &batches {
+            print!("Reverted ");
+            print_batch(batch);
+        }
+        Ok(())
+    }
+}
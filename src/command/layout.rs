@@ -0,0 +1,191 @@
+use super::{add::scan_files, fsck::data_hash, job_render, rehash::with_suffix};
+use crate::{
+    cli::MigrateLayoutCommand,
+    config::Config,
+    job::{JobHandle, Jobs},
+    Cindy,
+};
+use anyhow::{bail, Context, Result};
+use std::{
+    fs::{create_dir_all, read_dir, remove_dir, remove_dir_all, rename, write},
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::task::spawn_blocking;
+
+/// Moves the data store and thumbnails from one layout to another.
+///
+/// The new config is written next to the current one before anything is moved, and only
+/// replaces it once everything has been moved. Moving again is safe, so an interrupted migration
+/// is finished the next time the project is loaded.
+#[derive(Debug, Clone)]
+struct LayoutMigration {
+    folder: PathBuf,
+    old: Config,
+    new: Config,
+    config: PathBuf,
+    pending: PathBuf,
+}
+
+/// Remove empty folders below `path`, and `path` itself if it ends up empty.
+fn remove_empty_dirs(path: &Path) -> io::Result<()> {
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_empty_dirs(&entry.path())?;
+        }
+    }
+    if read_dir(path)?.next().is_none() {
+        remove_dir(path)?;
+    }
+    Ok(())
+}
+
+impl LayoutMigration {
+    fn new(folder: &Path, config_path: &Path, old: Config, new: Config) -> Self {
+        Self {
+            folder: folder.into(),
+            old,
+            new,
+            config: config_path.into(),
+            pending: with_suffix(config_path, "layout"),
+        }
+    }
+
+    fn run(&self, job: &JobHandle) -> Result<()> {
+        write(&self.pending, toml::to_string(&self.new)?)?;
+        self.move_data(job).context("Moving data")?;
+        self.move_thumbs().context("Moving thumbnails")?;
+        self.clear_segments().context("Clearing cached segments")?;
+        rename(&self.pending, &self.config)?;
+        Ok(())
+    }
+
+    /// Move data files to where the new layout expects them.
+    ///
+    /// Data files are named after their hash split into folders, so the hash can be read back
+    /// from their path whichever layout they are in.
+    fn move_data(&self, job: &JobHandle) -> Result<()> {
+        let old = self.folder.join(&self.old.data.path);
+        let new = self.folder.join(&self.new.data.path);
+        job.phase("moving", 0);
+        if old.exists() {
+            let mut moved = 0;
+            for result in scan_files(&old, &|_| true) {
+                job.check()?;
+                let (path, _) = result?;
+                let Some(hash) = data_hash(&old, &path) else {
+                    continue;
+                };
+                let target = self.folder.join(self.new.data.data_path(&hash));
+                if path != target {
+                    create_dir_all(target.parent().unwrap())?;
+                    rename(&path, &target).with_context(|| {
+                        format!("Moving {} to {}", path.display(), target.display())
+                    })?;
+                    moved += 1;
+                    job.progress(moved);
+                }
+            }
+            remove_empty_dirs(&old)?;
+        }
+        create_dir_all(new)?;
+        Ok(())
+    }
+
+    /// Move thumbnails to the new thumbnail folder, if it changed.
+    fn move_thumbs(&self) -> Result<()> {
+        let old = self.folder.join(&self.old.thumbs.path);
+        let new = self.folder.join(&self.new.thumbs.path);
+        if old != new && old.exists() {
+            create_dir_all(&new)?;
+            for entry in read_dir(&old)? {
+                let path = entry?.path();
+                if path != new {
+                    rename(&path, new.join(path.file_name().unwrap()))?;
+                }
+            }
+            if !new.starts_with(&old) {
+                remove_dir(&old)?;
+            }
+        }
+        create_dir_all(new)?;
+        Ok(())
+    }
+
+    /// Remove cached segments rather than moving them, they are generated again when requested.
+    fn clear_segments(&self) -> Result<()> {
+        let hls = self.folder.join(&self.old.hls.path);
+        if hls.exists() {
+            remove_dir_all(&hls)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finish a layout migration that was interrupted.
+pub async fn layout_recover(folder: &Path, config_path: &Path) -> Result<()> {
+    let pending = with_suffix(config_path, "layout");
+    if !tokio::fs::try_exists(&pending).await? {
+        return Ok(());
+    }
+    let old: Config = toml::from_str(&tokio::fs::read_to_string(config_path).await?)?;
+    let new: Config = toml::from_str(&tokio::fs::read_to_string(&pending).await?)?;
+    let migration = LayoutMigration::new(folder, config_path, old, new);
+    let job = Jobs::default().create("migrate-layout");
+    spawn_blocking(move || migration.run(&job))
+        .await?
+        .context("Finishing interrupted layout migration")
+}
+
+impl Cindy {
+    pub async fn command_migrate_layout(&self, command: &MigrateLayoutCommand) -> Result<()> {
+        let config = self.config();
+        let path = command.path.as_ref().unwrap_or(&config.data.path);
+        let prefix = command.prefix.as_ref().unwrap_or(&config.data.prefix);
+        let thumbs = command.thumbs.as_ref().unwrap_or(&config.thumbs.path);
+        let job = self.jobs().create("migrate-layout");
+        job_render(&job, self.migrate_layout(path, prefix, thumbs, &job)).await
+    }
+
+    /// Move the data store and thumbnails to a different layout, updating the config.
+    ///
+    /// This instance keeps using the previous layout, so the project needs to be loaded again
+    /// afterwards. It should not run while the project is being served.
+    pub async fn migrate_layout(
+        &self,
+        path: &Path,
+        prefix: &[u8],
+        thumbs: &Path,
+        job: &JobHandle,
+    ) -> Result<()> {
+        let length = self.hasher().output_size() * 2;
+        let total: usize = prefix.iter().map(|length| *length as usize).sum();
+        if prefix.contains(&0) || total >= length {
+            bail!("Prefix lengths must not be zero and add up to less than {length}");
+        }
+
+        let old = (**self.config()).clone();
+        let mut new = old.clone();
+        new.data.path = path.into();
+        new.data.prefix = prefix.into();
+        new.thumbs.path = thumbs.into();
+        if new == old {
+            bail!("Project already uses this layout");
+        }
+
+        // moved files would be visited again while scanning for the ones to move
+        let (old_path, new_path) = (&old.data.path, &new.data.path);
+        if old_path != new_path
+            && (old_path.starts_with(new_path) || new_path.starts_with(old_path))
+        {
+            bail!("Data paths must not be nested inside each other");
+        }
+
+        // holding the writer connection keeps files from being added in the meantime
+        let _database = self.database().await;
+        let migration = LayoutMigration::new(&self.cindy_folder(), &self.config_path(), old, new);
+        let job = job.clone();
+        spawn_blocking(move || migration.run(&job)).await?
+    }
+}
@@ -3,7 +3,7 @@ use anyhow::Result;
 
 impl Cindy {
     pub async fn command_query(&self, command: &QueryCommand) -> Result<()> {
-        let database = self.database().await;
+        let database = self.database_read().await?;
         let command = command.clone();
         tokio::task::spawn_blocking(move || {
             let hashes = database.query_hashes(&mut command.filters.iter())?;
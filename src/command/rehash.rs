@@ -0,0 +1,262 @@
+use super::job_render;
+use crate::{
+    cli::RehashCommand,
+    config::{Config, DataConfig, DataMode, HashAlgorithm},
+    database::Database,
+    hash::{BoxHash, ReadDigester},
+    job::JobHandle,
+    Cindy,
+};
+use anyhow::{bail, Context, Result};
+use futures::{stream, StreamExt};
+use rusqlite::Connection;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs::{create_dir_all, hard_link, remove_dir_all, remove_file, rename, write, File},
+    path::{Path, PathBuf},
+};
+use tokio::{sync::OwnedMutexGuard, task::spawn_blocking};
+
+/// Database config entry recording the hash algorithm of the last committed rehash.
+const REHASH_ALGORITHM: &str = "hash";
+
+/// Locations used while switching to a different hash algorithm.
+///
+/// The new data store and config are prepared next to the current ones, and only swapped in
+/// once the database transaction replacing the hashes has been committed.
+#[derive(Debug, Clone)]
+struct RehashPaths {
+    data: PathBuf,
+    staging: PathBuf,
+    previous: PathBuf,
+    config: PathBuf,
+    pending: PathBuf,
+    thumbs: PathBuf,
+    hls: PathBuf,
+}
+
+pub(super) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(".");
+    path.push(suffix);
+    path.into()
+}
+
+impl RehashPaths {
+    fn new(folder: &Path, config_path: &Path, config: &Config) -> Self {
+        let data = folder.join(&config.data.path);
+        Self {
+            staging: with_suffix(&data, "rehash"),
+            previous: with_suffix(&data, "old"),
+            data,
+            config: config_path.into(),
+            pending: with_suffix(config_path, "rehash"),
+            thumbs: folder.join(&config.thumbs.path),
+            hls: folder.join(&config.hls.path),
+        }
+    }
+
+    /// Swap in the new data store and config, safe to run again if it was interrupted.
+    fn finish(&self) -> Result<()> {
+        if self.staging.exists() {
+            if self.data.exists() {
+                if self.previous.exists() {
+                    remove_dir_all(&self.previous)?;
+                }
+                rename(&self.data, &self.previous)?;
+            }
+            rename(&self.staging, &self.data)?;
+        }
+        if self.pending.exists() {
+            rename(&self.pending, &self.config)?;
+        }
+        if self.previous.exists() {
+            remove_dir_all(&self.previous)?;
+        }
+
+        // thumbnails and segments are named by hash, they are generated again when needed
+        if self.thumbs.exists() {
+            remove_dir_all(&self.thumbs)?;
+        }
+        create_dir_all(&self.thumbs)?;
+        if self.hls.exists() {
+            remove_dir_all(&self.hls)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the new data store and config of a rehash that was not committed.
+    fn discard(&self) -> Result<()> {
+        if self.staging.exists() {
+            remove_dir_all(&self.staging)?;
+        }
+        if self.pending.exists() {
+            remove_file(&self.pending)?;
+        }
+        Ok(())
+    }
+}
+
+/// Finish or discard a rehash that was interrupted, depending on whether its database
+/// transaction was committed.
+pub async fn rehash_recover(folder: &Path, config_path: &Path) -> Result<()> {
+    let pending = with_suffix(config_path, "rehash");
+    if !tokio::fs::try_exists(&pending).await? {
+        return Ok(());
+    }
+    let config: Config = toml::from_str(&tokio::fs::read_to_string(&pending).await?)?;
+    let paths = RehashPaths::new(folder, config_path, &config);
+    let database: Database = Connection::open(folder.join(&config.index.path))?.into();
+    spawn_blocking(move || {
+        let committed = database.config_get(REHASH_ALGORITHM)?;
+        if committed.as_deref() == Some(config.data.hash.name()) {
+            paths.finish().context("Finishing interrupted rehash")
+        } else {
+            paths.discard().context("Discarding interrupted rehash")
+        }
+    })
+    .await?
+}
+
+impl Cindy {
+    pub async fn command_rehash(&self, command: &RehashCommand) -> Result<()> {
+        let job = self.jobs().create("rehash");
+        job_render(&job, self.rehash(&command.algorithm, &job)).await
+    }
+
+    /// Recompute the hashes of all files using a different algorithm, keeping their tags and
+    /// labels.
+    ///
+    /// Either everything switches to the new algorithm or nothing does. This instance keeps
+    /// using the previous configuration, so the project needs to be loaded again afterwards.
+    pub async fn rehash(&self, algorithm: &HashAlgorithm, job: &JobHandle) -> Result<()> {
+        if algorithm == &self.config().data.hash {
+            bail!("Project already uses {algorithm}");
+        }
+        let mut config = (**self.config()).clone();
+        config.data.hash = algorithm.clone();
+        let paths = RehashPaths::new(&self.cindy_folder(), &self.config_path(), &config);
+
+        // holding the writer connection keeps files from being added in the meantime
+        let database = self.database().await;
+        let result = self.rehash_commit(database, &paths, &config, job).await;
+        spawn_blocking(move || match result {
+            Ok(()) => paths.finish(),
+            Err(error) => paths.discard().and(Err(error)),
+        })
+        .await?
+    }
+
+    /// Hash all files, prepare the new data store and replace the hashes in the database.
+    async fn rehash_commit(
+        &self,
+        database: OwnedMutexGuard<Database>,
+        paths: &RehashPaths,
+        config: &Config,
+        job: &JobHandle,
+    ) -> Result<()> {
+        let (mut database, files) = spawn_blocking(move || {
+            let files = database.query_hashes(&mut [].iter());
+            (database, files)
+        })
+        .await?;
+        let hashes = self.rehash_files(files?, &config.data.hash, job).await?;
+        self.rehash_link(paths, config, &hashes, job).await?;
+
+        job.phase("committing", 0);
+        job.check()?;
+        let algorithm = config.data.hash.clone();
+        spawn_blocking(move || {
+            let transaction = database.transaction()?;
+            transaction.hash_replace(&hashes)?;
+            transaction.journal_hash_replace(&hashes)?;
+            transaction.config_set(REHASH_ALGORITHM, algorithm.name())?;
+            transaction.commit()?;
+            Ok(()) as Result<()>
+        })
+        .await?
+    }
+
+    /// Hash files with the new algorithm, returning a map of old to new hashes.
+    async fn rehash_files(
+        &self,
+        files: BTreeSet<BoxHash>,
+        algorithm: &HashAlgorithm,
+        job: &JobHandle,
+    ) -> Result<BTreeMap<BoxHash, BoxHash>> {
+        job.phase("hashing", files.len() as u64);
+        let mut results = stream::iter(files)
+            .map(|hash| async move {
+                let path = self
+                    .file_path(&hash)
+                    .await?
+                    .with_context(|| format!("No file found for {hash}"))?;
+                let algorithm = algorithm.clone();
+                let job = job.clone();
+                spawn_blocking(move || {
+                    job.check()?;
+                    let mut file =
+                        File::open(&path).with_context(|| format!("Opening {}", path.display()))?;
+                    let new = algorithm.hash_read(&mut file)?;
+                    Ok((hash, new)) as Result<_>
+                })
+                .await?
+            })
+            .buffer_unordered(self.threads());
+
+        let mut hashes = BTreeMap::new();
+        while let Some(result) = results.next().await {
+            let (old, new) = result?;
+            hashes.insert(old, new);
+            job.progress(hashes.len() as u64);
+        }
+
+        let unique: BTreeSet<&BoxHash> = hashes.values().collect();
+        if unique.len() != hashes.len() {
+            bail!("Files have the same hash using {algorithm}");
+        }
+        Ok(hashes)
+    }
+
+    /// Prepare the new data store and config next to the current ones.
+    async fn rehash_link(
+        &self,
+        paths: &RehashPaths,
+        config: &Config,
+        hashes: &BTreeMap<BoxHash, BoxHash>,
+        job: &JobHandle,
+    ) -> Result<()> {
+        job.phase("linking", hashes.len() as u64);
+        let cindy = self.clone();
+        let (paths, config, hashes, job) =
+            (paths.clone(), config.clone(), hashes.clone(), job.clone());
+        spawn_blocking(move || {
+            // written first, so that an interrupted rehash is cleaned up on the next load
+            write(&paths.pending, toml::to_string(&config)?)?;
+            if paths.staging.exists() {
+                remove_dir_all(&paths.staging)?;
+            }
+
+            // files are read from where they were added, so there is no data store to prepare
+            if config.data.mode == DataMode::Reference {
+                return Ok(());
+            }
+
+            let layout = DataConfig {
+                path: PathBuf::new(),
+                ..config.data.clone()
+            };
+            for (index, (old, new)) in hashes.iter().enumerate() {
+                job.check()?;
+                let target = paths.staging.join(layout.data_path(new));
+                create_dir_all(target.parent().unwrap())?;
+                hard_link(cindy.hash_path(old), &target)
+                    .with_context(|| format!("Linking {old} to {}", target.display()))?;
+                job.progress(index as u64 + 1);
+            }
+            Ok(()) as Result<()>
+        })
+        .await?
+    }
+}
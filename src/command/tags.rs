@@ -1,6 +1,7 @@
 use crate::{
     cli::{TagsCommand, TagsCreateCommand, TagsDeleteCommand, TagsListCommand, TagsRenameCommand},
-    common::tag::TagValueInfo,
+    common::{tag::TagValueInfo, BatchOperation, Origin},
+    history::Journal,
     tag::Tag,
     Cindy,
 };
@@ -25,13 +26,23 @@ impl Cindy {
     }
 
     pub async fn command_tags_create(&self, command: &TagsCreateCommand) -> Result<()> {
-        let database = self.database().await;
+        let mut database = self.database().await;
         let command = command.clone();
         tokio::task::spawn_blocking(move || {
+            let transaction = database.transaction()?;
+            let mut journal = Journal::new(&transaction, Origin::Cli);
             for tag in command.tags {
-                database.tag_name_create(tag.name(), None)?;
-                database.tag_value_create(tag.name(), tag.value())?;
+                journal.apply(&BatchOperation::TagNameCreate {
+                    name: tag.name().into(),
+                    display: None,
+                })?;
+                journal.apply(&BatchOperation::TagValueCreate {
+                    name: tag.name().into(),
+                    value: tag.value().into(),
+                    display: None,
+                })?;
             }
+            transaction.commit()?;
             Ok(()) as Result<()>
         })
         .await??;
@@ -39,7 +50,7 @@ impl Cindy {
     }
 
     pub async fn command_tags_list(&self, command: &TagsListCommand) -> Result<()> {
-        let database = self.database().await;
+        let database = self.database_read().await?;
         let command = command.clone();
         tokio::task::spawn_blocking(move || {
             let tags = if command.tags.is_empty() {
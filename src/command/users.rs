@@ -0,0 +1,109 @@
+use crate::{
+    auth::{password_hash, token_generate, token_hash},
+    cli::{
+        UsersAddCommand, UsersCommand, UsersListCommand, UsersPasswordCommand, UsersRemoveCommand,
+        UsersTokenCommand,
+    },
+    Cindy,
+};
+use anyhow::{Context, Result};
+use std::io::stdin;
+
+/// Read a password from standard input, an empty line means no password.
+fn read_password(password: &Option<String>) -> Result<Option<String>> {
+    let password = match password {
+        Some(password) => password.clone(),
+        None => {
+            eprintln!("Password:");
+            let mut line = String::new();
+            stdin().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if password.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(password_hash(&password)?))
+}
+
+impl Cindy {
+    pub async fn command_users(&self, command: &UsersCommand) -> Result<()> {
+        match command {
+            UsersCommand::Add(command) => self.command_users_add(command).await,
+            UsersCommand::Password(command) => self.command_users_password(command).await,
+            UsersCommand::Remove(command) => self.command_users_remove(command).await,
+            UsersCommand::List(command) => self.command_users_list(command).await,
+            UsersCommand::Token(command) => self.command_users_token(command).await,
+        }
+    }
+
+    pub async fn command_users_add(&self, command: &UsersAddCommand) -> Result<()> {
+        let password = read_password(&command.password)?;
+        let database = self.database().await;
+        let command = command.clone();
+        tokio::task::spawn_blocking(move || {
+            database
+                .user_create(&command.name, password.as_deref(), command.role)
+                .with_context(|| format!("Creating user {}", command.name))
+        })
+        .await??;
+        Ok(())
+    }
+
+    pub async fn command_users_password(&self, command: &UsersPasswordCommand) -> Result<()> {
+        let password = read_password(&command.password)?;
+        let database = self.database().await;
+        let command = command.clone();
+        tokio::task::spawn_blocking(move || {
+            database
+                .user_password_set(&command.name, password.as_deref())
+                .with_context(|| format!("Changing password of user {}", command.name))
+        })
+        .await??;
+        Ok(())
+    }
+
+    pub async fn command_users_remove(&self, command: &UsersRemoveCommand) -> Result<()> {
+        let database = self.database().await;
+        let command = command.clone();
+        tokio::task::spawn_blocking(move || {
+            database
+                .user_delete(&command.name)
+                .with_context(|| format!("Deleting user {}", command.name))
+        })
+        .await??;
+        Ok(())
+    }
+
+    pub async fn command_users_list(&self, _command: &UsersListCommand) -> Result<()> {
+        let database = self.database_read().await?;
+        let users = tokio::task::spawn_blocking(move || database.user_list()).await??;
+        for user in users {
+            println!("{} {}", user.name, user.role);
+        }
+        Ok(())
+    }
+
+    pub async fn command_users_token(&self, command: &UsersTokenCommand) -> Result<()> {
+        let token = self
+            .user_token_create(&command.name, &command.label)
+            .await?;
+        println!("{token}");
+        Ok(())
+    }
+
+    /// Create an API token for a user, which does not expire.
+    pub async fn user_token_create(&self, user: &str, label: &str) -> Result<String> {
+        let token = token_generate();
+        let hash = token_hash(&token);
+        let database = self.database().await;
+        let (user, label) = (user.to_string(), label.to_string());
+        tokio::task::spawn_blocking(move || {
+            database
+                .user_token_add(&user, &hash, &label, None)
+                .with_context(|| format!("Creating token for user {user}"))
+        })
+        .await??;
+        Ok(token)
+    }
+}
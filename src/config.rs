@@ -1,12 +1,21 @@
 use crate::hash::Hash;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf, str::FromStr};
+use thiserror::Error;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct Config {
     pub index: IndexConfig,
     pub thumbs: ThumbsConfig,
     pub data: DataConfig,
+    #[serde(default)]
+    pub transcode: TranscodeConfig,
+    #[serde(default)]
+    pub hls: HlsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -14,11 +23,36 @@ pub struct DataConfig {
     pub path: PathBuf,
     pub hash: HashAlgorithm,
     pub prefix: Vec<u8>,
+    /// How added files are placed into the data store.
+    #[serde(default)]
+    pub mode: DataMode,
+}
+
+/// How added files are placed into the data store.
+///
+/// Changing this only affects files added afterwards.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DataMode {
+    /// Reflink files where the filesystem supports it and hard link them otherwise.
+    #[default]
+    Auto,
+    /// Share the contents with a reflink, on filesystems that support it.
+    Reflink,
+    /// Hard link files, which requires the project and data store to be on the same filesystem.
+    Hardlink,
+    /// Copy files, checking that the copy has the expected hash.
+    Copy,
+    /// Leave files where they are and read them from the paths they were added from.
+    Reference,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct IndexConfig {
     pub path: PathBuf,
+    /// Maximum number of read-only connections, used to answer queries concurrently.
+    pub readers: usize,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -26,18 +60,110 @@ pub struct ThumbsConfig {
     pub path: PathBuf,
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TranscodeConfig {
+    /// Maximum number of videos being transcoded at the same time, at least one.
+    pub concurrency: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct HlsConfig {
+    /// Where to cache segments.
+    pub path: PathBuf,
+    /// Heights of the renditions to offer, ones larger than the video are left out.
+    pub renditions: Vec<u32>,
+    /// Length of segments, in seconds.
+    pub segment: u64,
+    /// Maximum size of cached segments in bytes, least recently used ones are removed first.
+    pub cache_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Require users to log in, otherwise anyone who can reach the server can make changes.
+    pub enabled: bool,
+    /// How long sessions last after logging in, in seconds.
+    pub session: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// Largest file that can be uploaded through the web interface, in bytes.
+    pub size_limit: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum HashAlgorithm {
     #[default]
     Blake2b512,
     Blake2s256,
+    /// Hashes large files using multiple threads.
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake2b512 => "blake2b512",
+            HashAlgorithm::Blake2s256 => "blake2s256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+impl DataMode {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            DataMode::Auto => "auto",
+            DataMode::Reflink => "reflink",
+            DataMode::Hardlink => "hardlink",
+            DataMode::Copy => "copy",
+            DataMode::Reference => "reference",
+        }
+    }
+}
+
+impl fmt::Display for DataMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown hash algorithm {0:?}, expected blake2b512, blake2s256, blake3 or sha256")]
+pub struct HashAlgorithmParseError(String);
+
+impl FromStr for HashAlgorithm {
+    type Err = HashAlgorithmParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "blake2b512" => Ok(HashAlgorithm::Blake2b512),
+            "blake2s256" => Ok(HashAlgorithm::Blake2s256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(HashAlgorithmParseError(input.into())),
+        }
+    }
 }
 
 impl Default for IndexConfig {
     fn default() -> Self {
         Self {
             path: "index.db".into(),
+            readers: 4,
         }
     }
 }
@@ -48,6 +174,7 @@ impl Default for DataConfig {
             path: "data".into(),
             hash: Default::default(),
             prefix: vec![2, 2],
+            mode: Default::default(),
         }
     }
 }
@@ -75,6 +202,40 @@ impl Default for ThumbsConfig {
     }
 }
 
+impl Default for TranscodeConfig {
+    fn default() -> Self {
+        Self { concurrency: 2 }
+    }
+}
+
+impl Default for HlsConfig {
+    fn default() -> Self {
+        Self {
+            path: "hls".into(),
+            renditions: vec![360, 720, 1080],
+            segment: 6,
+            cache_size: 4 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            session: 30 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            size_limit: 16 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -122,6 +283,113 @@ path = "thumbs"
         let _config: Config = toml::from_str(config_str).unwrap();
     }
 
+    #[test]
+    fn hash_algorithm_name() {
+        for algorithm in [
+            HashAlgorithm::Blake2b512,
+            HashAlgorithm::Blake2s256,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Sha256,
+        ] {
+            assert_eq!(algorithm.name().parse(), Ok(algorithm.clone()));
+            let serialized = serde_json::to_string(&algorithm).unwrap();
+            assert_eq!(serialized, format!("{:?}", algorithm.name()));
+        }
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_parse_transcode() {
+        let config_str = r#"
+[data]
+path = "data"
+hash = "blake2b512"
+prefix = [2, 2]
+
+[index]
+path = "index.db"
+
+[thumbs]
+path = "thumbs"
+
+[transcode]
+concurrency = 4
+        "#;
+        let config: Config = toml::from_str(config_str).unwrap();
+        assert_eq!(config.transcode.concurrency, 4);
+    }
+
+    #[test]
+    fn test_parse_data_mode() {
+        let config_str = r#"
+[data]
+path = "data"
+hash = "blake2b512"
+prefix = [2, 2]
+mode = "reference"
+
+[index]
+path = "index.db"
+
+[thumbs]
+path = "thumbs"
+        "#;
+        let config: Config = toml::from_str(config_str).unwrap();
+        assert_eq!(config.data.mode, DataMode::Reference);
+
+        // projects created before it was configurable keep trying reflinks before hard links
+        let config: Config =
+            toml::from_str(&config_str.replace("mode = \"reference\"", "")).unwrap();
+        assert_eq!(config.data.mode, DataMode::Auto);
+    }
+
+    #[test]
+    fn test_parse_hls() {
+        let config_str = r#"
+[data]
+path = "data"
+hash = "blake2b512"
+prefix = [2, 2]
+
+[index]
+path = "index.db"
+
+[thumbs]
+path = "thumbs"
+
+[hls]
+renditions = [480]
+cache_size = 1000000
+        "#;
+        let config: Config = toml::from_str(config_str).unwrap();
+        assert_eq!(config.hls.renditions, [480]);
+        assert_eq!(config.hls.cache_size, 1000000);
+        assert_eq!(config.hls.segment, HlsConfig::default().segment);
+        assert_eq!(config.hls.path, HlsConfig::default().path);
+    }
+
+    #[test]
+    fn test_parse_auth() {
+        let config_str = r#"
+[data]
+path = "data"
+hash = "blake2b512"
+prefix = [2, 2]
+
+[index]
+path = "index.db"
+
+[thumbs]
+path = "thumbs"
+
+[auth]
+enabled = true
+        "#;
+        let config: Config = toml::from_str(config_str).unwrap();
+        assert!(config.auth.enabled);
+        assert_eq!(config.auth.session, AuthConfig::default().session);
+    }
+
     #[test]
     fn test_data_path() {
         let data = DataConfig::default();
@@ -2,16 +2,35 @@ use crate::{
     hash::{BoxHash, Hash},
     tag::Tag,
 };
-use anyhow::Result;
-use rusqlite::{Connection, Error, Transaction};
+use anyhow::{bail, Result};
+use rusqlite::{Connection, Error, OptionalExtension, Transaction};
 use std::{collections::BTreeSet, ops::Deref};
 
-pub const SQLITE_SCHEMA: &str = include_str!("database/schema.sql");
+/// Schema migrations, applying the migration at index `n` brings the database to version `n + 1`.
+pub const MIGRATIONS: &[&str] = &[
+    include_str!("database/migrations/0001_initial.sql"),
+    include_str!("database/migrations/0002_attributes.sql"),
+    include_str!("database/migrations/0003_mime.sql"),
+    include_str!("database/migrations/0004_users.sql"),
+    include_str!("database/migrations/0005_journal.sql"),
+    include_str!("database/migrations/0006_file_stats.sql"),
+    include_str!("database/migrations/0007_file_verified.sql"),
+    include_str!("database/migrations/0008_file_phash.sql"),
+    include_str!("database/migrations/0009_file_fingerprints.sql"),
+    include_str!("database/migrations/0010_mime_pending.sql"),
+    include_str!("database/migrations/0011_similar_version.sql"),
+];
+
+/// Name of the config entry holding the schema version.
+const SCHEMA_VERSION: &str = "version";
 
 mod handlers;
+mod pool;
 #[cfg(test)]
 mod tests;
 
+pub use pool::{Pool, PoolGuard};
+
 /// Handle to database.
 pub trait Handle {
     /// Provide a connection to the database.
@@ -66,6 +85,97 @@ impl<T: Handle> Deref for Database<T> {
     }
 }
 
+impl<T: Handle> Database<T> {
+    /// Bring the database schema up to date, applying each pending migration in order.
+    ///
+    /// Fails if the database was created by a newer version with migrations this one does
+    /// not know about.
+    pub fn migrate(&self) -> Result<()> {
+        // does nothing inside of a transaction, so it needs to be enabled on open
+        self.execute_batch("PRAGMA foreign_keys=ON")?;
+
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() {
+            bail!(
+                "Database has schema version {version}, but only up to {} is supported",
+                MIGRATIONS.len()
+            );
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            self.execute_batch("SAVEPOINT migrate")?;
+            let result = self.execute_batch(migration).and_then(|()| {
+                self.execute(
+                    "INSERT OR REPLACE INTO config(name, value) VALUES (?, ?)",
+                    (SCHEMA_VERSION, index + 1),
+                )
+            });
+            if let Err(error) = result {
+                self.execute_batch("ROLLBACK TO migrate; RELEASE migrate")?;
+                return Err(anyhow::Error::from(error).context(format!(
+                    "Failed to migrate database to schema version {}",
+                    index + 1
+                )));
+            }
+            self.execute_batch("RELEASE migrate")?;
+        }
+
+        Ok(())
+    }
+
+    /// Switch to write-ahead logging, which lets readers on other connections keep going while
+    /// this one is writing.
+    pub fn wal(&self) -> Result<()> {
+        let mode: String =
+            self.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            bail!("Database does not support WAL mode, using {mode}");
+        }
+        Ok(())
+    }
+
+    /// Current schema version of the database, zero if it is empty.
+    pub fn schema_version(&self) -> Result<usize> {
+        if !self.table_exists("config")? {
+            return Ok(0);
+        }
+        let version: Option<usize> = self
+            .query_row(
+                "SELECT value FROM config WHERE name = ?",
+                [SCHEMA_VERSION],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match version {
+            Some(version) => Ok(version),
+            None => self.schema_version_legacy(),
+        }
+    }
+
+    /// Schema version of databases created before it was recorded, determined by the newest
+    /// table present.
+    ///
+    /// Version 3 only added a system tag with `INSERT OR IGNORE`, so it is safe to treat those
+    /// databases as version 2 and run it again.
+    fn schema_version_legacy(&self) -> Result<usize> {
+        for (version, table) in [(5, "journal"), (4, "users"), (2, "file_attributes")] {
+            if self.table_exists(table)? {
+                return Ok(version);
+            }
+        }
+        Ok(1)
+    }
+
+    fn table_exists(&self, name: &str) -> Result<bool> {
+        let exists = self.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+            [name],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+}
+
 #[test]
 fn test_transaction() {
     let mut connection: Database = Connection::open_in_memory().unwrap().into();
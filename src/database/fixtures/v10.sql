@@ -0,0 +1,320 @@
+-- database created by cindy at schema version 10.
+PRAGMA foreign_keys=ON;
+
+-- config, used to store version and hash algorithm.
+CREATE TABLE IF NOT EXISTS config(
+    name TEXT NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY (name)
+);
+
+INSERT INTO config(name, value) VALUES ('version', 10);
+
+-- files, stored by hash
+CREATE TABLE IF NOT EXISTS files(
+    id INTEGER NOT NULL,
+    hash BLOB NOT NULL,
+    PRIMARY KEY(id),
+    UNIQUE (hash)
+);
+
+CREATE TABLE IF NOT EXISTS tag_names(
+    id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    system INTEGER NOT NULL DEFAULT 0,
+    display TEXT,
+    PRIMARY KEY(id),
+    UNIQUE (name)
+);
+
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('ancestor', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('directory', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('duration', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('durationgroup', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('filename', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('filesize', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('format', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('height', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('media', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('mime', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('path', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('resolution', true);
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('width', true);
+
+CREATE TABLE IF NOT EXISTS tag_values(
+    id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL REFERENCES tag_names(id),
+    value TEXT NOT NULL,
+    display TEXT,
+    PRIMARY KEY (id),
+    UNIQUE (tag_id, value)
+);
+
+CREATE INDEX tag_values_by_tag ON tag_values(tag_id);
+
+CREATE VIEW IF NOT EXISTS tags AS
+    SELECT
+        tag_names.id as name_id,
+        tag_names.name as name,
+        tag_names.system as system,
+        tag_names.display as name_display,
+        tag_values.id as value_id,
+        tag_values.value as value,
+        tag_values.display as value_display
+    FROM tag_values
+    JOIN tag_names ON tag_names.id = tag_values.tag_id;
+
+CREATE TRIGGER tags_insert
+INSTEAD OF INSERT ON tags
+BEGIN
+    -- create tag value
+    INSERT INTO tag_values(tag_id, value)
+        VALUES (
+            (SELECT id FROM tag_names WHERE name = NEW.name),
+            NEW.value
+        );
+END;
+
+CREATE TRIGGER tags_delete
+INSTEAD OF DELETE ON tags
+FOR EACH ROW
+BEGIN
+    -- delete tag value
+    DELETE FROM tag_values
+        WHERE tag_id = OLD.name_id
+        AND value = OLD.value
+        AND OLD.system = 0;
+
+    -- delete tag name if no tag values left
+    DELETE FROM tag_names
+    WHERE (
+        SELECT count(id)
+            FROM tag_values
+            WHERE tag_id = tag_names.id
+        ) = 0
+        AND system = 0;
+END;
+
+CREATE TABLE IF NOT EXISTS file_tag_values(
+    id INTEGER NOT NULL PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    tag_value_id INTEGER NOT NULL REFERENCES tag_values(id),
+    UNIQUE (file_id, tag_value_id)
+);
+
+CREATE INDEX file_tag_values_by_file ON file_tag_values(file_id);
+CREATE INDEX file_tag_values_by_tag_value ON file_tag_values(tag_value_id);
+
+CREATE VIEW IF NOT EXISTS file_tags AS
+    SELECT
+        files.id as file_id,
+        files.hash as hash,
+        tags.name_id as name_id,
+        tags.name as name,
+        tags.name_display as name_display,
+        tags.system as system,
+        tags.value_id as value_id,
+        tags.value as value,
+        tags.value_display as value_display,
+        file_tag_values.id as id
+    FROM file_tag_values
+    JOIN files ON file_tag_values.file_id = files.id
+    JOIN tags ON file_tag_values.tag_value_id = tags.value_id;
+
+CREATE TRIGGER file_tags_insert
+INSTEAD OF INSERT ON file_tags
+BEGIN
+    INSERT INTO file_tag_values(file_id, tag_value_id)
+    VALUES (
+        (SELECT id FROM files WHERE hash = NEW.hash),
+        (SELECT value_id FROM tags WHERE name = NEW.name AND value = NEW.value)
+    );
+END;
+
+CREATE TRIGGER file_tags_delete
+INSTEAD OF DELETE ON file_tags
+FOR EACH ROW
+BEGIN
+    DELETE FROM file_tag_values
+    WHERE file_id = OLD.file_id
+    AND tag_value_id = OLD.value_id;
+END;
+
+CREATE TABLE IF NOT EXISTS label_rectangles(
+    id INTEGER NOT NULL PRIMARY KEY,
+    file_tag_value_id INTEGER NOT NULL REFERENCES file_tag_values(id),
+    x1 INTEGER NOT NULL,
+    y1 INTEGER NOT NULL,
+    x2 INTEGER NOT NULL,
+    y2 INTEGER NOT NULL,
+    CHECK (x1 <= x2),
+    CHECK (y1 <= y2),
+    UNIQUE (file_tag_value_id, x1, y1, x2, y2)
+);
+
+CREATE TABLE IF NOT EXISTS label_sequences(
+    id INTEGER NOT NULL PRIMARY KEY,
+    file_tag_value_id INTEGER NOT NULL REFERENCES file_tag_values(id),
+    t1 INTEGER NOT NULL,
+    t2 INTEGER NOT NULL,
+    CHECK (t1 <= t2),
+    UNIQUE (file_tag_value_id, t1, t2)
+);
+
+CREATE VIEW IF NOT EXISTS labels AS
+    SELECT
+        id,
+        file_tag_value_id,
+        x1,
+        y1,
+        x2,
+        y2,
+        null as t1,
+        null as t2,
+        'rectangle' as kind
+    FROM label_rectangles
+    UNION
+    SELECT
+        id,
+        file_tag_value_id,
+        null as x1,
+        null as y1,
+        null as x2,
+        null as y2,
+        t1,
+        t2,
+        'sequence' as kind
+    FROM label_sequences;
+
+CREATE VIEW IF NOT EXISTS file_labels AS
+    SELECT
+        labels.*,
+        file_tags.*
+    FROM labels
+    JOIN file_tags ON labels.file_tag_value_id = file_tags.id;
+
+-- free-form attributes of files.
+CREATE TABLE IF NOT EXISTS file_attributes(
+    id INTEGER NOT NULL PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    UNIQUE (file_id, name)
+);
+
+-- free-form attributes of labels, the kind determines which table the label id refers to.
+CREATE TABLE IF NOT EXISTS label_attributes(
+    id INTEGER NOT NULL PRIMARY KEY,
+    label_kind TEXT NOT NULL,
+    label_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    UNIQUE (label_kind, label_id, name)
+);
+
+CREATE TRIGGER label_rectangles_delete
+AFTER DELETE ON label_rectangles
+FOR EACH ROW
+BEGIN
+    DELETE FROM label_attributes
+    WHERE label_kind = 'rectangle'
+    AND label_id = OLD.id;
+END;
+
+CREATE TRIGGER label_sequences_delete
+AFTER DELETE ON label_sequences
+FOR EACH ROW
+BEGIN
+    DELETE FROM label_attributes
+    WHERE label_kind = 'sequence'
+    AND label_id = OLD.id;
+END;
+
+-- users that can log in to the web interface, passwords are stored as argon2 hashes.
+CREATE TABLE IF NOT EXISTS users(
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    password TEXT,
+    role TEXT NOT NULL,
+    UNIQUE (name)
+);
+
+-- session and API tokens of users, only their hashes are stored. API tokens don't expire.
+CREATE TABLE IF NOT EXISTS user_tokens(
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token BLOB NOT NULL,
+    name TEXT NOT NULL,
+    expires INTEGER,
+    UNIQUE (token)
+);
+
+-- changes to tags and labels, grouped into batches of changes made together.
+CREATE TABLE IF NOT EXISTS journal_batches(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time INTEGER NOT NULL,
+    origin TEXT NOT NULL
+);
+
+-- single changes, operation and inverse are stored as JSON.
+CREATE TABLE IF NOT EXISTS journal(
+    id INTEGER NOT NULL PRIMARY KEY,
+    batch_id INTEGER NOT NULL REFERENCES journal_batches(id) ON DELETE CASCADE,
+    operation TEXT NOT NULL,
+    inverse TEXT NOT NULL,
+    reverted INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX journal_by_batch ON journal(batch_id);
+
+-- size, modification time, inode and device of paths when they were last hashed, so that unchanged
+-- files do not need to be hashed again.
+CREATE TABLE IF NOT EXISTS file_stats(
+    path TEXT NOT NULL PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    size INTEGER NOT NULL,
+    mtime INTEGER NOT NULL,
+    inode INTEGER NOT NULL,
+    device INTEGER NOT NULL
+);
+
+CREATE INDEX file_stats_by_file ON file_stats(file_id);
+
+-- when the data of files was last checked against their hash, as unix timestamp.
+ALTER TABLE files ADD COLUMN verified INTEGER;
+
+-- perceptual hash of images, used to find resized or re-encoded copies of them.
+ALTER TABLE files ADD COLUMN phash INTEGER;
+
+-- perceptual hashes of frames of videos sampled at a fixed interval, stored as big-endian
+-- integers, used to find re-encoded or trimmed copies of them.
+CREATE TABLE IF NOT EXISTS file_fingerprints(
+    file_id INTEGER NOT NULL PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+    frames BLOB NOT NULL
+);
+
+-- files indexed before their MIME type was detected, these are checked once when adding files.
+CREATE TABLE IF NOT EXISTS mime_pending(
+    file_id INTEGER NOT NULL PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE
+);
+
+INSERT INTO mime_pending(file_id)
+    SELECT id FROM files
+    WHERE id NOT IN (SELECT file_id FROM file_tags WHERE name = 'mime');
+
+-- sample data
+INSERT INTO files(hash) VALUES (x'010203');
+INSERT INTO tag_names(name) VALUES ('person');
+INSERT INTO tags(name, value) VALUES ('person', 'alice');
+INSERT INTO file_tags(hash, name, value) VALUES (x'010203', 'person', 'alice');
+INSERT INTO file_attributes(file_id, name, value) VALUES (1, 'camera', 'x100');
+INSERT INTO users(name, password, role) VALUES ('alice', NULL, 'read_write');
+INSERT INTO journal_batches(time, origin) VALUES (0, '{"kind":"cli"}');
+INSERT INTO journal(batch_id, operation, inverse)
+    VALUES (1, '{"op":"tag_name_create","name":"person","display":null}', '[{"op":"tag_name_delete","name":"person"}]');
+INSERT INTO file_stats(path, file_id, size, mtime, inode, device)
+    VALUES ('photos/alice.jpg', 1, 3, 1700000000000000000, 42, 1);
+UPDATE files SET verified = 1700000000 WHERE id = 1;
+UPDATE files SET phash = -2 WHERE id = 1;
+INSERT INTO file_fingerprints(file_id, frames) VALUES (1, x'00000000000000010000000000000002');
+INSERT INTO mime_pending(file_id) VALUES (1);
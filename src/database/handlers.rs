@@ -1,16 +1,81 @@
 use super::*;
-use crate::tag::TagPredicate;
+use crate::{
+    hash::FileStat,
+    tag::{TagFilter, TagPredicate},
+};
 use cindy_common::{
     tag::{TagNameInfo, TagValueInfo},
-    Label, LabelKind, Point, Rectangle, Sequence,
+    Attributes, BatchOperation, Change, ChangeBatch, Duplicate, Label, LabelKind, Origin, Point,
+    Rectangle, Role, Sequence, User,
+};
+use rusqlite::{types::Type, Row, ToSql};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
 };
-use rusqlite::ToSql;
-use std::collections::BTreeMap;
 
 // Database interactions return Sqlite errors.
 type Result<T, E = rusqlite::Error> = std::result::Result<T, E>;
 
+/// Turn a tag filter into an SQLite GLOB pattern, where only `*` acts as wildcard.
+fn glob_pattern(filter: &str) -> String {
+    filter.replace('[', "[[]").replace('?', "[?]")
+}
+
+/// Read a user from a row with `name` and `role` columns.
+fn user_from_row(row: &Row<'_>) -> Result<User> {
+    let role: String = row.get("role")?;
+    let role = role.parse().map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error))
+    })?;
+    Ok(User {
+        name: row.get("name")?,
+        role,
+    })
+}
+
+/// Encode a value as JSON to store it in a column.
+fn json_encode<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value)
+        .map_err(|error| rusqlite::Error::ToSqlConversionFailure(Box::new(error)))
+}
+
+/// Decode a column which stores JSON.
+fn json_decode<T: DeserializeOwned>(row: &Row<'_>, column: &str) -> Result<T> {
+    let text: String = row.get(column)?;
+    serde_json::from_str(&text)
+        .map_err(|error| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(error)))
+}
+
+fn change_from_row(row: &Row<'_>) -> Result<Change> {
+    Ok(Change {
+        id: row.get("id")?,
+        operation: json_decode(row, "operation")?,
+        inverse: json_decode(row, "inverse")?,
+        reverted: row.get("reverted")?,
+    })
+}
+
 impl<T: Handle> Database<T> {
+    /// Get a config entry of the database.
+    pub fn config_get(&self, name: &str) -> Result<Option<String>> {
+        let mut query = self.prepare_cached("SELECT value FROM config WHERE name = ?")?;
+        let mut rows = query.query([name])?;
+        match rows.next()? {
+            Some(row) => row.get("value").map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Set a config entry of the database.
+    pub fn config_set(&self, name: &str, value: &str) -> Result<()> {
+        let mut query =
+            self.prepare_cached("INSERT OR REPLACE INTO config(name, value) VALUES (?, ?)")?;
+        query.execute([name, value])?;
+        Ok(())
+    }
+
     /// Add hash to database.
     pub fn hash_add(&self, hash: &Hash) -> Result<()> {
         let mut query = self.prepare_cached("INSERT OR IGNORE INTO files(hash) VALUES (?)")?;
@@ -25,6 +90,268 @@ impl<T: Handle> Database<T> {
         Ok(())
     }
 
+    /// Replace file hashes, keeping their tags and labels.
+    ///
+    /// New hashes may be the old hashes of other files, all replacements happen at once.
+    pub fn hash_replace(&self, hashes: &BTreeMap<BoxHash, BoxHash>) -> Result<()> {
+        let mut select = self.prepare_cached("SELECT id FROM files WHERE hash = ?")?;
+        let ids = hashes
+            .iter()
+            .map(|(old, new)| Ok((select.query_row([&old[..]], |row| row.get(0))?, new)))
+            .collect::<Result<Vec<(i64, &BoxHash)>>>()?;
+
+        // text never equals a blob, so this frees up the old hashes without conflicts
+        let mut clear =
+            self.prepare_cached("UPDATE files SET hash = CAST(id AS TEXT) WHERE id = ?")?;
+        for (id, _) in &ids {
+            clear.execute([id])?;
+        }
+        let mut update = self.prepare_cached("UPDATE files SET hash = ? WHERE id = ?")?;
+        for (id, new) in &ids {
+            update.execute((&new[..], id))?;
+        }
+        Ok(())
+    }
+
+    /// Get the recorded stat info of a path, along with the hash of the file it had.
+    pub fn file_stat(&self, path: &Path) -> Result<Option<(FileStat, BoxHash)>> {
+        let mut query = self.prepare_cached(
+            "SELECT file_stats.*, files.hash
+            FROM file_stats
+            JOIN files ON files.id = file_stats.file_id
+            WHERE path = ?",
+        )?;
+        let mut rows = query.query([path.to_string_lossy()])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        // stored as signed integers, casting back restores the original value
+        let stat = FileStat {
+            size: row.get::<_, i64>("size")? as u64,
+            mtime: row.get("mtime")?,
+            inode: row.get::<_, i64>("inode")? as u64,
+            device: row.get::<_, i64>("device")? as u64,
+        };
+        let hash = Box::<[u8]>::from(row.get::<_, Vec<u8>>("hash")?).into();
+        Ok(Some((stat, hash)))
+    }
+
+    /// Paths the file with this hash was added from, as recorded with their stat info.
+    pub fn file_stat_paths(&self, hash: &Hash) -> Result<Vec<PathBuf>> {
+        let mut query = self.prepare_cached(
+            "SELECT path
+            FROM file_stats
+            JOIN files ON files.id = file_stats.file_id
+            WHERE hash = ?
+            ORDER BY path",
+        )?;
+        let paths = query
+            .query_map([hash.as_slice()], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<Result<_, _>>()?;
+        Ok(paths)
+    }
+
+    /// Record the stat info of a path which was hashed, does nothing if the hash is not known.
+    pub fn file_stat_set(&self, path: &Path, stat: &FileStat, hash: &Hash) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "INSERT OR REPLACE INTO file_stats(path, file_id, size, mtime, inode, device)
+            SELECT ?, id, ?, ?, ?, ? FROM files WHERE hash = ?",
+        )?;
+        query.execute((
+            path.to_string_lossy(),
+            stat.size as i64,
+            stat.mtime,
+            stat.inode as i64,
+            stat.device as i64,
+            hash.as_slice(),
+        ))?;
+        Ok(())
+    }
+
+    /// Forget the stat info of a path, once the file is no longer there.
+    pub fn file_stat_remove(&self, path: &Path) -> Result<()> {
+        let mut query = self.prepare_cached("DELETE FROM file_stats WHERE path = ?")?;
+        query.execute([path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// All hashes, the ones whose data was verified longest ago or never first.
+    pub fn hashes_by_verified(&self) -> Result<Vec<BoxHash>> {
+        let mut query = self.prepare_cached("SELECT hash FROM files ORDER BY verified, id")?;
+        let hashes = query
+            .query([])?
+            .mapped(|row| row.get::<_, Vec<u8>>(0))
+            .map(|hash| hash.map(|hash| Box::<[u8]>::from(hash).into()))
+            .collect::<Result<_, _>>()?;
+        Ok(hashes)
+    }
+
+    /// Record when the data of a file was verified against its hash.
+    pub fn hash_verified(&self, hash: &Hash, time: i64) -> Result<()> {
+        let mut query = self.prepare_cached("UPDATE files SET verified = ? WHERE hash = ?")?;
+        query.execute((time, hash.as_slice()))?;
+        Ok(())
+    }
+
+    /// Hashes of files indexed before MIME types were detected, which were not checked yet.
+    pub fn hashes_mime_pending(&self) -> Result<Vec<BoxHash>> {
+        let mut query = self.prepare_cached(
+            "SELECT hash FROM mime_pending
+            JOIN files ON files.id = mime_pending.file_id
+            ORDER BY files.id",
+        )?;
+        let hashes = query
+            .query([])?
+            .mapped(|row| row.get::<_, Vec<u8>>(0))
+            .map(|hash| hash.map(|hash| Box::<[u8]>::from(hash).into()))
+            .collect::<Result<_, _>>()?;
+        Ok(hashes)
+    }
+
+    /// Record that the MIME type of a file was checked, whether one was detected or not.
+    pub fn hash_mime_checked(&self, hash: &Hash) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "DELETE FROM mime_pending WHERE file_id = (SELECT id FROM files WHERE hash = ?)",
+        )?;
+        query.execute([hash.as_slice()])?;
+        Ok(())
+    }
+
+    /// Record the perceptual hash of a file.
+    pub fn hash_phash_set(&self, hash: &Hash, phash: u64) -> Result<()> {
+        let mut query = self.prepare_cached("UPDATE files SET phash = ? WHERE hash = ?")?;
+        query.execute((phash as i64, hash.as_slice()))?;
+        Ok(())
+    }
+
+    /// Hashes of all files which have a perceptual hash, along with it.
+    pub fn hash_phashes(&self) -> Result<Vec<(BoxHash, u64)>> {
+        let mut query = self
+            .prepare_cached("SELECT hash, phash FROM files WHERE phash IS NOT NULL ORDER BY id")?;
+        let phashes = query
+            .query([])?
+            .mapped(|row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?)))
+            .map(|row| row.map(|(hash, phash)| (Box::<[u8]>::from(hash).into(), phash as u64)))
+            .collect::<Result<_, _>>()?;
+        Ok(phashes)
+    }
+
+    /// Hashes of images which have no perceptual hash yet, such as ones added before it existed.
+    pub fn hashes_without_phash(&self) -> Result<Vec<BoxHash>> {
+        let mut query = self.prepare_cached(
+            "SELECT hash FROM files
+            WHERE phash IS NULL
+            AND id IN (SELECT file_id FROM file_tags WHERE name = 'media' AND value = 'image')
+            ORDER BY id",
+        )?;
+        let hashes = query
+            .query([])?
+            .mapped(|row| row.get::<_, Vec<u8>>(0))
+            .map(|hash| hash.map(|hash| Box::<[u8]>::from(hash).into()))
+            .collect::<Result<_, _>>()?;
+        Ok(hashes)
+    }
+
+    /// Record the fingerprint of a video.
+    pub fn hash_fingerprint_set(&self, hash: &Hash, frames: &[u64]) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "INSERT OR REPLACE INTO file_fingerprints(file_id, frames)
+            SELECT id, ? FROM files WHERE hash = ?",
+        )?;
+        let frames: Vec<u8> = frames
+            .iter()
+            .flat_map(|frame| frame.to_be_bytes())
+            .collect();
+        query.execute((frames, hash.as_slice()))?;
+        Ok(())
+    }
+
+    /// Hashes of all files which have a fingerprint, along with it.
+    pub fn hash_fingerprints(&self) -> Result<Vec<(BoxHash, Vec<u64>)>> {
+        let mut query = self.prepare_cached(
+            "SELECT files.hash, file_fingerprints.frames
+            FROM file_fingerprints
+            JOIN files ON files.id = file_fingerprints.file_id
+            ORDER BY files.id",
+        )?;
+        let fingerprints = query
+            .query([])?
+            .mapped(|row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)))
+            .map(|row| {
+                row.map(|(hash, frames)| {
+                    let frames = frames
+                        .chunks_exact(8)
+                        .map(|frame| u64::from_be_bytes(frame.try_into().unwrap()))
+                        .collect();
+                    (Box::<[u8]>::from(hash).into(), frames)
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(fingerprints)
+    }
+
+    /// Counter which changes whenever perceptual hashes or fingerprints do.
+    pub fn similar_version(&self) -> Result<u64> {
+        let mut query = self.prepare_cached("SELECT version FROM similar_version")?;
+        let version: i64 = query.query_row([], |row| row.get(0))?;
+        Ok(version as u64)
+    }
+
+    /// Hashes of videos which have no fingerprint yet.
+    pub fn hashes_without_fingerprint(&self) -> Result<Vec<BoxHash>> {
+        let mut query = self.prepare_cached(
+            "SELECT hash FROM files
+            WHERE id NOT IN (SELECT file_id FROM file_fingerprints)
+            AND id IN (SELECT file_id FROM file_tags WHERE name = 'media' AND value = 'video')
+            ORDER BY id",
+        )?;
+        let hashes = query
+            .query([])?
+            .mapped(|row| row.get::<_, Vec<u8>>(0))
+            .map(|hash| hash.map(|hash| Box::<[u8]>::from(hash).into()))
+            .collect::<Result<_, _>>()?;
+        Ok(hashes)
+    }
+
+    /// Files which were added from more than one path, judged by their path tags.
+    pub fn hash_duplicates(&self) -> Result<Vec<Duplicate>> {
+        let mut query = self.prepare_cached(
+            "SELECT hash, value, (
+                SELECT value FROM file_tags AS sizes
+                WHERE sizes.file_id = paths.file_id AND sizes.name = 'filesize'
+            )
+            FROM file_tags AS paths
+            WHERE name = 'path'
+            AND file_id IN (
+                SELECT file_id FROM file_tags
+                WHERE name = 'path'
+                GROUP BY file_id HAVING count(*) > 1
+            )
+            ORDER BY file_id, value",
+        )?;
+        let mut duplicates: Vec<(BoxHash, u64, Vec<PathBuf>)> = vec![];
+        let mut rows = query.query([])?;
+        while let Some(row) = rows.next()? {
+            let hash: BoxHash = Box::<[u8]>::from(row.get::<_, Vec<u8>>(0)?).into();
+            // path tags start with a slash, stat info is recorded without it
+            let value: String = row.get(1)?;
+            let path = PathBuf::from(value.strip_prefix('/').unwrap_or(&value));
+            match duplicates.last_mut() {
+                Some((last, _, paths)) if *last == hash => paths.push(path),
+                _ => {
+                    let size: Option<String> = row.get(2)?;
+                    let size = size.and_then(|size| size.parse().ok()).unwrap_or(0);
+                    duplicates.push((hash, size, vec![path]));
+                }
+            }
+        }
+        Ok(duplicates
+            .into_iter()
+            .map(|(hash, size, paths)| Duplicate::new(hash, size, paths))
+            .collect())
+    }
+
     /// Check if a hash exists.
     pub fn hash_exists(&self, hash: &Hash) -> Result<bool> {
         let mut query = self.prepare_cached("SELECT * FROM files WHERE hash = ?")?;
@@ -61,6 +388,22 @@ impl<T: Handle> Database<T> {
         Ok(())
     }
 
+    /// Determines if a tag name exists, and the value if one is given.
+    pub fn tag_exists(&self, name: &str, value: Option<&str>) -> Result<bool> {
+        let mut query = self.prepare_cached(
+            "SELECT EXISTS(
+                SELECT id FROM tag_names
+                WHERE name = ?1
+                AND (?2 IS NULL OR EXISTS(
+                    SELECT id FROM tag_values
+                    WHERE tag_id = tag_names.id
+                    AND value = ?2
+                ))
+            )",
+        )?;
+        query.query_row((name, value), |row| row.get(0))
+    }
+
     /// List tags in database.
     pub fn tag_list(
         &self,
@@ -119,6 +462,18 @@ impl<T: Handle> Database<T> {
         Ok(())
     }
 
+    /// Delete a tag name, only if it has no values and is not a system tag.
+    pub fn tag_name_delete(&self, name: &str) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "DELETE FROM tag_names
+            WHERE name = ?
+            AND system = 0
+            AND NOT EXISTS (SELECT id FROM tag_values WHERE tag_id = tag_names.id)",
+        )?;
+        query.execute([name])?;
+        Ok(())
+    }
+
     /// List tag names
     pub fn tag_names(&self) -> Result<BTreeMap<String, TagNameInfo>> {
         let mut query = self.prepare_cached(
@@ -203,15 +558,15 @@ impl<T: Handle> Database<T> {
         &self,
         query: &mut dyn Iterator<Item = &TagPredicate<'_>>,
     ) -> Result<BTreeSet<BoxHash>> {
-        let mut params: Vec<Option<&str>> = vec![];
+        let mut params: Vec<Option<String>> = vec![];
         let mut segments = vec![];
         for predicate in query {
             let filter = predicate.filter();
             let segment = "
                 (EXISTS (SELECT file_id FROM file_tags
                     WHERE files.id = file_tags.file_id
-                    AND coalesce(name = ?, true)
-                    AND coalesce(value = ?, true)
+                    AND coalesce(name GLOB ?, true)
+                    AND coalesce(value GLOB ?, true)
                     ))
             "
             .to_string();
@@ -219,8 +574,8 @@ impl<T: Handle> Database<T> {
                 TagPredicate::Missing(_) => format!("(NOT {segment})"),
                 _other => segment,
             };
-            params.push(filter.name());
-            params.push(filter.value());
+            params.push(filter.name().map(glob_pattern));
+            params.push(filter.value().map(glob_pattern));
             segments.push(segment);
         }
         let query_string = match segments.len() {
@@ -404,24 +759,47 @@ impl<T: Handle> Database<T> {
         name: Option<&str>,
         value: Option<&str>,
         kind: Option<LabelKind>,
-    ) -> Result<BTreeSet<(Tag, Label)>> {
+        attribute: Option<&TagFilter<'_>>,
+    ) -> Result<BTreeSet<(Tag, Label, Attributes)>> {
         let mut query = self.prepare_cached(
-            "SELECT *
-            FROM file_labels
-            WHERE coalesce(hash = ?, true)
-            AND coalesce(name = ?, true)
-            AND coalesce(value = ?, true)
-            AND coalesce(kind = ?, true)",
+            "SELECT
+                labels.id as label_id,
+                labels.kind as kind,
+                labels.x1 as x1,
+                labels.y1 as y1,
+                labels.x2 as x2,
+                labels.y2 as y2,
+                labels.t1 as t1,
+                labels.t2 as t2,
+                file_tags.name as name,
+                file_tags.value as value
+            FROM labels
+            JOIN file_tags ON labels.file_tag_value_id = file_tags.id
+            WHERE coalesce(file_tags.hash = ?, true)
+            AND coalesce(file_tags.name = ?, true)
+            AND coalesce(file_tags.value = ?, true)
+            AND coalesce(labels.kind = ?, true)
+            AND (NOT ? OR EXISTS (
+                SELECT id FROM label_attributes
+                WHERE label_attributes.label_kind = labels.kind
+                AND label_attributes.label_id = labels.id
+                AND coalesce(label_attributes.name = ?, true)
+                AND coalesce(label_attributes.value = ?, true)
+            ))",
         )?;
         let rows = query.query((
             file.map(|f| f.as_slice()),
             name,
             value,
             kind.map(|k| k.name()),
+            attribute.is_some(),
+            attribute.and_then(TagFilter::name),
+            attribute.and_then(TagFilter::value),
         ))?;
         rows.mapped(|row| {
             let tag = Tag::new(row.get("name")?, row.get("value")?);
-            let label = match row.get::<_, String>("kind")? {
+            let kind: String = row.get("kind")?;
+            let label = match &kind {
                 kind if kind == LabelKind::Rectangle.name() => Rectangle {
                     start: Point::new(row.get("x1")?, row.get("y1")?),
                     end: Point::new(row.get("x2")?, row.get("y2")?),
@@ -434,15 +812,392 @@ impl<T: Handle> Database<T> {
                 .into(),
                 _ => unreachable!("encountered unknown label kind"),
             };
-            Ok((tag, label))
+            let attributes = self.label_attributes_by_id(&kind, row.get("label_id")?)?;
+            Ok((tag, label, attributes))
         })
-        .collect::<Result<BTreeSet<(Tag, Label)>, _>>()
-        .map_err(Into::into)
+        .collect::<Result<BTreeSet<(Tag, Label, Attributes)>, _>>()
+    }
+
+    /// Determine the ID of a label, fails if the label does not exist.
+    fn label_id(&self, file: &Hash, name: &str, value: &str, label: &Label) -> Result<i64> {
+        match label {
+            Label::Rectangle(rect) => {
+                let mut query = self.prepare_cached(
+                    "SELECT id FROM label_rectangles
+                    WHERE file_tag_value_id = (SELECT id FROM file_tags WHERE hash = ? AND name = ? AND value = ?)
+                    AND x1 = ?
+                    AND y1 = ?
+                    AND x2 = ?
+                    AND y2 = ?",
+                )?;
+                query.query_row(
+                    (
+                        file.as_slice(),
+                        name,
+                        value,
+                        rect.start.x,
+                        rect.start.y,
+                        rect.end.x,
+                        rect.end.y,
+                    ),
+                    |row| row.get("id"),
+                )
+            }
+            Label::Sequence(seq) => {
+                let mut query = self.prepare_cached(
+                    "SELECT id FROM label_sequences
+                    WHERE file_tag_value_id = (SELECT id FROM file_tags WHERE hash = ? AND name = ? AND value = ?)
+                    AND t1 = ?
+                    AND t2 = ?",
+                )?;
+                query.query_row((file.as_slice(), name, value, seq.start, seq.end), |row| {
+                    row.get("id")
+                })
+            }
+        }
     }
 
-    /// Run migrations on database.
-    pub fn migrate(&self) -> Result<()> {
-        self.execute_batch(SQLITE_SCHEMA)?;
+    /// Get the attributes of a label by its kind and ID, as returned by [`Self::label_id`].
+    fn label_attributes_by_id(&self, kind: &str, id: i64) -> Result<Attributes> {
+        let mut query = self.prepare_cached(
+            "SELECT name, value
+            FROM label_attributes
+            WHERE label_kind = ?
+            AND label_id = ?",
+        )?;
+        let rows = query.query((kind, id))?;
+        rows.mapped(|row| Ok((row.get("name")?, row.get("value")?)))
+            .collect()
+    }
+
+    /// Get the attributes of a label.
+    pub fn label_attributes(
+        &self,
+        file: &Hash,
+        name: &str,
+        value: &str,
+        label: &Label,
+    ) -> Result<Attributes> {
+        let id = self.label_id(file, name, value, label)?;
+        self.label_attributes_by_id(LabelKind::from(label).name(), id)
+    }
+
+    /// Set an attribute of a label, overwriting any previous value.
+    pub fn label_attribute_set(
+        &self,
+        file: &Hash,
+        name: &str,
+        value: &str,
+        label: &Label,
+        attribute: &str,
+        attribute_value: &str,
+    ) -> Result<()> {
+        let id = self.label_id(file, name, value, label)?;
+        let mut query = self.prepare_cached(
+            "INSERT OR REPLACE INTO label_attributes(label_kind, label_id, name, value)
+            VALUES (?, ?, ?, ?)",
+        )?;
+        query.execute((
+            LabelKind::from(label).name(),
+            id,
+            attribute,
+            attribute_value,
+        ))?;
+        Ok(())
+    }
+
+    /// Remove an attribute of a label, or all attributes if none is specified.
+    pub fn label_attribute_remove(
+        &self,
+        file: &Hash,
+        name: &str,
+        value: &str,
+        label: &Label,
+        attribute: Option<&str>,
+    ) -> Result<()> {
+        let id = self.label_id(file, name, value, label)?;
+        let mut query = self.prepare_cached(
+            "DELETE FROM label_attributes
+            WHERE label_kind = ?
+            AND label_id = ?
+            AND coalesce(name = ?, true)",
+        )?;
+        query.execute((LabelKind::from(label).name(), id, attribute))?;
+        Ok(())
+    }
+
+    /// Get the attributes of a file.
+    pub fn file_attributes(&self, file: &Hash) -> Result<Attributes> {
+        let mut query = self.prepare_cached(
+            "SELECT name, value
+            FROM file_attributes
+            WHERE file_id = (SELECT id FROM files WHERE hash = ?)",
+        )?;
+        let rows = query.query([file.as_slice()])?;
+        rows.mapped(|row| Ok((row.get("name")?, row.get("value")?)))
+            .collect()
+    }
+
+    /// Set an attribute of a file, overwriting any previous value.
+    pub fn file_attribute_set(&self, file: &Hash, name: &str, value: &str) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "INSERT OR REPLACE INTO file_attributes(file_id, name, value)
+            VALUES ((SELECT id FROM files WHERE hash = ?), ?, ?)",
+        )?;
+        query.execute((file.as_slice(), name, value))?;
+        Ok(())
+    }
+
+    /// Remove an attribute of a file, or all attributes if none is specified.
+    pub fn file_attribute_remove(&self, file: &Hash, name: Option<&str>) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "DELETE FROM file_attributes
+            WHERE file_id = (SELECT id FROM files WHERE hash = ?)
+            AND coalesce(name = ?, true)",
+        )?;
+        query.execute((file.as_slice(), name))?;
+        Ok(())
+    }
+
+    /// Create a user, the password is the hash of it.
+    pub fn user_create(&self, name: &str, password: Option<&str>, role: Role) -> Result<()> {
+        let mut query =
+            self.prepare_cached("INSERT INTO users(name, password, role) VALUES (?, ?, ?)")?;
+        query.execute((name, password, role.name()))?;
+        Ok(())
+    }
+
+    /// Change the password (hash) of a user.
+    pub fn user_password_set(&self, name: &str, password: Option<&str>) -> Result<()> {
+        let mut query = self.prepare_cached("UPDATE users SET password = ? WHERE name = ?")?;
+        if query.execute((password, name))? == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// Get the password hash of a user, if the user exists and has one.
+    pub fn user_password(&self, name: &str) -> Result<Option<String>> {
+        let mut query = self.prepare_cached("SELECT password FROM users WHERE name = ?")?;
+        let mut rows = query.query([name])?;
+        match rows.next()? {
+            Some(row) => row.get("password"),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete a user, along with all of their tokens.
+    pub fn user_delete(&self, name: &str) -> Result<()> {
+        let mut query = self.prepare_cached("DELETE FROM users WHERE name = ?")?;
+        if query.execute([name])? == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// List all users.
+    pub fn user_list(&self) -> Result<Vec<User>> {
+        let mut query = self.prepare_cached("SELECT name, role FROM users ORDER BY name")?;
+        let rows = query.query([])?;
+        rows.mapped(user_from_row).collect()
+    }
+
+    /// Add a token (by hash) for a user, which expires at the given UNIX timestamp.
+    pub fn user_token_add(
+        &self,
+        user: &str,
+        token: &[u8],
+        name: &str,
+        expires: Option<i64>,
+    ) -> Result<()> {
+        let mut query = self.prepare_cached(
+            "INSERT INTO user_tokens(user_id, token, name, expires)
+            VALUES ((SELECT id FROM users WHERE name = ?), ?, ?, ?)",
+        )?;
+        query.execute((user, token, name, expires))?;
+        Ok(())
+    }
+
+    /// Look up the user a token (by hash) belongs to, if it has not expired at `now`.
+    pub fn user_token_get(&self, token: &[u8], now: i64) -> Result<Option<User>> {
+        let mut query = self.prepare_cached(
+            "SELECT users.name, users.role
+            FROM user_tokens
+            JOIN users ON users.id = user_tokens.user_id
+            WHERE user_tokens.token = ?
+            AND coalesce(user_tokens.expires > ?, true)",
+        )?;
+        let mut rows = query.query((token, now))?;
+        rows.next()?.map(user_from_row).transpose()
+    }
+
+    /// Remove a token (by hash).
+    pub fn user_token_remove(&self, token: &[u8]) -> Result<()> {
+        let mut query = self.prepare_cached("DELETE FROM user_tokens WHERE token = ?")?;
+        query.execute([token])?;
+        Ok(())
+    }
+
+    /// Remove all tokens which expired at `now`.
+    pub fn user_tokens_expire(&self, now: i64) -> Result<()> {
+        let mut query = self.prepare_cached("DELETE FROM user_tokens WHERE expires <= ?")?;
+        query.execute([now])?;
+        Ok(())
+    }
+
+    /// Start a new batch of changes, returning its id.
+    pub fn journal_batch_create(&self, time: i64, origin: &Origin) -> Result<u64> {
+        let mut query =
+            self.prepare_cached("INSERT INTO journal_batches(time, origin) VALUES (?, ?)")?;
+        query.execute((time, json_encode(origin)?))?;
+        Ok(self.last_insert_rowid() as u64)
+    }
+
+    /// Record a change and the operations which undo it.
+    pub fn journal_change_add(
+        &self,
+        batch: u64,
+        operation: &BatchOperation,
+        inverse: &[BatchOperation],
+    ) -> Result<()> {
+        let mut query = self
+            .prepare_cached("INSERT INTO journal(batch_id, operation, inverse) VALUES (?, ?, ?)")?;
+        query.execute((batch, json_encode(operation)?, json_encode(&inverse)?))?;
+        Ok(())
+    }
+
+    /// Mark a change as reverted.
+    pub fn journal_change_revert(&self, id: u64) -> Result<()> {
+        let mut query = self.prepare_cached("UPDATE journal SET reverted = 1 WHERE id = ?")?;
+        query.execute([id])?;
+        Ok(())
+    }
+
+    /// Replace file hashes in all recorded changes.
+    pub fn journal_hash_replace(&self, hashes: &BTreeMap<BoxHash, BoxHash>) -> Result<()> {
+        let replace = |operation: &mut BatchOperation| {
+            if let Some(file) = operation.file_mut() {
+                if let Some(new) = hashes.get(file) {
+                    *file = new.clone();
+                }
+            }
+        };
+        let mut select = self.prepare_cached("SELECT * FROM journal")?;
+        let changes = select
+            .query([])?
+            .mapped(change_from_row)
+            .collect::<Result<Vec<Change>>>()?;
+        let mut update =
+            self.prepare_cached("UPDATE journal SET operation = ?, inverse = ? WHERE id = ?")?;
+        for mut change in changes {
+            replace(&mut change.operation);
+            change.inverse.iter_mut().for_each(&replace);
+            update.execute((
+                json_encode(&change.operation)?,
+                json_encode(&change.inverse)?,
+                change.id,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Get a single change.
+    pub fn journal_change(&self, id: u64) -> Result<Change> {
+        let mut query = self.prepare_cached("SELECT * FROM journal WHERE id = ?")?;
+        query.query_row([id], change_from_row)
+    }
+
+    /// Changes of a batch, in the order they were made.
+    pub fn journal_changes(&self, batch: u64) -> Result<Vec<Change>> {
+        let mut query =
+            self.prepare_cached("SELECT * FROM journal WHERE batch_id = ? ORDER BY id")?;
+        let rows = query.query([batch])?;
+        rows.mapped(change_from_row).collect()
+    }
+
+    /// Get a single batch of changes.
+    pub fn journal_batch(&self, id: u64) -> Result<ChangeBatch> {
+        let mut query = self.prepare_cached("SELECT * FROM journal_batches WHERE id = ?")?;
+        let (time, origin) = query.query_row([id], |row| {
+            Ok((row.get("time")?, json_decode(row, "origin")?))
+        })?;
+        Ok(ChangeBatch {
+            id,
+            time,
+            origin,
+            changes: self.journal_changes(id)?,
+        })
+    }
+
+    /// List the most recent batches of changes, newest first.
+    ///
+    /// Batches whose changes were all reverted are only included if `reverted` is set.
+    pub fn journal_batches(&self, limit: u64, reverted: bool) -> Result<Vec<ChangeBatch>> {
+        let mut query = self.prepare_cached(
+            "SELECT id FROM journal_batches
+            WHERE ? OR EXISTS (
+                SELECT id FROM journal
+                WHERE batch_id = journal_batches.id
+                AND NOT reverted
+            )
+            ORDER BY id DESC
+            LIMIT ?",
+        )?;
+        let ids = query
+            .query((reverted, limit))?
+            .mapped(|row| row.get(0))
+            .collect::<Result<Vec<u64>>>()?;
+        ids.into_iter().map(|id| self.journal_batch(id)).collect()
+    }
+
+    /// Tag values whose tag name no longer exists, as id and value.
+    pub fn dangling_tag_values(&self) -> Result<Vec<(i64, String)>> {
+        let mut query = self.prepare_cached(
+            "SELECT id, value FROM tag_values
+            WHERE id NOT IN (SELECT value_id FROM tags)",
+        )?;
+        let values = query
+            .query([])?
+            .mapped(|row| Ok((row.get(0)?, row.get(1)?)))
+            .collect::<Result<_, _>>()?;
+        Ok(values)
+    }
+
+    /// Tags of files whose file or tag value no longer exists.
+    pub fn dangling_file_tags(&self) -> Result<Vec<i64>> {
+        let mut query = self.prepare_cached(
+            "SELECT id FROM file_tag_values
+            WHERE id NOT IN (SELECT id FROM file_tags)",
+        )?;
+        let ids = query
+            .query([])?
+            .mapped(|row| row.get(0))
+            .collect::<Result<_, _>>()?;
+        Ok(ids)
+    }
+
+    /// Labels whose file tag no longer exists, as kind and id.
+    pub fn dangling_labels(&self) -> Result<Vec<(String, i64)>> {
+        let mut query = self.prepare_cached(
+            "SELECT kind, id FROM labels
+            WHERE file_tag_value_id NOT IN (SELECT id FROM file_tags)",
+        )?;
+        let labels = query
+            .query([])?
+            .mapped(|row| Ok((row.get(0)?, row.get(1)?)))
+            .collect::<Result<_, _>>()?;
+        Ok(labels)
+    }
+
+    /// Remove dangling labels, file tags and tag values, in that order so that nothing refers
+    /// to removed rows.
+    pub fn dangling_remove(&self) -> Result<()> {
+        self.execute_batch(
+            "DELETE FROM label_rectangles WHERE file_tag_value_id NOT IN (SELECT id FROM file_tags);
+            DELETE FROM label_sequences WHERE file_tag_value_id NOT IN (SELECT id FROM file_tags);
+            DELETE FROM file_tag_values WHERE id NOT IN (SELECT id FROM file_tags);
+            DELETE FROM tag_values WHERE id NOT IN (SELECT value_id FROM tags);",
+        )?;
         Ok(())
     }
 }
@@ -1,5 +1,3 @@
-PRAGMA foreign_keys=ON;
-
 -- config, used to store version and hash algorithm.
 CREATE TABLE IF NOT EXISTS config(
     name TEXT NOT NULL,
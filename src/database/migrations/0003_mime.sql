@@ -0,0 +1,2 @@
+-- system tag for the detected MIME type.
+INSERT OR IGNORE INTO tag_names(name, system) VALUES ('mime', true);
@@ -0,0 +1,2 @@
+-- when the data of files was last checked against their hash, as unix timestamp.
+ALTER TABLE files ADD COLUMN verified INTEGER;
@@ -0,0 +1,2 @@
+-- perceptual hash of images, used to find resized or re-encoded copies of them.
+ALTER TABLE files ADD COLUMN phash INTEGER;
@@ -0,0 +1,6 @@
+-- perceptual hashes of frames of videos sampled at a fixed interval, stored as big-endian
+-- integers, used to find re-encoded or trimmed copies of them.
+CREATE TABLE IF NOT EXISTS file_fingerprints(
+    file_id INTEGER NOT NULL PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+    frames BLOB NOT NULL
+);
@@ -0,0 +1,8 @@
+-- files indexed before their MIME type was detected, these are checked once when adding files.
+CREATE TABLE IF NOT EXISTS mime_pending(
+    file_id INTEGER NOT NULL PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE
+);
+
+INSERT INTO mime_pending(file_id)
+    SELECT id FROM files
+    WHERE id NOT IN (SELECT file_id FROM file_tags WHERE name = 'mime');
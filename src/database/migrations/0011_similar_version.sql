@@ -0,0 +1,33 @@
+-- counts changes to perceptual hashes and fingerprints, so that indexes of them only need to be
+-- rebuilt when this changed.
+CREATE TABLE IF NOT EXISTS similar_version(
+    version INTEGER NOT NULL
+);
+
+INSERT INTO similar_version(version) VALUES (0);
+
+CREATE TRIGGER IF NOT EXISTS similar_phash_update AFTER UPDATE OF phash ON files
+BEGIN
+    UPDATE similar_version SET version = version + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS similar_phash_delete AFTER DELETE ON files
+WHEN OLD.phash IS NOT NULL
+BEGIN
+    UPDATE similar_version SET version = version + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS similar_fingerprint_insert AFTER INSERT ON file_fingerprints
+BEGIN
+    UPDATE similar_version SET version = version + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS similar_fingerprint_update AFTER UPDATE ON file_fingerprints
+BEGIN
+    UPDATE similar_version SET version = version + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS similar_fingerprint_delete AFTER DELETE ON file_fingerprints
+BEGIN
+    UPDATE similar_version SET version = version + 1;
+END;
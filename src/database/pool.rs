@@ -0,0 +1,137 @@
+use super::Database;
+use rusqlite::{Connection, Error, OpenFlags};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Pool of read-only connections to a database in WAL mode.
+///
+/// Readers see the database as of the last commit, so they keep working while the writer
+/// connection is in the middle of a transaction. Connections are opened when first needed and
+/// kept around for reuse.
+#[derive(Debug)]
+pub struct Pool {
+    path: PathBuf,
+    idle: Mutex<Vec<Database>>,
+    permits: Arc<Semaphore>,
+}
+
+impl Pool {
+    /// Create a pool of up to `size` connections to the database at `path`.
+    pub fn new(path: &Path, size: usize) -> Self {
+        Self {
+            path: path.into(),
+            idle: Default::default(),
+            permits: Arc::new(Semaphore::new(size.max(1))),
+        }
+    }
+
+    /// Get a connection, waiting if all of them are in use.
+    pub async fn get(self: &Arc<Self>) -> Result<PoolGuard, Error> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("pool semaphore is never closed");
+        let idle = self.idle.lock().unwrap().pop();
+        let database = match idle {
+            Some(database) => database,
+            None => self.open()?,
+        };
+        Ok(PoolGuard {
+            database: Some(database),
+            pool: self.clone(),
+            _permit: permit,
+        })
+    }
+
+    fn open(&self) -> Result<Database, Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI;
+        Connection::open_with_flags(&self.path, flags).map(Database)
+    }
+}
+
+/// Connection borrowed from a [`Pool`], which is returned to it when dropped.
+#[derive(Debug)]
+pub struct PoolGuard {
+    database: Option<Database>,
+    pool: Arc<Pool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for PoolGuard {
+    type Target = Database;
+
+    fn deref(&self) -> &Self::Target {
+        self.database.as_ref().expect("only taken when dropped")
+    }
+}
+
+impl Drop for PoolGuard {
+    fn drop(&mut self) {
+        if let Some(database) = self.database.take() {
+            self.pool.idle.lock().unwrap().push(database);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn can_read_during_write() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("index.db");
+        let mut writer: Database = Connection::open(&path).unwrap().into();
+        writer.wal().unwrap();
+        writer.migrate().unwrap();
+        writer.tag_name_create("person", None).unwrap();
+
+        let pool = Arc::new(Pool::new(&path, 2));
+        let transaction = writer.transaction().unwrap();
+        transaction.tag_name_create("place", None).unwrap();
+
+        // readers are not blocked, and only see what was committed
+        let reader = pool.get().await.unwrap();
+        assert!(reader.tag_exists("person", None).unwrap());
+        assert!(!reader.tag_exists("place", None).unwrap());
+        drop(reader);
+
+        transaction.commit().unwrap();
+        let reader = pool.get().await.unwrap();
+        assert!(reader.tag_exists("place", None).unwrap());
+        assert!(reader.tag_name_create("thing", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn reuses_connections() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("index.db");
+        let writer: Database = Connection::open(&path).unwrap().into();
+        writer.wal().unwrap();
+        writer.migrate().unwrap();
+
+        let pool = Arc::new(Pool::new(&path, 2));
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+
+        // all connections are in use
+        assert!(tokio::time::timeout(Duration::from_millis(10), pool.get())
+            .await
+            .is_err());
+
+        drop(first);
+        drop(second);
+        let _third = pool.get().await.unwrap();
+        assert_eq!(pool.idle.lock().unwrap().len(), 1);
+    }
+}
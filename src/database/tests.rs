@@ -1,12 +1,156 @@
 use super::*;
-use crate::tag::{TagFilter, TagPredicate, TagValueInfo};
-use cindy_common::{Point, Rectangle, Sequence};
+use crate::{
+    hash::FileStat,
+    tag::{TagFilter, TagPredicate, TagValueInfo},
+};
+use cindy_common::{
+    Attributes, BatchOperation, Duplicate, Origin, Point, Rectangle, Role, Sequence, User,
+};
 use proptest::prelude::*;
+use std::path::Path;
+
+/// Databases created by every schema version before the latest, with some data in the tables
+/// each version added.
+const FIXTURES: &[&str] = &[
+    include_str!("fixtures/v1.sql"),
+    include_str!("fixtures/v2.sql"),
+    include_str!("fixtures/v3.sql"),
+    include_str!("fixtures/v4.sql"),
+    include_str!("fixtures/v5.sql"),
+    include_str!("fixtures/v6.sql"),
+    include_str!("fixtures/v7.sql"),
+    include_str!("fixtures/v8.sql"),
+    include_str!("fixtures/v9.sql"),
+    include_str!("fixtures/v10.sql"),
+];
+
+/// Tables, views, triggers and indices of the database.
+fn schema(database: &Database) -> BTreeSet<(String, String, Option<String>)> {
+    let mut query = database
+        .prepare("SELECT type, name, sql FROM sqlite_master")
+        .unwrap();
+    let rows = query
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
 
 #[test]
 fn test_migrate() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    assert_eq!(database.schema_version().unwrap(), 0);
+    database.migrate().unwrap();
+    assert_eq!(database.schema_version().unwrap(), MIGRATIONS.len());
+
+    // running again does nothing
+    database.migrate().unwrap();
+    assert_eq!(database.schema_version().unwrap(), MIGRATIONS.len());
+}
+
+#[test]
+fn can_migrate_fixtures() {
+    let latest = Database(Connection::open_in_memory().unwrap());
+    latest.migrate().unwrap();
+
+    // version 3 databases cannot be told apart from version 2
+    let detected = [1, 2, 2, 4, 5, 6, 7, 8, 9, 10];
+    for (index, fixture) in FIXTURES.iter().enumerate() {
+        let version = index + 1;
+        let database = Database(Connection::open_in_memory().unwrap());
+        database.execute_batch(fixture).unwrap();
+        assert_eq!(database.schema_version().unwrap(), detected[index]);
+
+        database.migrate().unwrap();
+        assert_eq!(database.schema_version().unwrap(), MIGRATIONS.len());
+        assert_eq!(schema(&database), schema(&latest), "fixture v{version}");
+
+        // data survives the upgrade
+        let hash = Hash::new(&[1, 2, 3]);
+        let tags = database.hash_tags(hash, None, None).unwrap();
+        assert_eq!(tags, [Tag::new("person".into(), "alice".into())].into());
+        assert!(database.tag_exists("mime", None).unwrap());
+        if version >= 2 {
+            let attributes = database.file_attributes(hash).unwrap();
+            assert_eq!(attributes, [("camera".into(), "x100".into())].into());
+        }
+        if version >= 4 {
+            let user = User {
+                name: "alice".into(),
+                role: Role::ReadWrite,
+            };
+            assert_eq!(database.user_list().unwrap(), [user]);
+        }
+        if version >= 5 {
+            let batch = database.journal_batch(1).unwrap();
+            assert_eq!(batch.origin, Origin::Cli);
+            assert_eq!(batch.changes.len(), 1);
+        }
+        if version >= 6 {
+            let stat = FileStat {
+                size: 3,
+                mtime: 1_700_000_000_000_000_000,
+                inode: 42,
+                device: 1,
+            };
+            let recorded = database.file_stat(Path::new("photos/alice.jpg")).unwrap();
+            assert_eq!(recorded, Some((stat, BoxHash::from(hash))));
+        }
+        if version >= 7 {
+            let verified: Option<i64> = database
+                .query_row("SELECT verified FROM files WHERE id = 1", [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(verified, Some(1_700_000_000));
+        }
+        if version >= 8 {
+            let phashes = database.hash_phashes().unwrap();
+            assert_eq!(phashes, [(BoxHash::from(hash), u64::MAX - 1)]);
+        }
+        if version >= 9 {
+            let fingerprints = database.hash_fingerprints().unwrap();
+            assert_eq!(fingerprints, [(BoxHash::from(hash), vec![1, 2])]);
+        }
+
+        // files from before MIME types were detected are checked once
+        assert_eq!(
+            database.hashes_mime_pending().unwrap(),
+            [BoxHash::from(hash)]
+        );
+        database.hash_mime_checked(hash).unwrap();
+        assert!(database.hashes_mime_pending().unwrap().is_empty());
+
+        // changes to existing files are counted from the upgrade on
+        assert_eq!(database.similar_version().unwrap(), 0);
+        database.hash_phash_set(hash, 1).unwrap();
+        assert_eq!(database.similar_version().unwrap(), 1);
+    }
+}
+
+#[test]
+fn cannot_migrate_newer() {
     let database = Database(Connection::open_in_memory().unwrap());
     database.migrate().unwrap();
+    database
+        .execute(
+            "UPDATE config SET value = ? WHERE name = 'version'",
+            [MIGRATIONS.len() + 1],
+        )
+        .unwrap();
+    assert!(database.migrate().is_err());
+}
+
+#[test]
+fn failed_migration_is_rolled_back() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.execute_batch(FIXTURES[3]).unwrap();
+    // conflicts with the table created by the next migration
+    database
+        .execute_batch("CREATE VIEW journal AS SELECT 1")
+        .unwrap();
+    assert!(database.migrate().is_err());
+    assert_eq!(database.schema_version().unwrap(), 4);
+    assert!(!database.table_exists("journal_batches").unwrap());
 }
 
 #[test]
@@ -18,6 +162,180 @@ fn can_manage_files() {
     database.hash_remove(&hash).unwrap();
 }
 
+#[test]
+fn can_replace_hashes() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let first: BoxHash = Hash::new(&[1, 1]).into();
+    let second: BoxHash = Hash::new(&[2, 2]).into();
+    let third: BoxHash = Hash::new(&[3, 3]).into();
+    database.hash_add(&first).unwrap();
+    database.hash_add(&second).unwrap();
+    database.tag_name_create("name", None).unwrap();
+    database.tag_value_create("name", "first").unwrap();
+    database.hash_tag_add(&first, "name", "first").unwrap();
+
+    // new hash of the first file is the old hash of the second one
+    let hashes = [
+        (first.clone(), second.clone()),
+        (second.clone(), third.clone()),
+    ]
+    .into();
+    database.hash_replace(&hashes).unwrap();
+    assert!(!database.hash_exists(&first).unwrap());
+    assert!(database.hash_exists(&third).unwrap());
+    let tags = database.hash_tags(&second, None, None).unwrap();
+    assert_eq!(tags, [Tag::new("name".into(), "first".into())].into());
+}
+
+#[test]
+fn can_manage_file_stats() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[1, 2, 3]);
+    let path = Path::new("folder/file.txt");
+    let stat = FileStat {
+        size: 5,
+        mtime: -1,
+        inode: u64::MAX,
+        device: 7,
+    };
+
+    // only recorded for known hashes
+    database.file_stat_set(path, &stat, hash).unwrap();
+    assert_eq!(database.file_stat(path).unwrap(), None);
+
+    database.hash_add(hash).unwrap();
+    database.file_stat_set(path, &stat, hash).unwrap();
+    assert_eq!(
+        database.file_stat(path).unwrap(),
+        Some((stat, BoxHash::from(hash)))
+    );
+    database
+        .file_stat_set(Path::new("copy.txt"), &stat, hash)
+        .unwrap();
+    assert_eq!(
+        database.file_stat_paths(hash).unwrap(),
+        [Path::new("copy.txt"), path]
+    );
+    database.file_stat_remove(Path::new("copy.txt")).unwrap();
+    assert_eq!(database.file_stat_paths(hash).unwrap(), [path]);
+
+    // removed along with the file
+    database.hash_remove(hash).unwrap();
+    assert_eq!(database.file_stat(path).unwrap(), None);
+    assert!(database.file_stat_paths(hash).unwrap().is_empty());
+}
+
+#[test]
+fn can_manage_phashes() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let [image, text] = [Hash::new(&[1]), Hash::new(&[2])];
+    let stat = FileStat {
+        size: 5,
+        mtime: 0,
+        inode: 1,
+        device: 1,
+    };
+    for hash in [image, text] {
+        database.hash_add(hash).unwrap();
+        database
+            .file_stat_set(Path::new(&format!("{hash}")), &stat, hash)
+            .unwrap();
+    }
+    database.tag_value_create("media", "image").unwrap();
+    database.hash_tag_add(image, "media", "image").unwrap();
+    assert_eq!(
+        database.hashes_without_phash().unwrap(),
+        [BoxHash::from(image)]
+    );
+    assert!(database.hash_phashes().unwrap().is_empty());
+
+    // stored as signed integer, but read back unchanged
+    let version = database.similar_version().unwrap();
+    database.hash_phash_set(image, u64::MAX).unwrap();
+    assert!(database.similar_version().unwrap() > version);
+    assert!(database.hashes_without_phash().unwrap().is_empty());
+    assert_eq!(
+        database.hash_phashes().unwrap(),
+        [(BoxHash::from(image), u64::MAX)]
+    );
+}
+
+#[test]
+fn can_list_duplicates() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let [file, other] = [Hash::new(&[1]), Hash::new(&[2])];
+    for (hash, path) in [(file, "/a/file"), (other, "/other")] {
+        database.hash_add(hash).unwrap();
+        database.tag_value_create("path", path).unwrap();
+        database.hash_tag_add(hash, "path", path).unwrap();
+    }
+    database.tag_value_create("filesize", "5").unwrap();
+    database.hash_tag_add(file, "filesize", "5").unwrap();
+
+    // only files added from several paths are duplicates
+    assert!(database.hash_duplicates().unwrap().is_empty());
+    for path in ["/copy", "/b/copy"] {
+        database.tag_value_create("path", path).unwrap();
+        database.hash_tag_add(file, "path", path).unwrap();
+    }
+    assert_eq!(
+        database.hash_duplicates().unwrap(),
+        [Duplicate::new(
+            file.into(),
+            5,
+            vec!["a/file".into(), "b/copy".into(), "copy".into()]
+        )]
+    );
+    assert_eq!(database.hash_duplicates().unwrap()[0].reclaimable, 10);
+
+    database
+        .hash_tag_remove(file, Some("path"), Some("/copy"))
+        .unwrap();
+    database
+        .hash_tag_remove(file, Some("path"), Some("/b/copy"))
+        .unwrap();
+    assert!(database.hash_duplicates().unwrap().is_empty());
+}
+
+#[test]
+fn can_manage_fingerprints() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[1]);
+    let frames = [0, u64::MAX, 0x0123_4567_89ab_cdef];
+
+    // only recorded for known hashes
+    database.hash_fingerprint_set(hash, &frames).unwrap();
+    assert!(database.hash_fingerprints().unwrap().is_empty());
+
+    database.hash_add(hash).unwrap();
+    database.tag_value_create("media", "video").unwrap();
+    database.hash_tag_add(hash, "media", "video").unwrap();
+    assert_eq!(
+        database.hashes_without_fingerprint().unwrap(),
+        [BoxHash::from(hash)]
+    );
+    let version = database.similar_version().unwrap();
+    database.hash_fingerprint_set(hash, &frames).unwrap();
+    assert!(database.hashes_without_fingerprint().unwrap().is_empty());
+    assert_eq!(
+        database.hash_fingerprints().unwrap(),
+        [(BoxHash::from(hash), frames.to_vec())]
+    );
+    assert!(database.similar_version().unwrap() > version);
+
+    // removed along with the file
+    let version = database.similar_version().unwrap();
+    database.execute("DELETE FROM file_tag_values", []).unwrap();
+    database.hash_remove(hash).unwrap();
+    assert!(database.hash_fingerprints().unwrap().is_empty());
+    assert!(database.similar_version().unwrap() > version);
+}
+
 #[test]
 fn tags_initially_empty() {
     let database = Database(Connection::open_in_memory().unwrap());
@@ -28,6 +346,18 @@ fn tags_initially_empty() {
     assert_eq!(list, [].into());
 }
 
+#[test]
+fn can_check_tag_exists() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    database.tag_name_create("name", None).unwrap();
+    database.tag_value_create("name", "value").unwrap();
+    assert!(database.tag_exists("name", None).unwrap());
+    assert!(database.tag_exists("name", Some("value")).unwrap());
+    assert!(!database.tag_exists("name", Some("other")).unwrap());
+    assert!(!database.tag_exists("other", None).unwrap());
+}
+
 #[test]
 fn can_tags_list_all_one() {
     let database = Database(Connection::open_in_memory().unwrap());
@@ -648,6 +978,47 @@ fn can_query_files_by_tag_name_value() {
     assert_eq!(hashes, [hash2.into()].into());
 }
 
+#[test]
+fn can_query_files_by_tag_glob() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash1 = Hash::new(&[0x01]);
+    let hash2 = Hash::new(&[0x02]);
+    database.hash_add(&hash1).unwrap();
+    database.hash_add(&hash2).unwrap();
+    database.tag_value_create("mime", "image/png").unwrap();
+    database.tag_value_create("mime", "video/mp4").unwrap();
+    database.tag_value_create("mime", "image/[x]?").unwrap();
+    database.hash_tag_add(&hash1, "mime", "image/png").unwrap();
+    database.hash_tag_add(&hash2, "mime", "video/mp4").unwrap();
+
+    let hashes = database
+        .query_hashes(&mut ["mime:image/*".parse::<TagPredicate>().unwrap()].iter())
+        .unwrap();
+    assert_eq!(hashes, [hash1.into()].into());
+
+    let hashes = database
+        .query_hashes(&mut ["mi*:*/mp4".parse::<TagPredicate>().unwrap()].iter())
+        .unwrap();
+    assert_eq!(hashes, [hash2.into()].into());
+
+    let hashes = database
+        .query_hashes(&mut ["!mime:image/*".parse::<TagPredicate>().unwrap()].iter())
+        .unwrap();
+    assert_eq!(hashes, [hash2.into()].into());
+
+    // only `*` is a wildcard
+    let hashes = database
+        .query_hashes(&mut ["mime:image/[p]ng".parse::<TagPredicate>().unwrap()].iter())
+        .unwrap();
+    assert_eq!(hashes, [].into());
+    database.hash_tag_add(&hash2, "mime", "image/[x]?").unwrap();
+    let hashes = database
+        .query_hashes(&mut ["mime:image/[x]?".parse::<TagPredicate>().unwrap()].iter())
+        .unwrap();
+    assert_eq!(hashes, [hash2.into()].into());
+}
+
 #[test]
 fn stress_test() {
     let database = Database(Connection::open_in_memory().unwrap());
@@ -752,6 +1123,48 @@ fn can_label_add_seq() {
         .unwrap();
 }
 
+#[test]
+fn can_remove_dangling() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(hash).unwrap();
+    database.tag_name_create("name", None).unwrap();
+    database.tag_value_create("name", "value").unwrap();
+    database.tag_value_create("name", "other").unwrap();
+    database.hash_tag_add(hash, "name", "value").unwrap();
+    database.hash_tag_add(hash, "name", "other").unwrap();
+    database
+        .label_add(hash, "name", "value", &Sequence { start: 0, end: 5 }.into())
+        .unwrap();
+    assert!(database.dangling_tag_values().unwrap().is_empty());
+    assert!(database.dangling_file_tags().unwrap().is_empty());
+    assert!(database.dangling_labels().unwrap().is_empty());
+
+    // rows removed while foreign keys were not enforced
+    database
+        .execute_batch(
+            "PRAGMA foreign_keys=OFF;
+            DELETE FROM tag_values WHERE value = 'other';
+            DELETE FROM file_tag_values WHERE tag_value_id IN
+                (SELECT id FROM tag_values WHERE value = 'value');
+            DELETE FROM tag_names WHERE name = 'name';
+            PRAGMA foreign_keys=ON;",
+        )
+        .unwrap();
+    let values = database.dangling_tag_values().unwrap();
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0].1, "value");
+    assert_eq!(database.dangling_file_tags().unwrap().len(), 1);
+    assert_eq!(database.dangling_labels().unwrap()[0].0, "sequence");
+
+    database.dangling_remove().unwrap();
+    assert!(database.dangling_tag_values().unwrap().is_empty());
+    assert!(database.dangling_file_tags().unwrap().is_empty());
+    assert!(database.dangling_labels().unwrap().is_empty());
+    assert!(database.hash_exists(hash).unwrap());
+}
+
 #[test]
 fn can_label_remove_rect() {
     let database = Database(Connection::open_in_memory().unwrap());
@@ -791,7 +1204,7 @@ fn can_label_remove_sequence() {
 fn can_label_query_empty() {
     let database = Database(Connection::open_in_memory().unwrap());
     database.migrate().unwrap();
-    let labels = database.label_get(None, None, None, None).unwrap();
+    let labels = database.label_get(None, None, None, None, None).unwrap();
     assert_eq!(labels.len(), 0);
 }
 
@@ -810,10 +1223,17 @@ fn can_label_get_rect() {
     }
     .into();
     database.label_add(&hash, "name", "value", &label).unwrap();
-    let labels = database.label_get(Some(&hash), None, None, None).unwrap();
+    let labels = database
+        .label_get(Some(&hash), None, None, None, None)
+        .unwrap();
     assert_eq!(
         labels,
-        [(Tag::new("name".into(), "value".into()), label)].into()
+        [(
+            Tag::new("name".into(), "value".into()),
+            label,
+            Attributes::new()
+        )]
+        .into()
     );
 }
 
@@ -828,13 +1248,217 @@ fn can_label_get_seq() {
     database.hash_tag_add(&hash, "name", "value").unwrap();
     let label = Sequence { start: 0, end: 15 }.into();
     database.label_add(&hash, "name", "value", &label).unwrap();
-    let labels = database.label_get(Some(&hash), None, None, None).unwrap();
+    let labels = database
+        .label_get(Some(&hash), None, None, None, None)
+        .unwrap();
     assert_eq!(
         labels,
-        [(Tag::new("name".into(), "value".into()), label)].into()
+        [(
+            Tag::new("name".into(), "value".into()),
+            label,
+            Attributes::new()
+        )]
+        .into()
     );
 }
 
+#[test]
+fn can_file_attributes_set() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(&hash).unwrap();
+    assert_eq!(database.file_attributes(&hash).unwrap(), Attributes::new());
+
+    database
+        .file_attribute_set(&hash, "comment", "blurry")
+        .unwrap();
+    database
+        .file_attribute_set(&hash, "source", "camera")
+        .unwrap();
+    database
+        .file_attribute_set(&hash, "source", "phone")
+        .unwrap();
+    assert_eq!(
+        database.file_attributes(&hash).unwrap(),
+        [
+            ("comment".to_string(), "blurry".to_string()),
+            ("source".to_string(), "phone".to_string())
+        ]
+        .into()
+    );
+}
+
+#[test]
+fn can_file_attributes_remove() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(&hash).unwrap();
+    database
+        .file_attribute_set(&hash, "comment", "blurry")
+        .unwrap();
+    database
+        .file_attribute_set(&hash, "source", "camera")
+        .unwrap();
+
+    database
+        .file_attribute_remove(&hash, Some("comment"))
+        .unwrap();
+    assert_eq!(
+        database.file_attributes(&hash).unwrap(),
+        [("source".to_string(), "camera".to_string())].into()
+    );
+
+    database.file_attribute_remove(&hash, None).unwrap();
+    assert_eq!(database.file_attributes(&hash).unwrap(), Attributes::new());
+}
+
+#[test]
+fn can_label_attributes_set() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(&hash).unwrap();
+    database.tag_name_create("name", None).unwrap();
+    database.tag_value_create("name", "value").unwrap();
+    database.hash_tag_add(&hash, "name", "value").unwrap();
+    let label = Rectangle {
+        start: Point::new(0, 0),
+        end: Point::new(64, 64),
+    }
+    .into();
+    database.label_add(&hash, "name", "value", &label).unwrap();
+    database
+        .label_attribute_set(&hash, "name", "value", &label, "occluded", "true")
+        .unwrap();
+    database
+        .label_attribute_set(&hash, "name", "value", &label, "confidence", "0.8")
+        .unwrap();
+
+    let attributes: Attributes = [
+        ("confidence".to_string(), "0.8".to_string()),
+        ("occluded".to_string(), "true".to_string()),
+    ]
+    .into();
+    assert_eq!(
+        database
+            .label_attributes(&hash, "name", "value", &label)
+            .unwrap(),
+        attributes
+    );
+    let labels = database
+        .label_get(Some(&hash), None, None, None, None)
+        .unwrap();
+    assert_eq!(
+        labels,
+        [(Tag::new("name".into(), "value".into()), label, attributes)].into()
+    );
+}
+
+#[test]
+fn can_label_attributes_missing_label() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(&hash).unwrap();
+    let label = Sequence { start: 0, end: 15 }.into();
+    let result = database.label_attribute_set(&hash, "name", "value", &label, "comment", "text");
+    assert!(matches!(result, Err(Error::QueryReturnedNoRows)));
+}
+
+#[test]
+fn can_label_attributes_remove() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(&hash).unwrap();
+    database.tag_name_create("name", None).unwrap();
+    database.tag_value_create("name", "value").unwrap();
+    database.hash_tag_add(&hash, "name", "value").unwrap();
+    let label = Sequence { start: 0, end: 15 }.into();
+    database.label_add(&hash, "name", "value", &label).unwrap();
+    database
+        .label_attribute_set(&hash, "name", "value", &label, "comment", "intro")
+        .unwrap();
+    database
+        .label_attribute_remove(&hash, "name", "value", &label, Some("comment"))
+        .unwrap();
+    assert_eq!(
+        database
+            .label_attributes(&hash, "name", "value", &label)
+            .unwrap(),
+        Attributes::new()
+    );
+
+    // removing the label also removes its attributes
+    database
+        .label_attribute_set(&hash, "name", "value", &label, "comment", "intro")
+        .unwrap();
+    database
+        .label_remove(&hash, "name", "value", &label)
+        .unwrap();
+    database.label_add(&hash, "name", "value", &label).unwrap();
+    assert_eq!(
+        database
+            .label_attributes(&hash, "name", "value", &label)
+            .unwrap(),
+        Attributes::new()
+    );
+}
+
+#[test]
+fn can_label_get_by_attribute() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let hash = Hash::new(&[0x01]);
+    database.hash_add(&hash).unwrap();
+    database.tag_name_create("name", None).unwrap();
+    database.tag_value_create("name", "value").unwrap();
+    database.hash_tag_add(&hash, "name", "value").unwrap();
+    let occluded = Rectangle {
+        start: Point::new(0, 0),
+        end: Point::new(64, 64),
+    }
+    .into();
+    let visible = Rectangle {
+        start: Point::new(10, 10),
+        end: Point::new(20, 20),
+    }
+    .into();
+    database
+        .label_add(&hash, "name", "value", &occluded)
+        .unwrap();
+    database
+        .label_add(&hash, "name", "value", &visible)
+        .unwrap();
+    database
+        .label_attribute_set(&hash, "name", "value", &occluded, "occluded", "true")
+        .unwrap();
+    database
+        .label_attribute_set(&hash, "name", "value", &visible, "occluded", "false")
+        .unwrap();
+
+    let filter = TagFilter::new(Some("occluded"), Some("true"));
+    let labels = database
+        .label_get(Some(&hash), None, None, None, Some(&filter))
+        .unwrap();
+    assert_eq!(labels.len(), 1);
+    assert_eq!(labels.iter().next().unwrap().1, occluded);
+
+    let filter = TagFilter::new(Some("occluded"), None);
+    let labels = database
+        .label_get(Some(&hash), None, None, None, Some(&filter))
+        .unwrap();
+    assert_eq!(labels.len(), 2);
+
+    let filter = TagFilter::new(Some("confidence"), None);
+    let labels = database
+        .label_get(Some(&hash), None, None, None, Some(&filter))
+        .unwrap();
+    assert_eq!(labels.len(), 0);
+}
+
 // TODO: test label_get with more loaded data?
 
 #[test]
@@ -1076,3 +1700,131 @@ proptest! {
         assert_eq!(result, expected);
     }
 }
+
+#[test]
+fn can_manage_users() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    assert_eq!(database.user_list().unwrap(), []);
+
+    database
+        .user_create("alice", Some("hash"), Role::ReadWrite)
+        .unwrap();
+    database.user_create("bob", None, Role::ReadOnly).unwrap();
+    assert!(database.user_create("bob", None, Role::ReadOnly).is_err());
+    assert_eq!(
+        database.user_list().unwrap(),
+        [
+            User {
+                name: "alice".into(),
+                role: Role::ReadWrite
+            },
+            User {
+                name: "bob".into(),
+                role: Role::ReadOnly
+            }
+        ]
+    );
+
+    assert_eq!(
+        database.user_password("alice").unwrap(),
+        Some("hash".into())
+    );
+    assert_eq!(database.user_password("bob").unwrap(), None);
+    assert_eq!(database.user_password("carol").unwrap(), None);
+    database.user_password_set("bob", Some("other")).unwrap();
+    assert_eq!(database.user_password("bob").unwrap(), Some("other".into()));
+    assert!(database.user_password_set("carol", None).is_err());
+
+    database.user_delete("alice").unwrap();
+    assert!(database.user_delete("alice").is_err());
+    assert_eq!(database.user_list().unwrap().len(), 1);
+}
+
+#[test]
+fn can_manage_user_tokens() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    database.user_create("alice", None, Role::ReadOnly).unwrap();
+    let alice = User {
+        name: "alice".into(),
+        role: Role::ReadOnly,
+    };
+
+    database
+        .user_token_add("alice", &[0x01], "api", None)
+        .unwrap();
+    database
+        .user_token_add("alice", &[0x02], "session", Some(100))
+        .unwrap();
+    assert!(database
+        .user_token_add("carol", &[0x03], "api", None)
+        .is_err());
+
+    assert_eq!(
+        database.user_token_get(&[0x01], 50).unwrap(),
+        Some(alice.clone())
+    );
+    assert_eq!(
+        database.user_token_get(&[0x02], 50).unwrap(),
+        Some(alice.clone())
+    );
+    assert_eq!(database.user_token_get(&[0x02], 100).unwrap(), None);
+    assert_eq!(database.user_token_get(&[0x03], 50).unwrap(), None);
+
+    // expiring only removes sessions
+    database.user_tokens_expire(100).unwrap();
+    database
+        .user_token_add("alice", &[0x02], "session", Some(200))
+        .unwrap();
+    assert_eq!(
+        database.user_token_get(&[0x01], 150).unwrap(),
+        Some(alice.clone())
+    );
+
+    database.user_token_remove(&[0x01]).unwrap();
+    assert_eq!(database.user_token_get(&[0x01], 150).unwrap(), None);
+
+    // tokens are removed along with their user
+    database.user_delete("alice").unwrap();
+    assert_eq!(database.user_token_get(&[0x02], 150).unwrap(), None);
+}
+
+#[test]
+fn can_manage_journal() {
+    let database = Database(Connection::open_in_memory().unwrap());
+    database.migrate().unwrap();
+    let create = BatchOperation::TagNameCreate {
+        name: "person".into(),
+        display: None,
+    };
+    let delete = BatchOperation::TagNameDelete {
+        name: "person".into(),
+    };
+
+    let first = database.journal_batch_create(100, &Origin::Cli).unwrap();
+    database
+        .journal_change_add(first, &create, std::slice::from_ref(&delete))
+        .unwrap();
+    let second = database.journal_batch_create(200, &Origin::Api).unwrap();
+    database
+        .journal_change_add(second, &delete, std::slice::from_ref(&create))
+        .unwrap();
+
+    let batches = database.journal_batches(10, false).unwrap();
+    assert_eq!(
+        batches.iter().map(|batch| batch.id).collect::<Vec<_>>(),
+        [second, first]
+    );
+    assert_eq!(batches[1].time, 100);
+    assert_eq!(batches[1].origin, Origin::Cli);
+    assert_eq!(batches[1].changes[0].operation, create);
+    assert_eq!(batches[1].changes[0].inverse, [delete]);
+    assert_eq!(database.journal_batches(1, false).unwrap().len(), 1);
+
+    let change = batches[0].changes[0].id;
+    database.journal_change_revert(change).unwrap();
+    assert!(database.journal_change(change).unwrap().reverted);
+    assert_eq!(database.journal_batches(10, false).unwrap().len(), 1);
+    assert_eq!(database.journal_batches(10, true).unwrap().len(), 2);
+}
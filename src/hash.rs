@@ -1,7 +1,12 @@
 use crate::config::HashAlgorithm;
 pub use cindy_common::{ArcHash, BoxHash, Hash};
-use digest::DynDigest;
-use std::io::{Read, Result as IoResult};
+use digest::{
+    consts::U32, DynDigest, FixedOutput, FixedOutputReset, Output, OutputSizeUser, Reset, Update,
+};
+use std::{
+    fs::Metadata,
+    io::{Read, Result as IoResult},
+};
 
 pub trait Digester: std::fmt::Debug {
     /// Create new Hasher
@@ -11,6 +16,11 @@ pub trait Digester: std::fmt::Debug {
     fn output_size(&self) -> usize {
         self.create().output_size()
     }
+
+    /// Size of the chunks to feed the hasher with when hashing files.
+    fn buffer_size(&self) -> usize {
+        DEFAULT_BUFFER_SIZE
+    }
 }
 
 impl Digester for HashAlgorithm {
@@ -19,6 +29,96 @@ impl Digester for HashAlgorithm {
         match self {
             Blake2b512 => Box::<blake2::Blake2b512>::default() as _,
             Blake2s256 => Box::<blake2::Blake2s256>::default() as _,
+            Blake3 => Box::<Blake3Hasher>::default() as _,
+            Sha256 => Box::<sha2::Sha256>::default() as _,
+        }
+    }
+
+    fn buffer_size(&self) -> usize {
+        match self {
+            // large enough to be spread over multiple threads
+            HashAlgorithm::Blake3 => 16 * BLAKE3_PARALLEL_SIZE,
+            _ => DEFAULT_BUFFER_SIZE,
+        }
+    }
+}
+
+/// Updates of at least this size are hashed using multiple threads by BLAKE3.
+const BLAKE3_PARALLEL_SIZE: usize = 128 * 1024;
+
+/// BLAKE3 hasher, which spreads large updates over multiple threads.
+#[derive(Clone, Default)]
+struct Blake3Hasher(blake3::Hasher);
+
+impl OutputSizeUser for Blake3Hasher {
+    type OutputSize = U32;
+}
+
+impl Update for Blake3Hasher {
+    fn update(&mut self, data: &[u8]) {
+        if data.len() >= BLAKE3_PARALLEL_SIZE {
+            self.0.update_rayon(data);
+        } else {
+            self.0.update(data);
+        }
+    }
+}
+
+impl FixedOutput for Blake3Hasher {
+    fn finalize_into(self, output: &mut Output<Self>) {
+        output.copy_from_slice(self.0.finalize().as_bytes());
+    }
+}
+
+impl FixedOutputReset for Blake3Hasher {
+    fn finalize_into_reset(&mut self, output: &mut Output<Self>) {
+        output.copy_from_slice(self.0.finalize().as_bytes());
+        self.0.reset();
+    }
+}
+
+impl Reset for Blake3Hasher {
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// Metadata of a file, if it is unchanged the file does not need to be hashed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub size: u64,
+    /// Modification time, in nanoseconds since the UNIX epoch.
+    pub mtime: i64,
+    pub inode: u64,
+    pub device: u64,
+}
+
+#[cfg(unix)]
+impl From<&Metadata> for FileStat {
+    fn from(metadata: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            size: metadata.size(),
+            mtime: metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec(),
+            inode: metadata.ino(),
+            device: metadata.dev(),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl From<&Metadata> for FileStat {
+    fn from(metadata: &Metadata) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos() as i64);
+        Self {
+            size: metadata.len(),
+            mtime: mtime.unwrap_or_default(),
+            inode: 0,
+            device: 0,
         }
     }
 }
@@ -47,6 +147,10 @@ pub trait ReadDigester {
 }
 
 impl<T: Digester + ?Sized> ReadDigester for T {
+    fn hash_read(&self, reader: &mut dyn Read) -> IoResult<BoxHash> {
+        self.hash_read_bufsize(reader, self.buffer_size())
+    }
+
     fn hash_read_bufsize(&self, reader: &mut dyn Read, buffer_size: usize) -> IoResult<BoxHash> {
         let mut hasher = self.create();
         let mut buffer = vec![0; buffer_size];
@@ -186,4 +290,55 @@ mod tests {
         let algorithm = HashAlgorithm::Blake2s256;
         assert_eq!(algorithm.output_size(), 32);
     }
+
+    const BLAKE3_HASH_EMPTY: [u8; 32] = [
+        175, 19, 73, 185, 245, 249, 161, 166, 160, 64, 77, 234, 54, 220, 201, 73, 155, 203, 37,
+        201, 173, 193, 18, 183, 204, 154, 147, 202, 228, 31, 50, 98,
+    ];
+
+    const BLAKE3_HASH_HELLO: [u8; 32] = [
+        234, 143, 22, 61, 179, 134, 130, 146, 94, 68, 145, 197, 229, 141, 75, 179, 80, 110, 248,
+        193, 78, 183, 138, 134, 233, 8, 197, 98, 74, 103, 32, 15,
+    ];
+
+    #[test]
+    fn can_hash_data_blake3() {
+        let algorithm = HashAlgorithm::Blake3;
+        assert_eq!(algorithm.hash_data(b""), BLAKE3_HASH_EMPTY);
+        assert_eq!(algorithm.hash_data(b"hello"), BLAKE3_HASH_HELLO);
+        assert_eq!(algorithm.output_size(), 32);
+    }
+
+    #[test]
+    fn can_hash_read_blake3_parallel() {
+        let algorithm = HashAlgorithm::Blake3;
+        let data = vec![17; 3 * algorithm.buffer_size() + 5];
+        let expected = blake3::hash(&data);
+        assert_eq!(algorithm.hash_data(&data), expected.as_bytes()[..]);
+        assert_eq!(
+            algorithm.hash_read(&mut &data[..]).unwrap(),
+            expected.as_bytes()[..]
+        );
+    }
+
+    const SHA256_HASH_EMPTY: [u8; 32] = [
+        227, 176, 196, 66, 152, 252, 28, 20, 154, 251, 244, 200, 153, 111, 185, 36, 39, 174, 65,
+        228, 100, 155, 147, 76, 164, 149, 153, 27, 120, 82, 184, 85,
+    ];
+
+    const SHA256_HASH_HELLO: [u8; 32] = [
+        44, 242, 77, 186, 95, 176, 163, 14, 38, 232, 59, 42, 197, 185, 226, 158, 27, 22, 30, 92,
+        31, 167, 66, 94, 115, 4, 51, 98, 147, 139, 152, 36,
+    ];
+
+    #[test]
+    fn can_hash_data_sha256() {
+        let algorithm = HashAlgorithm::Sha256;
+        assert_eq!(algorithm.hash_data(b""), SHA256_HASH_EMPTY);
+        assert_eq!(
+            algorithm.hash_read(&mut &b"hello"[..]).unwrap(),
+            SHA256_HASH_HELLO
+        );
+        assert_eq!(algorithm.output_size(), 32);
+    }
 }
@@ -0,0 +1,500 @@
+//! Journal of changes to tags and labels, which allows reverting them.
+use crate::{
+    database::{Database, Handle},
+    hash::Hash,
+};
+use anyhow::{bail, Result};
+use cindy_common::{BatchOperation, Change, Label, LabelKind, Mutation, Origin, Tag};
+
+fn file_check<T: Handle>(database: &Database<T>, file: &Hash) -> Result<()> {
+    if !database.hash_exists(file)? {
+        bail!("File {file} does not exist");
+    }
+    Ok(())
+}
+
+fn tag_check<T: Handle>(database: &Database<T>, name: &str, value: Option<&str>) -> Result<()> {
+    if !database.tag_exists(name, value)? {
+        match value {
+            Some(value) => bail!("Tag {name}:{value} does not exist"),
+            None => bail!("Tag {name} does not exist"),
+        }
+    }
+    Ok(())
+}
+
+fn file_tag_exists<T: Handle>(database: &Database<T>, file: &Hash, tag: &Tag) -> Result<bool> {
+    let tags = database.hash_tags(file, Some(tag.name()), Some(tag.value()))?;
+    Ok(!tags.is_empty())
+}
+
+fn file_tag_check<T: Handle>(database: &Database<T>, file: &Hash, tag: &Tag) -> Result<()> {
+    file_check(database, file)?;
+    if !file_tag_exists(database, file, tag)? {
+        bail!("File {file} is not tagged with {tag}");
+    }
+    Ok(())
+}
+
+fn label_exists<T: Handle>(
+    database: &Database<T>,
+    file: &Hash,
+    tag: &Tag,
+    label: &Label,
+) -> Result<bool> {
+    let labels = database.label_get(
+        Some(file),
+        Some(tag.name()),
+        Some(tag.value()),
+        Some(LabelKind::from(label)),
+        None,
+    )?;
+    Ok(labels.iter().any(|(_, existing, _)| existing == label))
+}
+
+fn tag_value_display<T: Handle>(
+    database: &Database<T>,
+    name: &str,
+    value: &str,
+) -> Result<Option<String>> {
+    let tags = database.tag_list(Some(name), Some(value))?;
+    Ok(tags.into_values().next().map(|info| info.display))
+}
+
+fn tag_name_display<T: Handle>(database: &Database<T>, name: &str) -> Result<Option<String>> {
+    Ok(database.tag_names()?.remove(name).map(|info| info.display))
+}
+
+/// Make sure what an operation refers to exists, so that it does not silently do nothing.
+pub fn check<T: Handle>(database: &Database<T>, operation: &BatchOperation) -> Result<()> {
+    match operation {
+        BatchOperation::FileTagAdd { file, name, value } => {
+            file_check(database, file)?;
+            tag_check(database, name, Some(value))?;
+        }
+        BatchOperation::FileTagRemove { file, .. } => file_check(database, file)?,
+        BatchOperation::TagNameCreate { .. } => {}
+        BatchOperation::TagNameDelete { name } => match database.tag_names()?.get(name) {
+            None => bail!("Tag {name} does not exist"),
+            Some(info) if info.system => bail!("Tag {name} is a system tag"),
+            Some(info) if info.values > 0 => bail!("Tag {name} still has values"),
+            Some(_) => {}
+        },
+        BatchOperation::TagNameRename { name, .. }
+        | BatchOperation::TagValueCreate { name, .. }
+        | BatchOperation::TagNameDisplay { name, .. } => tag_check(database, name, None)?,
+        BatchOperation::TagValueDelete { name, value } => {
+            tag_check(database, name, Some(value))?;
+            let tags = database.tag_list(Some(name), Some(value))?;
+            if tags.values().any(|info| info.system) {
+                bail!("Tag {name}:{value} is a system tag");
+            }
+        }
+        BatchOperation::TagValueDisplay { name, value, .. } => {
+            tag_check(database, name, Some(value))?
+        }
+        BatchOperation::LabelAdd { file, tag, .. }
+        | BatchOperation::LabelRemove { file, tag, .. } => file_tag_check(database, file, tag)?,
+    }
+    Ok(())
+}
+
+/// Apply a single operation.
+pub fn apply<T: Handle>(database: &Database<T>, operation: &BatchOperation) -> Result<()> {
+    match operation {
+        BatchOperation::FileTagAdd { file, name, value } => {
+            database.hash_tag_add(file, name, value)?
+        }
+        BatchOperation::FileTagRemove { file, name, value } => {
+            database.hash_tag_remove(file, name.as_deref(), value.as_deref())?
+        }
+        BatchOperation::TagNameCreate { name, display } => {
+            database.tag_name_create(name, display.as_deref())?
+        }
+        BatchOperation::TagNameDelete { name } => database.tag_name_delete(name)?,
+        BatchOperation::TagNameRename { name, new } => database.tag_name_rename(name, new)?,
+        BatchOperation::TagValueCreate {
+            name,
+            value,
+            display,
+        } => {
+            database.tag_value_create(name, value)?;
+            if let Some(display) = display {
+                database.tag_value_display(name, value, display)?;
+            }
+        }
+        BatchOperation::TagValueDelete { name, value } => {
+            database.tag_delete(Some(name), Some(value))?
+        }
+        BatchOperation::TagNameDisplay { name, display } => {
+            database.tag_name_display(name, display)?
+        }
+        BatchOperation::TagValueDisplay {
+            name,
+            value,
+            display,
+        } => database.tag_value_display(name, value, display)?,
+        BatchOperation::LabelAdd { file, tag, label } => {
+            database.label_add(file, tag.name(), tag.value(), label)?
+        }
+        BatchOperation::LabelRemove { file, tag, label } => {
+            database.label_remove(file, tag.name(), tag.value(), label)?
+        }
+    }
+    Ok(())
+}
+
+/// Operations which undo the given operation, determined before it is applied.
+///
+/// This is empty if the operation would not change anything, assuming it passes [`check`].
+pub fn inverse<T: Handle>(
+    database: &Database<T>,
+    operation: &BatchOperation,
+) -> Result<Vec<BatchOperation>> {
+    let inverse = match operation {
+        BatchOperation::FileTagAdd { file, name, value } => {
+            let tag = Tag::new(name.clone(), value.clone());
+            if file_tag_exists(database, file, &tag)? {
+                vec![]
+            } else {
+                vec![BatchOperation::FileTagRemove {
+                    file: file.clone(),
+                    name: Some(name.clone()),
+                    value: Some(value.clone()),
+                }]
+            }
+        }
+        BatchOperation::FileTagRemove { file, name, value } => database
+            .hash_tags(file, name.as_deref(), value.as_deref())?
+            .into_iter()
+            .map(|tag| BatchOperation::FileTagAdd {
+                file: file.clone(),
+                name: tag.name().into(),
+                value: tag.value().into(),
+            })
+            .collect(),
+        BatchOperation::TagNameCreate { name, .. } => match database.tag_exists(name, None)? {
+            true => vec![],
+            false => vec![BatchOperation::TagNameDelete { name: name.clone() }],
+        },
+        BatchOperation::TagNameDelete { name } => match tag_name_display(database, name)? {
+            Some(display) => vec![BatchOperation::TagNameCreate {
+                name: name.clone(),
+                display: Some(display),
+            }],
+            None => vec![],
+        },
+        BatchOperation::TagNameRename { name, new } => vec![BatchOperation::TagNameRename {
+            name: new.clone(),
+            new: name.clone(),
+        }],
+        BatchOperation::TagValueCreate {
+            name,
+            value,
+            display,
+        } => match (tag_value_display(database, name, value)?, display) {
+            (None, _) => vec![BatchOperation::TagValueDelete {
+                name: name.clone(),
+                value: value.clone(),
+            }],
+            (Some(old), Some(display)) if &old != display => {
+                vec![BatchOperation::TagValueDisplay {
+                    name: name.clone(),
+                    value: value.clone(),
+                    display: old,
+                }]
+            }
+            _ => vec![],
+        },
+        BatchOperation::TagValueDelete { name, value } => {
+            let Some(display) = tag_value_display(database, name, value)? else {
+                return Ok(vec![]);
+            };
+            let mut inverse = vec![];
+            // deleting the last value of a tag also deletes the tag name
+            let names = database.tag_names()?;
+            if let Some(info) = names
+                .get(name)
+                .filter(|info| info.values == 1 && !info.system)
+            {
+                inverse.push(BatchOperation::TagNameCreate {
+                    name: name.clone(),
+                    display: Some(info.display.clone()),
+                });
+            }
+            inverse.push(BatchOperation::TagValueCreate {
+                name: name.clone(),
+                value: value.clone(),
+                display: Some(display),
+            });
+            inverse
+        }
+        BatchOperation::TagNameDisplay { name, display } => {
+            match tag_name_display(database, name)? {
+                Some(old) if &old != display => vec![BatchOperation::TagNameDisplay {
+                    name: name.clone(),
+                    display: old,
+                }],
+                _ => vec![],
+            }
+        }
+        BatchOperation::TagValueDisplay {
+            name,
+            value,
+            display,
+        } => match tag_value_display(database, name, value)? {
+            Some(old) if &old != display => vec![BatchOperation::TagValueDisplay {
+                name: name.clone(),
+                value: value.clone(),
+                display: old,
+            }],
+            _ => vec![],
+        },
+        BatchOperation::LabelAdd { file, tag, label } => {
+            match label_exists(database, file, tag, label)? {
+                true => vec![],
+                false => vec![BatchOperation::LabelRemove {
+                    file: file.clone(),
+                    tag: tag.clone(),
+                    label: *label,
+                }],
+            }
+        }
+        BatchOperation::LabelRemove { file, tag, label } => {
+            match label_exists(database, file, tag, label)? {
+                true => vec![BatchOperation::LabelAdd {
+                    file: file.clone(),
+                    tag: tag.clone(),
+                    label: *label,
+                }],
+                false => vec![],
+            }
+        }
+    };
+    Ok(inverse)
+}
+
+/// Mutations caused by the operations, without duplicates.
+pub fn mutations<'a>(operations: impl IntoIterator<Item = &'a BatchOperation>) -> Vec<Mutation> {
+    let mut mutations = vec![];
+    for mutation in operations.into_iter().map(BatchOperation::mutation) {
+        if !mutations.contains(&mutation) {
+            mutations.push(mutation);
+        }
+    }
+    mutations
+}
+
+/// Applies operations and records them as one batch of changes.
+pub struct Journal<'a, T: Handle> {
+    database: &'a Database<T>,
+    origin: Origin,
+    batch: Option<u64>,
+}
+
+impl<'a, T: Handle> Journal<'a, T> {
+    pub fn new(database: &'a Database<T>, origin: Origin) -> Self {
+        Self {
+            database,
+            origin,
+            batch: None,
+        }
+    }
+
+    /// Apply an operation, recording it unless it did not change anything.
+    ///
+    /// Operations which fail the [`check`] don't change anything, so they are not recorded.
+    pub fn apply(&mut self, operation: &BatchOperation) -> Result<()> {
+        let inverse = match check(self.database, operation) {
+            Ok(()) => inverse(self.database, operation)?,
+            Err(_) => vec![],
+        };
+        apply(self.database, operation)?;
+        if inverse.is_empty() {
+            return Ok(());
+        }
+
+        let batch = match self.batch {
+            Some(batch) => batch,
+            None => {
+                let time = chrono::Utc::now().timestamp();
+                let batch = self.database.journal_batch_create(time, &self.origin)?;
+                *self.batch.insert(batch)
+            }
+        };
+        self.database
+            .journal_change_add(batch, operation, &inverse)?;
+        Ok(())
+    }
+}
+
+/// Revert changes, most recent first, returning the operations that were applied.
+///
+/// Changes which were already reverted, and inverse operations which would not change anything,
+/// are skipped. Reverting fails if later changes conflict, for example if a tag that was removed
+/// from a file has since been deleted.
+pub fn revert<T: Handle>(
+    database: &Database<T>,
+    changes: &[Change],
+) -> Result<Vec<BatchOperation>> {
+    let mut applied = vec![];
+    for change in changes.iter().rev().filter(|change| !change.reverted) {
+        for operation in &change.inverse {
+            // deleting a tag value can delete its name too, so it might already be gone
+            if inverse(database, operation)?.is_empty() {
+                continue;
+            }
+            check(database, operation)?;
+            apply(database, operation)?;
+            applied.push(operation.clone());
+        }
+        database.journal_change_revert(change.id)?;
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cindy_common::{Point, Rectangle};
+    use rusqlite::Connection;
+    use std::collections::BTreeMap;
+
+    fn database() -> Database {
+        let database: Database = Connection::open_in_memory().unwrap().into();
+        database.migrate().unwrap();
+        database
+    }
+
+    /// Everything a journaled operation can change.
+    fn snapshot(database: &Database, file: &Hash) -> impl PartialEq + std::fmt::Debug {
+        let names: BTreeMap<_, _> = database
+            .tag_names()
+            .unwrap()
+            .into_iter()
+            .map(|(name, info)| (name, info.display))
+            .collect();
+        (
+            names,
+            database.tag_list(None, None).unwrap(),
+            database.hash_tags(file, None, None).unwrap(),
+            database
+                .label_get(Some(file), None, None, None, None)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn revert_restores_state() {
+        let database = database();
+        let file = Hash::new(&[0x01, 0x23]);
+        let tag = Tag::new("person".into(), "alice".into());
+        let label = Label::Rectangle(Rectangle {
+            start: Point::new(1, 2),
+            end: Point::new(3, 4),
+        });
+        database.hash_add(file).unwrap();
+        database.tag_name_create("person", None).unwrap();
+        database.tag_value_create("person", "alice").unwrap();
+        database.hash_tag_add(file, "person", "alice").unwrap();
+        database.label_add(file, "person", "alice", &label).unwrap();
+        let before = snapshot(&database, file);
+
+        let operations = [
+            BatchOperation::TagValueDisplay {
+                name: "person".into(),
+                value: "alice".into(),
+                display: "Alice".into(),
+            },
+            BatchOperation::LabelRemove {
+                file: file.into(),
+                tag: tag.clone(),
+                label,
+            },
+            BatchOperation::TagValueCreate {
+                name: "person".into(),
+                value: "bob".into(),
+                display: None,
+            },
+            BatchOperation::FileTagAdd {
+                file: file.into(),
+                name: "person".into(),
+                value: "bob".into(),
+            },
+            BatchOperation::TagNameRename {
+                name: "person".into(),
+                new: "people".into(),
+            },
+        ];
+        let mut journal = Journal::new(&database, Origin::Cli);
+        for operation in &operations {
+            journal.apply(operation).unwrap();
+        }
+        assert_ne!(snapshot(&database, file), before);
+
+        let batches = database.journal_batches(10, false).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].origin, Origin::Cli);
+        let recorded: Vec<_> = batches[0]
+            .changes
+            .iter()
+            .map(|change| change.operation.clone())
+            .collect();
+        assert_eq!(recorded, operations);
+
+        revert(&database, &batches[0].changes).unwrap();
+        assert_eq!(snapshot(&database, file), before);
+        assert!(database.journal_batches(10, false).unwrap().is_empty());
+        assert!(database.journal_batches(10, true).unwrap()[0].reverted());
+    }
+
+    #[test]
+    fn journal_skips_unchanged() {
+        let database = database();
+        let file = Hash::new(&[0x01, 0x23]);
+        database.hash_add(file).unwrap();
+        database.tag_name_create("person", None).unwrap();
+        database.tag_value_create("person", "alice").unwrap();
+
+        let mut journal = Journal::new(&database, Origin::Api);
+        // tag value exists already
+        journal
+            .apply(&BatchOperation::TagValueCreate {
+                name: "person".into(),
+                value: "alice".into(),
+                display: None,
+            })
+            .unwrap();
+        // tag value does not exist
+        journal
+            .apply(&BatchOperation::FileTagAdd {
+                file: file.into(),
+                name: "person".into(),
+                value: "bob".into(),
+            })
+            .unwrap();
+        assert!(database.journal_batches(10, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn revert_conflict() {
+        let database = database();
+        let file = Hash::new(&[0x01, 0x23]);
+        database.hash_add(file).unwrap();
+        database.tag_name_create("person", None).unwrap();
+        Journal::new(&database, Origin::Cli)
+            .apply(&BatchOperation::TagValueCreate {
+                name: "person".into(),
+                value: "alice".into(),
+                display: None,
+            })
+            .unwrap();
+
+        // the tag value can't be deleted while it is in use
+        database.hash_tag_add(file, "person", "alice").unwrap();
+        let changes = database.journal_batches(10, false).unwrap()[0]
+            .changes
+            .clone();
+        assert!(revert(&database, &changes).is_err());
+    }
+}
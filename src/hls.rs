@@ -0,0 +1,348 @@
+use crate::{
+    hash::{BoxHash, Hash},
+    media::{scaled_size, StreamInfo},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs::{metadata, read_dir, remove_file, File},
+    io::Result,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Most stream infos remembered, the cache starts over once it holds this many.
+const STREAM_INFOS: usize = 4096;
+
+/// What is remembered between requests, so that videos are not probed and the cache is not
+/// scanned again on every request.
+#[derive(Debug, Default)]
+pub struct HlsState {
+    infos: Mutex<BTreeMap<BoxHash, StreamInfo>>,
+    /// Size of the cached segments in bytes, unknown until the cache was first scanned.
+    size: Mutex<Option<u64>>,
+}
+
+impl HlsState {
+    /// Stream info of a video, probing it with `probe` unless it is remembered.
+    pub fn stream_info(
+        &self,
+        hash: &Hash,
+        probe: impl FnOnce() -> anyhow::Result<StreamInfo>,
+    ) -> anyhow::Result<StreamInfo> {
+        if let Some(info) = self.infos.lock().unwrap().get(hash) {
+            return Ok(*info);
+        }
+        let info = probe()?;
+        let mut infos = self.infos.lock().unwrap();
+        if infos.len() >= STREAM_INFOS {
+            infos.clear();
+        }
+        infos.insert(hash.into(), info);
+        Ok(info)
+    }
+
+    /// Record a segment that was added to the cache at `path`, evicting the least recently used
+    /// ones once the cache grows beyond `limit` bytes.
+    ///
+    /// Eviction goes a tenth below the limit, so that the cache is not scanned for every segment
+    /// added while it is full.
+    pub fn added(&self, path: &Path, limit: u64, segment: &Path) -> Result<()> {
+        let length = metadata(segment)?.len();
+        let mut size = self.size.lock().unwrap();
+        let current = match *size {
+            Some(size) => size + length,
+            None => {
+                let mut files = vec![];
+                cached_files(path, &mut files)?;
+                files.iter().map(|(_, size, _)| size).sum()
+            }
+        };
+        *size = Some(match current > limit {
+            true => evict(path, limit - limit / 10, segment)?,
+            false => current,
+        });
+        Ok(())
+    }
+}
+
+/// Variant of a video at a specific resolution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rendition {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rendition {
+    /// Rough estimate of the bitrate, players use this to choose a rendition.
+    pub fn bandwidth(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height) * 4
+    }
+}
+
+/// Renditions offered for a video, from the configured heights.
+///
+/// Heights larger than the video are left out, if none are left the original height is used.
+pub fn renditions(heights: &[u32], info: &StreamInfo) -> Vec<Rendition> {
+    let mut heights: Vec<u32> = heights
+        .iter()
+        .copied()
+        .filter(|height| *height <= info.height)
+        .collect();
+    if heights.is_empty() {
+        heights.push(info.height);
+    }
+    heights.sort_unstable();
+    heights.dedup();
+    heights
+        .into_iter()
+        .map(|height| {
+            let (width, height) = scaled_size(info.width, info.height, Some(height));
+            Rendition { width, height }
+        })
+        .collect()
+}
+
+/// Playlist listing the renditions, each of which have their own playlist.
+pub fn master_playlist(renditions: &[Rendition]) -> String {
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    for rendition in renditions {
+        let Rendition { width, height } = rendition;
+        writeln!(
+            playlist,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={width}x{height}",
+            rendition.bandwidth()
+        )
+        .unwrap();
+        writeln!(playlist, "{height}/index.m3u8").unwrap();
+    }
+    playlist
+}
+
+/// Number of segments of the given length (in milliseconds) needed for a video.
+pub fn segment_count(duration: u64, segment: u64) -> u64 {
+    duration.div_ceil(segment.max(1))
+}
+
+/// Playlist of the segments of one rendition, named by their index.
+pub fn media_playlist(duration: u64, segment: u64) -> String {
+    let segment = segment.max(1);
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    writeln!(playlist, "#EXT-X-TARGETDURATION:{}", segment.div_ceil(1000)).unwrap();
+    playlist.push_str("#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n");
+    for index in 0..segment_count(duration, segment) {
+        let length = segment.min(duration - index * segment);
+        writeln!(playlist, "#EXTINF:{:.3},", length as f64 / 1000.0).unwrap();
+        writeln!(playlist, "{index}.ts").unwrap();
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist
+}
+
+/// Mark a cached segment as recently used.
+pub fn touch(path: &Path) -> Result<()> {
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+fn cached_files(path: &Path, files: &mut Vec<(SystemTime, u64, PathBuf)>) -> Result<()> {
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let path = entry.path();
+        if metadata.is_dir() {
+            cached_files(&path, files)?;
+        } else if path.extension() != Some("partial".as_ref()) {
+            // segments being generated are left alone
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    Ok(())
+}
+
+/// Remove the least recently used files from the cache until it fits into `limit` bytes,
+/// returning its size afterwards.
+///
+/// The `keep` file is never removed, so that a segment that was just created can be served.
+pub fn evict(path: &Path, limit: u64, keep: &Path) -> Result<u64> {
+    if metadata(path).is_err() {
+        return Ok(0);
+    }
+
+    let mut files = vec![];
+    cached_files(path, &mut files)?;
+    let mut size: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort();
+    for (_, length, file) in files {
+        if size <= limit {
+            break;
+        }
+        if file == keep {
+            continue;
+        }
+        remove_file(&file)?;
+        size -= length;
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::write, time::Duration};
+    use tempfile::tempdir;
+
+    const INFO: StreamInfo = StreamInfo {
+        width: 1920,
+        height: 1080,
+        duration: 14_500,
+    };
+
+    #[test]
+    fn renditions_skip_larger() {
+        let small = StreamInfo {
+            width: 854,
+            height: 480,
+            ..INFO
+        };
+        assert_eq!(
+            renditions(&[1080, 360, 720], &small),
+            [Rendition {
+                width: 640,
+                height: 360
+            }]
+        );
+        assert_eq!(
+            renditions(&[1080, 360, 720], &INFO),
+            [
+                Rendition {
+                    width: 640,
+                    height: 360
+                },
+                Rendition {
+                    width: 1280,
+                    height: 720
+                },
+                Rendition {
+                    width: 1920,
+                    height: 1080
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renditions_fallback_original() {
+        let tiny = StreamInfo {
+            width: 320,
+            height: 241,
+            ..INFO
+        };
+        assert_eq!(
+            renditions(&[360], &tiny),
+            [Rendition {
+                width: 320,
+                height: 240
+            }]
+        );
+    }
+
+    #[test]
+    fn master_playlist_lists_renditions() {
+        let playlist = master_playlist(&renditions(&[360, 720], &INFO));
+        assert_eq!(
+            playlist,
+            "#EXTM3U\n#EXT-X-VERSION:3\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=921600,RESOLUTION=640x360\n360/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=3686400,RESOLUTION=1280x720\n720/index.m3u8\n"
+        );
+    }
+
+    #[test]
+    fn media_playlist_segments() {
+        assert_eq!(segment_count(INFO.duration, 6000), 3);
+        assert_eq!(segment_count(12_000, 6000), 2);
+        assert_eq!(
+            media_playlist(INFO.duration, 6000),
+            "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n\
+            #EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n\
+            #EXTINF:6.000,\n0.ts\n#EXTINF:6.000,\n1.ts\n#EXTINF:2.500,\n2.ts\n\
+            #EXT-X-ENDLIST\n"
+        );
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let dir = tempdir().unwrap();
+        let segments = dir.path().join("hash").join("360");
+        std::fs::create_dir_all(&segments).unwrap();
+        let now = SystemTime::now();
+        for index in 0..4u64 {
+            let path = segments.join(format!("{index}.ts"));
+            write(&path, [0; 100]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(100 - index))
+                .unwrap();
+        }
+
+        // using a segment makes it the most recently used one
+        touch(&segments.join("0.ts")).unwrap();
+        assert_eq!(evict(dir.path(), 250, &segments.join("3.ts")).unwrap(), 200);
+
+        assert!(segments.join("0.ts").exists());
+        assert!(!segments.join("1.ts").exists());
+        assert!(!segments.join("2.ts").exists());
+        assert!(segments.join("3.ts").exists());
+    }
+
+    #[test]
+    fn state_tracks_size() {
+        let dir = tempdir().unwrap();
+        let state = HlsState::default();
+        for index in 0..10 {
+            let path = dir.path().join(format!("{index}.ts"));
+            write(&path, [0; 100]).unwrap();
+            state.added(dir.path(), 500, &path).unwrap();
+        }
+        // segments being generated are not counted or removed
+        write(dir.path().join("10.partial"), [0; 100]).unwrap();
+
+        // evicted below the limit whenever it was exceeded
+        let files = read_dir(dir.path()).unwrap().count();
+        assert!(files <= 6, "{files} files left");
+        assert!(dir.path().join("9.ts").exists());
+        assert!(dir.path().join("10.partial").exists());
+        assert_eq!(*state.size.lock().unwrap(), Some((files as u64 - 1) * 100));
+    }
+
+    #[test]
+    fn state_remembers_stream_info() {
+        let state = HlsState::default();
+        let hash = Hash::new(&[1]);
+        assert_eq!(state.stream_info(hash, || Ok(INFO)).unwrap(), INFO);
+        let info = state.stream_info(hash, || panic!("probed again")).unwrap();
+        assert_eq!(info, INFO);
+    }
+
+    #[test]
+    fn evict_keeps_new_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.ts");
+        write(&path, [0; 100]).unwrap();
+        evict(dir.path(), 10, &path).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn evict_missing_cache() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        evict(&missing, 10, &missing.join("0.ts")).unwrap();
+    }
+}
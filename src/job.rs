@@ -0,0 +1,215 @@
+use anyhow::{bail, Result};
+use cindy_common::{JobInfo, JobStatus};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::watch;
+
+/// How many finished jobs are kept around so their outcome can be looked up.
+const FINISHED_JOBS: usize = 32;
+
+#[derive(Debug)]
+struct JobState {
+    progress: watch::Sender<JobInfo>,
+    cancelled: AtomicBool,
+}
+
+/// Handle to a job, used by the job to report progress and by others to follow or cancel it.
+#[derive(Clone, Debug)]
+pub struct JobHandle {
+    state: Arc<JobState>,
+}
+
+impl JobHandle {
+    fn new(id: u64, name: &str) -> Self {
+        let info = JobInfo {
+            id,
+            name: name.into(),
+            ..Default::default()
+        };
+        Self {
+            state: Arc::new(JobState {
+                progress: watch::channel(info).0,
+                cancelled: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Current progress of the job.
+    pub fn info(&self) -> JobInfo {
+        self.state.progress.borrow().clone()
+    }
+
+    /// Follow the progress of the job.
+    pub fn subscribe(&self) -> watch::Receiver<JobInfo> {
+        self.state.progress.subscribe()
+    }
+
+    /// Start a new phase with the given amount of items, zero if not known.
+    pub fn phase(&self, phase: &str, total: u64) {
+        self.state.progress.send_modify(|info| {
+            info.phase = phase.into();
+            info.done = 0;
+            info.total = total;
+        });
+    }
+
+    /// Update the amount of items done in the current phase.
+    pub fn progress(&self, done: u64) {
+        self.state.progress.send_modify(|info| info.done = done);
+    }
+
+    /// Record an error that does not stop the job.
+    pub fn error(&self, error: String) {
+        self.state
+            .progress
+            .send_modify(|info| info.errors.push(error));
+    }
+
+    /// Ask the job to stop, it will do so the next time it checks.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails if the job was cancelled, jobs call this regularly to stop early.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("Job was cancelled");
+        }
+        Ok(())
+    }
+
+    /// Record the outcome of the job.
+    pub fn finish<T>(&self, result: &Result<T>) {
+        let cancelled = self.is_cancelled();
+        self.state.progress.send_modify(|info| {
+            info.status = match result {
+                Ok(_) => JobStatus::Done,
+                Err(_) if cancelled => JobStatus::Cancelled,
+                Err(error) => {
+                    info.errors.push(format!("{error:#}"));
+                    JobStatus::Failed
+                }
+            };
+        });
+    }
+}
+
+/// Jobs that are running or have recently finished.
+#[derive(Debug, Default)]
+pub struct Jobs {
+    next: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, JobHandle>>,
+}
+
+impl Jobs {
+    /// Register a new job, the caller is responsible for running and finishing it.
+    pub fn create(&self, name: &str) -> JobHandle {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let job = JobHandle::new(id, name);
+        let mut jobs = self.jobs.lock().expect("Failure to lock jobs");
+        jobs.insert(id, job.clone());
+
+        // forget the oldest finished jobs
+        let finished: Vec<u64> = jobs
+            .iter()
+            .filter(|(_, job)| job.info().status.finished())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in finished
+            .iter()
+            .take(finished.len().saturating_sub(FINISHED_JOBS))
+        {
+            jobs.remove(id);
+        }
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<JobHandle> {
+        self.jobs
+            .lock()
+            .expect("Failure to lock jobs")
+            .get(&id)
+            .cloned()
+    }
+
+    /// Progress of all jobs, ordered by when they were started.
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.jobs
+            .lock()
+            .expect("Failure to lock jobs")
+            .values()
+            .map(JobHandle::info)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn job_progress() {
+        let jobs = Jobs::default();
+        let job = jobs.create("add");
+        let mut receiver = job.subscribe();
+        job.phase("hashing", 10);
+        job.progress(4);
+        job.error("cannot read file".into());
+        assert!(receiver.has_changed().unwrap());
+
+        let info = receiver.borrow_and_update().clone();
+        assert_eq!(info.name, "add");
+        assert_eq!(info.phase, "hashing");
+        assert_eq!((info.done, info.total), (4, 10));
+        assert_eq!(info.errors, ["cannot read file"]);
+        assert_eq!(info.status, JobStatus::Running);
+
+        job.finish(&Ok(()));
+        assert_eq!(jobs.list()[0].status, JobStatus::Done);
+    }
+
+    #[test]
+    fn job_cancel() {
+        let jobs = Jobs::default();
+        let job = jobs.create("add");
+        assert!(job.check().is_ok());
+        jobs.get(job.info().id).unwrap().cancel();
+        let result = job.check();
+        assert!(result.is_err());
+        job.finish(&result);
+        assert_eq!(job.info().status, JobStatus::Cancelled);
+    }
+
+    #[test]
+    fn job_failed() {
+        let jobs = Jobs::default();
+        let job = jobs.create("add");
+        job.finish::<()>(&Err(anyhow!("disk full")));
+        let info = job.info();
+        assert_eq!(info.status, JobStatus::Failed);
+        assert_eq!(info.errors, ["disk full"]);
+    }
+
+    #[test]
+    fn jobs_forget_finished() {
+        let jobs = Jobs::default();
+        let running = jobs.create("running");
+        for _ in 0..FINISHED_JOBS + 5 {
+            jobs.create("done").finish(&Ok(()));
+        }
+        jobs.create("last");
+        let list = jobs.list();
+        assert_eq!(list.len(), FINISHED_JOBS + 2);
+        assert_eq!(list[0].id, running.info().id);
+    }
+}
@@ -1,20 +1,27 @@
+pub mod auth;
 mod cindy;
 pub mod cli;
 mod command;
 pub mod config;
 mod database;
 pub mod hash;
+mod history;
+#[cfg(feature = "ffmpeg")]
+mod hls;
+pub mod job;
 #[cfg(feature = "ffmpeg")]
 mod media;
 mod plugins;
 #[cfg(feature = "server")]
 mod server;
+pub mod similar;
 
 pub use crate::{
     cindy::Cindy,
     cli::{Command, Options},
+    command::{fsck, gc},
     config::Config,
-    database::Database,
+    database::{Database, Pool, PoolGuard},
 };
 pub use cindy_common::{
     self as common,
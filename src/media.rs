@@ -1,15 +1,30 @@
-use crate::Tag;
+use crate::{
+    similar::{difference_hash, DHASH_HEIGHT, DHASH_WIDTH},
+    Tag,
+};
 use anyhow::{anyhow, Result};
 use chrono::NaiveTime;
+use cindy_common::{Rectangle, Sequence, TranscodeProfile};
 use ffmpeg_next::{
     self as ffmpeg,
-    codec::context::Context,
-    format::{context::Input, input},
+    codec::{self, context::Context},
+    decoder, encoder, filter,
+    format::{
+        self,
+        context::{Input, Output},
+        input, output_as, Pixel,
+    },
+    frame::{Audio, Video},
+    media::Type,
+    picture,
+    software::scaling::{self, Flags},
     util::log::{set_level, Level},
+    ChannelLayout, Codec, Dictionary, Packet, Rational,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeSet, path::Path};
+use std::{collections::BTreeSet, fs::write, path::Path};
 use strum::Display;
+use tempfile::NamedTempFile;
 
 pub fn ffmpeg_init() -> Result<()> {
     ffmpeg::init()?;
@@ -278,11 +293,765 @@ fn image_info(input: &Input) -> Result<ImageInfo> {
     Ok(info)
 }
 
+/// Decode the first video frame at or after `time` (in milliseconds) as RGB.
+fn decode_frame(path: &Path, time: u64) -> Result<Video> {
+    let mut file = input(&path)?;
+    let stream = file
+        .streams()
+        .best(Type::Video)
+        .ok_or_else(|| anyhow!("No video stream"))?;
+    let index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let mut decoder = Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    // seek timestamps are in microseconds, this lands on the keyframe before the time.
+    if time > 0 {
+        let timestamp = time as i64 * 1000;
+        file.seek(timestamp, ..timestamp)?;
+    }
+
+    let is_wanted = |frame: &Video| match frame.timestamp() {
+        Some(timestamp) => (timestamp as f64 * time_base * 1000.0) >= time as f64,
+        None => true,
+    };
+
+    let mut frame = Video::empty();
+    let mut found = false;
+    for (stream, packet) in file.packets() {
+        if stream.index() != index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut frame).is_ok() {
+            if is_wanted(&frame) {
+                found = true;
+                break;
+            }
+        }
+        if found {
+            break;
+        }
+    }
+
+    if !found {
+        decoder.send_eof()?;
+        while decoder.receive_frame(&mut frame).is_ok() {
+            if is_wanted(&frame) {
+                found = true;
+                break;
+            }
+        }
+    }
+
+    if !found {
+        return Err(anyhow!("No frame at {time}ms"));
+    }
+
+    let mut scaler = scaling::Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        Pixel::RGB24,
+        frame.width(),
+        frame.height(),
+        Flags::BILINEAR,
+    )?;
+    let mut rgb = Video::empty();
+    scaler.run(&frame, &mut rgb)?;
+    Ok(rgb)
+}
+
+/// Copy the region described by `rectangle` out of an RGB frame.
+fn crop_frame(frame: &Video, rectangle: &Rectangle) -> Result<Video> {
+    if rectangle.end.x <= rectangle.start.x || rectangle.end.y <= rectangle.start.y {
+        return Err(anyhow!("Empty crop region {rectangle:?}"));
+    }
+    if rectangle.end.x > u64::from(frame.width()) || rectangle.end.y > u64::from(frame.height()) {
+        return Err(anyhow!(
+            "Crop region {rectangle:?} exceeds frame of {}x{}",
+            frame.width(),
+            frame.height()
+        ));
+    }
+
+    // bounds checked above, so these fit into the frame dimensions
+    let width = rectangle.width() as usize;
+    let height = rectangle.height() as usize;
+    let (x, y) = (rectangle.start.x as usize, rectangle.start.y as usize);
+    let mut cropped = Video::new(Pixel::RGB24, width as u32, height as u32);
+    let (input_stride, output_stride) = (frame.stride(0), cropped.stride(0));
+    let input = frame.data(0);
+    let output = cropped.data_mut(0);
+    for row in 0..height {
+        let start = (y + row) * input_stride + x * 3;
+        output[row * output_stride..][..width * 3].copy_from_slice(&input[start..][..width * 3]);
+    }
+
+    Ok(cropped)
+}
+
+/// Encode an RGB frame as PNG.
+fn encode_png(frame: &Video) -> Result<Vec<u8>> {
+    let codec = encoder::find(codec::Id::PNG).ok_or_else(|| anyhow!("No PNG encoder"))?;
+    let mut encoder = Context::new().encoder().video()?;
+    encoder.set_width(frame.width());
+    encoder.set_height(frame.height());
+    encoder.set_format(Pixel::RGB24);
+    encoder.set_time_base((1, 1));
+    let mut encoder = encoder.open_as(codec)?;
+    encoder.send_frame(frame)?;
+    encoder.send_eof()?;
+    let mut packet = Packet::empty();
+    encoder.receive_packet(&mut packet)?;
+    Ok(packet.data().unwrap_or_default().to_vec())
+}
+
+/// Time between the frames of a video which make up its fingerprint, in milliseconds.
+const FINGERPRINT_INTERVAL: u64 = 2000;
+
+/// Most frames in the fingerprint of a video, longer videos are only fingerprinted up to here.
+const FINGERPRINT_FRAMES: usize = 256;
+
+/// Perceptual hash of a frame, see [`difference_hash`].
+fn frame_hash(frame: &Video) -> Result<u64> {
+    let mut scaler = scaling::Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        Pixel::GRAY8,
+        DHASH_WIDTH as u32,
+        DHASH_HEIGHT as u32,
+        Flags::AREA,
+    )?;
+    let mut gray = Video::empty();
+    scaler.run(frame, &mut gray)?;
+
+    let (stride, data) = (gray.stride(0), gray.data(0));
+    let mut pixels = [[0; DHASH_WIDTH]; DHASH_HEIGHT];
+    for (y, row) in pixels.iter_mut().enumerate() {
+        row.copy_from_slice(&data[y * stride..][..DHASH_WIDTH]);
+    }
+    Ok(difference_hash(&pixels))
+}
+
+/// Perceptual hash of an image or the first frame of a video.
+pub fn perceptual_hash(path: &Path) -> Result<u64> {
+    frame_hash(&decode_frame(path, 0)?)
+}
+
+/// Perceptual hashes of the frames of a video at a fixed interval.
+pub fn video_fingerprint(path: &Path) -> Result<Vec<u64>> {
+    let duration = stream_info(path)?.duration;
+    let mut fingerprint = vec![];
+    for time in (0..duration.max(1))
+        .step_by(FINGERPRINT_INTERVAL as usize)
+        .take(FINGERPRINT_FRAMES)
+    {
+        match decode_frame(path, time) {
+            Ok(frame) => fingerprint.push(frame_hash(&frame)?),
+            // the duration is only an estimate for some containers
+            Err(_) if !fingerprint.is_empty() => break,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(fingerprint)
+}
+
+/// Temporary file to write `output` to before moving it into place, so that failures don't leave
+/// broken files in the cache.
+///
+/// It is unique, so that concurrent requests for the same output don't write into the same file.
+fn partial_file(output: &Path) -> Result<NamedTempFile> {
+    let folder = output
+        .parent()
+        .ok_or_else(|| anyhow!("No folder for {}", output.display()))?;
+    Ok(tempfile::Builder::new()
+        .suffix(".partial")
+        .tempfile_in(folder)?)
+}
+
+/// Crop a region out of an image or a video frame, writing it as PNG to `output`.
+///
+/// For videos, `time` (in milliseconds) selects the frame, defaulting to the first one.
+pub fn image_crop(
+    path: &Path,
+    output: &Path,
+    rectangle: &Rectangle,
+    time: Option<u64>,
+) -> Result<()> {
+    let frame = decode_frame(path, time.unwrap_or(0))?;
+    let cropped = crop_frame(&frame, rectangle)?;
+    let partial = partial_file(output)?;
+    write(partial.path(), encode_png(&cropped)?)?;
+    partial.persist(output)?;
+    Ok(())
+}
+
+/// Cut a time range (in milliseconds) out of a video, writing it as Matroska to `output`.
+///
+/// Packets are copied without re-encoding, so the clip starts at the keyframe at or before the
+/// start of the sequence.
+pub fn video_clip(path: &Path, output: &Path, sequence: &Sequence) -> Result<()> {
+    if sequence.end <= sequence.start {
+        return Err(anyhow!("Empty clip range {sequence:?}"));
+    }
+
+    let mut file = input(&path)?;
+    let video = file
+        .streams()
+        .best(Type::Video)
+        .ok_or_else(|| anyhow!("No video stream"))?
+        .index();
+
+    let partial = partial_file(output)?;
+    let mut clip = output_as(partial.path(), "matroska")?;
+
+    // map input streams to output streams, dropping data streams and the like
+    let mut mapping = vec![None; file.nb_streams() as usize];
+    let mut time_bases = vec![Rational(0, 1); file.nb_streams() as usize];
+    let mut streams = 0;
+    for (index, stream) in file.streams().enumerate() {
+        let medium = stream.parameters().medium();
+        if medium != Type::Audio && medium != Type::Video && medium != Type::Subtitle {
+            continue;
+        }
+        mapping[index] = Some(streams);
+        time_bases[index] = stream.time_base();
+        streams += 1;
+        let mut clip_stream = clip.add_stream(encoder::find(codec::Id::None))?;
+        clip_stream.set_parameters(stream.parameters());
+        // the codec tag of the input container may not be valid in matroska.
+        unsafe {
+            (*clip_stream.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+    }
+
+    clip.write_header()?;
+
+    // seek timestamps are in microseconds, this lands on the keyframe before the start.
+    let start = sequence.start as i64 * 1000;
+    file.seek(start, ..start)?;
+
+    for (stream, mut packet) in file.packets() {
+        let index = stream.index();
+        let Some(output_index) = mapping[index] else {
+            continue;
+        };
+
+        let time_base = f64::from(time_bases[index]);
+        let time = packet.pts().or(packet.dts()).unwrap_or(0) as f64 * time_base * 1000.0;
+        if time > sequence.end as f64 {
+            if index == video {
+                break;
+            }
+            continue;
+        }
+
+        let output_time_base = clip
+            .stream(output_index)
+            .ok_or_else(|| anyhow!("Missing output stream {output_index}"))?
+            .time_base();
+        packet.rescale_ts(time_bases[index], output_time_base);
+        packet.set_position(-1);
+        packet.set_stream(output_index);
+        packet.write_interleaved(&mut clip)?;
+    }
+
+    clip.write_trailer()?;
+    drop(clip);
+    partial.persist(output)?;
+    Ok(())
+}
+
+/// Video and audio encoders for a transcoding profile.
+///
+/// The external encoders are preferred, the builtin ones are used as fallback if ffmpeg was
+/// built without them.
+fn profile_encoders(profile: TranscodeProfile) -> Result<(Codec, Codec)> {
+    let (video, audio) = match profile {
+        TranscodeProfile::Webm => (
+            encoder::find_by_name("libvpx-vp9").or_else(|| encoder::find(codec::Id::VP9)),
+            encoder::find_by_name("libopus").or_else(|| encoder::find(codec::Id::OPUS)),
+        ),
+        TranscodeProfile::Mp4 => (
+            encoder::find_by_name("libx264").or_else(|| encoder::find(codec::Id::H264)),
+            encoder::find(codec::Id::AAC),
+        ),
+    };
+    let video = video.ok_or_else(|| anyhow!("No video encoder for {}", profile.name()))?;
+    let audio = audio.ok_or_else(|| anyhow!("No audio encoder for {}", profile.name()))?;
+    Ok((video, audio))
+}
+
+/// Width and height of a video scaled to `height`, keeping the aspect ratio.
+///
+/// Both are rounded down to even numbers, as YUV420P requires.
+pub fn scaled_size(width: u32, height: u32, target: Option<u32>) -> (u32, u32) {
+    let (width, height) = match target {
+        Some(target) if height > 0 => (
+            (u64::from(width) * u64::from(target) / u64::from(height)) as u32,
+            target,
+        ),
+        _ => (width, height),
+    };
+    ((width & !1).max(2), (height & !1).max(2))
+}
+
+/// Dimensions and duration of the best video stream of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Duration in milliseconds.
+    pub duration: u64,
+}
+
+pub fn stream_info(path: &Path) -> Result<StreamInfo> {
+    let file = input(&path)?;
+    let stream = file
+        .streams()
+        .best(Type::Video)
+        .ok_or_else(|| anyhow!("No video stream"))?;
+    let decoder = Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    // not all containers store the duration of streams, but the container duration is an estimate
+    let duration = if stream.duration() > 0 {
+        stream.duration() as f64 * f64::from(stream.time_base()) * 1000.0
+    } else {
+        file.duration().max(0) as f64 / 1000.0
+    };
+
+    Ok(StreamInfo {
+        width: decoder.width(),
+        height: decoder.height(),
+        duration: duration.ceil() as u64,
+    })
+}
+
+/// Time of a frame in milliseconds.
+fn frame_time(timestamp: Option<i64>, time_base: Rational) -> Option<f64> {
+    timestamp.map(|timestamp| timestamp as f64 * f64::from(time_base) * 1000.0)
+}
+
+/// How to transcode a video.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TranscodeOptions {
+    profile: TranscodeProfile,
+    /// Height to scale the video to, keeping the aspect ratio.
+    height: Option<u32>,
+    /// Time range to keep, in milliseconds.
+    window: Option<Sequence>,
+}
+
+/// Decodes the video stream, converts it to YUV420P and encodes it again.
+struct VideoTranscoder {
+    stream: usize,
+    time_base: Rational,
+    window: Option<Sequence>,
+    decoder: decoder::Video,
+    scaler: scaling::Context,
+    encoder: encoder::Video,
+    /// Set once a frame past the end of the window was decoded.
+    done: bool,
+}
+
+impl VideoTranscoder {
+    fn new(
+        input: &Input,
+        output: &mut Output,
+        codec: Codec,
+        options: &TranscodeOptions,
+    ) -> Result<Self> {
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or_else(|| anyhow!("No video stream"))?;
+        let decoder = Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+
+        let (width, height) = scaled_size(decoder.width(), decoder.height(), options.height);
+        let scaler = scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::YUV420P,
+            width,
+            height,
+            Flags::BILINEAR,
+        )?;
+
+        let mut encoder = Context::new().encoder().video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_format(Pixel::YUV420P);
+        encoder.set_time_base(stream.time_base());
+        encoder.set_frame_rate(Some(stream.avg_frame_rate()));
+        if output
+            .format()
+            .flags()
+            .contains(format::Flags::GLOBAL_HEADER)
+        {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        let mut codec_options = Dictionary::new();
+        match options.profile {
+            TranscodeProfile::Webm => {
+                codec_options.set("deadline", "realtime");
+                codec_options.set("cpu-used", "8");
+                codec_options.set("row-mt", "1");
+            }
+            TranscodeProfile::Mp4 => {
+                codec_options.set("preset", "veryfast");
+            }
+        }
+        let encoder = encoder.open_as_with(codec, codec_options)?;
+
+        let mut output_stream = output.add_stream(codec)?;
+        output_stream.set_parameters(&encoder);
+        output_stream.set_time_base(stream.time_base());
+
+        Ok(Self {
+            stream: stream.index(),
+            time_base: stream.time_base(),
+            window: options.window,
+            decoder,
+            scaler,
+            encoder,
+            done: false,
+        })
+    }
+
+    fn send_packet(&mut self, packet: &Packet) -> Result<()> {
+        self.decoder.send_packet(packet)?;
+        Ok(())
+    }
+
+    fn send_eof(&mut self) -> Result<()> {
+        self.decoder.send_eof()?;
+        Ok(())
+    }
+
+    /// Encode all frames the decoder has ready.
+    fn encode(&mut self) -> Result<()> {
+        let mut frame = Video::empty();
+        let mut scaled = Video::empty();
+        while self.decoder.receive_frame(&mut frame).is_ok() {
+            if let (Some(window), Some(time)) =
+                (&self.window, frame_time(frame.timestamp(), self.time_base))
+            {
+                if time >= window.end as f64 {
+                    self.done = true;
+                }
+                if self.done || time < window.start as f64 {
+                    continue;
+                }
+            }
+            self.scaler.run(&frame, &mut scaled)?;
+            scaled.set_pts(frame.timestamp());
+            scaled.set_kind(picture::Type::None);
+            self.encoder.send_frame(&scaled)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the audio stream, resamples it to 48kHz stereo and encodes it again.
+struct AudioTranscoder {
+    stream: usize,
+    time_base: Rational,
+    window: Option<Sequence>,
+    decoder: decoder::Audio,
+    filter: filter::Graph,
+    encoder: encoder::Audio,
+    samples: i64,
+}
+
+impl AudioTranscoder {
+    const RATE: i32 = 48000;
+
+    fn new(
+        input: &Input,
+        output: &mut Output,
+        codec: Codec,
+        options: &TranscodeOptions,
+    ) -> Result<Option<Self>> {
+        let Some(stream) = input.streams().best(Type::Audio) else {
+            return Ok(None);
+        };
+        let decoder = Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+
+        let sample_format = codec
+            .audio()?
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or_else(|| anyhow!("No sample format for {}", codec.name()))?;
+        let mut encoder = Context::new().encoder().audio()?;
+        encoder.set_rate(Self::RATE);
+        encoder.set_channel_layout(ChannelLayout::STEREO);
+        encoder.set_channels(ChannelLayout::STEREO.channels());
+        encoder.set_format(sample_format);
+        encoder.set_bit_rate(128_000);
+        encoder.set_time_base((1, Self::RATE));
+        if output
+            .format()
+            .flags()
+            .contains(format::Flags::GLOBAL_HEADER)
+        {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = encoder.open_as(codec)?;
+
+        let mut output_stream = output.add_stream(codec)?;
+        output_stream.set_parameters(&encoder);
+        output_stream.set_time_base((1, Self::RATE));
+
+        // some containers don't store the channel layout, only the number of channels
+        let layout = match decoder.channel_layout() {
+            layout if layout.is_empty() => ChannelLayout::default(decoder.channels().into()),
+            layout => layout,
+        };
+        let mut filter = filter::Graph::new();
+        let args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            stream.time_base(),
+            decoder.rate(),
+            decoder.format().name(),
+            layout.bits()
+        );
+        filter.add(
+            &filter::find("abuffer").ok_or_else(|| anyhow!("No abuffer filter"))?,
+            "in",
+            &args,
+        )?;
+        filter.add(
+            &filter::find("abuffersink").ok_or_else(|| anyhow!("No abuffersink filter"))?,
+            "out",
+            "",
+        )?;
+        {
+            let mut sink = filter
+                .get("out")
+                .ok_or_else(|| anyhow!("Missing filter output"))?;
+            sink.set_sample_format(encoder.format());
+            sink.set_channel_layout(encoder.channel_layout());
+            sink.set_sample_rate(encoder.rate());
+        }
+        filter.output("in", 0)?.input("out", 0)?.parse("anull")?;
+        filter.validate()?;
+
+        // most encoders need frames of a fixed number of samples
+        if !codec
+            .capabilities()
+            .contains(codec::Capabilities::VARIABLE_FRAME_SIZE)
+        {
+            if let Some(mut sink) = filter.get("out") {
+                sink.sink().set_frame_size(encoder.frame_size());
+            }
+        }
+
+        // output timestamps count samples, so they start where the window does
+        let start = options
+            .window
+            .as_ref()
+            .map(|window| window.start)
+            .unwrap_or(0);
+        Ok(Some(Self {
+            stream: stream.index(),
+            time_base: stream.time_base(),
+            window: options.window,
+            decoder,
+            filter,
+            encoder,
+            samples: start as i64 * i64::from(Self::RATE) / 1000,
+        }))
+    }
+
+    fn send_packet(&mut self, packet: &Packet) -> Result<()> {
+        self.decoder.send_packet(packet)?;
+        Ok(())
+    }
+
+    fn send_eof(&mut self) -> Result<()> {
+        self.decoder.send_eof()?;
+        self.encode_decoded()?;
+        self.filter
+            .get("in")
+            .ok_or_else(|| anyhow!("Missing filter input"))?
+            .source()
+            .flush()?;
+        Ok(())
+    }
+
+    /// Push all decoded frames through the filter.
+    fn encode_decoded(&mut self) -> Result<()> {
+        let mut frame = Audio::empty();
+        while self.decoder.receive_frame(&mut frame).is_ok() {
+            if let (Some(window), Some(time)) =
+                (&self.window, frame_time(frame.timestamp(), self.time_base))
+            {
+                if time < window.start as f64 || time >= window.end as f64 {
+                    continue;
+                }
+            }
+            frame.set_pts(frame.timestamp());
+            self.filter
+                .get("in")
+                .ok_or_else(|| anyhow!("Missing filter input"))?
+                .source()
+                .add(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// Encode all frames the filter has ready.
+    fn encode(&mut self) -> Result<()> {
+        self.encode_decoded()?;
+        let mut sink = self
+            .filter
+            .get("out")
+            .ok_or_else(|| anyhow!("Missing filter output"))?;
+        let mut filtered = Audio::empty();
+        while sink.sink().frame(&mut filtered).is_ok() {
+            filtered.set_pts(Some(self.samples));
+            self.samples += filtered.samples() as i64;
+            self.encoder.send_frame(&filtered)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write all packets an encoder has ready to the output.
+fn write_encoded(encoder: &mut encoder::Encoder, output: &mut Output, index: usize) -> Result<()> {
+    let time_base = encoder.time_base();
+    let output_time_base = output
+        .stream(index)
+        .ok_or_else(|| anyhow!("Missing output stream {index}"))?
+        .time_base();
+    let mut packet = Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        packet.set_stream(index);
+        packet.rescale_ts(time_base, output_time_base);
+        packet.set_position(-1);
+        packet.write_interleaved(output)?;
+    }
+    Ok(())
+}
+
+/// Transcode the best video and audio streams of `file` into `output`.
+fn transcode(
+    file: &mut Input,
+    output: &mut Output,
+    options: &TranscodeOptions,
+    header: Dictionary,
+) -> Result<()> {
+    let (video_codec, audio_codec) = profile_encoders(options.profile)?;
+    let mut video = VideoTranscoder::new(file, output, video_codec, options)?;
+    let mut audio = AudioTranscoder::new(file, output, audio_codec, options)?;
+    output.write_header_with(header)?;
+
+    // seek timestamps are in microseconds, this lands on the keyframe before the start.
+    if let Some(window) = &options.window {
+        let start = window.start as i64 * 1000;
+        file.seek(start, ..start)?;
+    }
+
+    for (stream, packet) in file.packets() {
+        if stream.index() == video.stream {
+            video.send_packet(&packet)?;
+            video.encode()?;
+            write_encoded(&mut video.encoder, output, 0)?;
+            if video.done {
+                break;
+            }
+        } else if let Some(audio) = audio
+            .as_mut()
+            .filter(|audio| audio.stream == stream.index())
+        {
+            audio.send_packet(&packet)?;
+            audio.encode()?;
+            write_encoded(&mut audio.encoder, output, 1)?;
+        }
+    }
+
+    video.send_eof()?;
+    video.encode()?;
+    video.encoder.send_eof()?;
+    write_encoded(&mut video.encoder, output, 0)?;
+    if let Some(audio) = audio.as_mut() {
+        audio.send_eof()?;
+        audio.encode()?;
+        audio.encoder.send_eof()?;
+        write_encoded(&mut audio.encoder, output, 1)?;
+    }
+
+    output.write_trailer()?;
+    Ok(())
+}
+
+/// Transcode a video into a format browsers can play, writing it to `output`.
+///
+/// Only the best video and audio streams are kept, audio is resampled to 48kHz stereo.
+pub fn video_transcode(path: &Path, output: &Path, profile: TranscodeProfile) -> Result<()> {
+    let mut file = input(&path)?;
+    let partial = partial_file(output)?;
+    let mut transcoded = output_as(partial.path(), profile.name())?;
+
+    // move the index to the front of mp4 files, so that they can be streamed
+    let mut header = Dictionary::new();
+    if profile == TranscodeProfile::Mp4 {
+        header.set("movflags", "+faststart");
+    }
+
+    let options = TranscodeOptions {
+        profile,
+        height: None,
+        window: None,
+    };
+    transcode(&mut file, &mut transcoded, &options, header)?;
+    drop(transcoded);
+    partial.persist(output)?;
+    Ok(())
+}
+
+/// Encode a time range (in milliseconds) of a video as an MPEG-TS segment for HLS, scaled to
+/// the given height.
+///
+/// Timestamps are kept from the original video, so that consecutive segments line up.
+pub fn video_segment(path: &Path, output: &Path, height: u32, sequence: &Sequence) -> Result<()> {
+    if sequence.end <= sequence.start {
+        return Err(anyhow!("Empty segment range {sequence:?}"));
+    }
+
+    let mut file = input(&path)?;
+    let partial = partial_file(output)?;
+    let mut segment = output_as(partial.path(), "mpegts")?;
+    let options = TranscodeOptions {
+        profile: TranscodeProfile::Mp4,
+        height: Some(height),
+        window: Some(*sequence),
+    };
+    transcode(&mut file, &mut segment, &options, Dictionary::new())?;
+    drop(segment);
+    partial.persist(output)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::similar::{distance, fingerprint_clusters};
+    use cindy_common::Point;
     use serde::Deserialize;
     use std::fs::read_to_string;
+    use tempfile::tempdir;
 
     #[derive(Deserialize)]
     struct Samples {
@@ -383,4 +1152,141 @@ mod tests {
             let _debug = format!("{:?}", sample.info);
         }
     }
+
+    #[test]
+    fn image_crop_samples() {
+        let dir = tempdir().unwrap();
+        let rectangle = Rectangle {
+            start: Point::new(10, 20),
+            end: Point::new(60, 50),
+        };
+        for (file, time) in [("image1.jpg", None), ("video1.mkv", Some(2000))] {
+            let output = dir.path().join(format!("{file}.png"));
+            image_crop(&Path::new("samples").join(file), &output, &rectangle, time).unwrap();
+            let MediaInfo::Image(info) = media_info(&output).unwrap() else {
+                panic!("{file} crop is not an image");
+            };
+            assert_eq!(info.width, 50, "{file} crop width");
+            assert_eq!(info.height, 30, "{file} crop height");
+        }
+    }
+
+    #[test]
+    fn perceptual_hash_samples() {
+        let hash = |file| perceptual_hash(&Path::new("samples").join(file)).unwrap();
+        let image1 = hash("image1.jpg");
+
+        // re-encoded as PNG, with a few pixels cropped off
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("image1.png");
+        let rectangle = Rectangle {
+            start: Point::new(2, 2),
+            end: Point::new(298, 298),
+        };
+        image_crop(Path::new("samples/image1.jpg"), &output, &rectangle, None).unwrap();
+        assert!(distance(image1, perceptual_hash(&output).unwrap()) <= 4);
+
+        for file in ["image2.jpg", "image3.jpg"] {
+            assert!(distance(image1, hash(file)) > 10, "{file} is different");
+        }
+    }
+
+    #[test]
+    fn video_fingerprint_samples() {
+        let video = Path::new("samples/video1.mkv");
+        let dir = tempdir().unwrap();
+        let transcoded = dir.path().join("transcoded.webm");
+        video_transcode(video, &transcoded, TranscodeProfile::Webm).unwrap();
+        let trimmed = dir.path().join("trimmed.mkv");
+        let sequence = Sequence {
+            start: 4000,
+            end: 13000,
+        };
+        video_clip(video, &trimmed, &sequence).unwrap();
+
+        let fingerprints = [
+            video,
+            transcoded.as_path(),
+            trimmed.as_path(),
+            Path::new("samples/video2.avi"),
+        ]
+        .map(|path| video_fingerprint(path).unwrap());
+        assert!(fingerprints[0].len() > 1);
+        assert_eq!(fingerprint_clusters(&fingerprints, 10), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn image_crop_out_of_bounds() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("crop.png");
+        let rectangle = Rectangle {
+            start: Point::new(250, 250),
+            end: Point::new(350, 350),
+        };
+        let result = image_crop(Path::new("samples/image1.jpg"), &output, &rectangle, None);
+        assert!(result.is_err());
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn video_clip_samples() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("clip.mkv");
+        let sequence = Sequence {
+            start: 2000,
+            end: 5000,
+        };
+        video_clip(Path::new("samples/video1.mkv"), &output, &sequence).unwrap();
+        let MediaInfo::Video(info) = media_info(&output).unwrap() else {
+            panic!("clip is not a video");
+        };
+        assert_eq!(info.width, 640);
+        assert_eq!(info.height, 360);
+        assert!(info.duration < 13);
+    }
+
+    #[test]
+    fn video_transcode_sample() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("video1.webm");
+        video_transcode(
+            Path::new("samples/video1.avi"),
+            &output,
+            TranscodeProfile::Webm,
+        )
+        .unwrap();
+        let MediaInfo::Video(info) = media_info(&output).unwrap() else {
+            panic!("transcode is not a video");
+        };
+        assert_eq!(info.format, VideoFormat::Mkv);
+        assert_eq!(info.width, 480);
+        assert_eq!(info.height, 270);
+        // the temporary file was moved into place
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn stream_info_sample() {
+        let info = stream_info(Path::new("samples/video1.mkv")).unwrap();
+        assert_eq!(info.width, 640);
+        assert_eq!(info.height, 360);
+        assert!(info.duration > 0);
+    }
+
+    #[test]
+    fn video_segment_sample() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("1.ts");
+        let sequence = Sequence {
+            start: 6000,
+            end: 12000,
+        };
+        video_segment(Path::new("samples/video1.mkv"), &output, 180, &sequence).unwrap();
+        let MediaInfo::Video(info) = media_info(&output).unwrap() else {
+            panic!("segment is not a video");
+        };
+        assert_eq!(info.format, VideoFormat::Ts);
+        assert_eq!(info.width, 320);
+        assert_eq!(info.height, 180);
+    }
 }
@@ -1,20 +1,24 @@
 use crate::Cindy;
-use axum::Router;
+use axum::{middleware::from_fn_with_state, routing::get, Router};
 
 mod api;
+mod auth;
 mod error;
 mod frontend;
+mod range;
 
 use error::Error;
 
-fn router() -> Router<Cindy> {
-    Router::new()
-        .nest("/api/v1", api::router())
-        .merge(frontend::router())
+fn router(cindy: &Cindy) -> Router<Cindy> {
+    let api = api::router()
+        .route("/auth/user", get(auth::current_user))
+        .route_layer(from_fn_with_state(cindy.clone(), auth::authenticate))
+        .merge(auth::router());
+    Router::new().nest("/api/v1", api).merge(frontend::router())
 }
 
 impl Cindy {
     pub fn router(&self) -> Router {
-        router().with_state(self.clone())
+        router(self).with_state(self.clone())
     }
 }
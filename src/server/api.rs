@@ -1,9 +1,25 @@
 use crate::{server::Error, Cindy};
-use axum::Router;
+use axum::{Extension, Router};
+use cindy_common::{Origin, User};
 
+mod batch;
+mod duplicates;
+mod events;
 mod file;
+mod history;
+mod jobs;
 mod query;
+mod similar;
 mod tags;
+mod upload;
+
+/// Origin of changes made by a request, the user is only known if authentication is enabled.
+fn origin(user: Option<Extension<User>>) -> Origin {
+    match user {
+        Some(Extension(user)) => Origin::User { name: user.name },
+        None => Origin::Api,
+    }
+}
 
 async fn not_found() -> Error {
     Error::NotFound
@@ -14,5 +30,12 @@ pub fn router() -> Router<Cindy> {
         .nest("/file", file::router())
         .nest("/query", query::router())
         .merge(tags::router())
+        .merge(events::router())
+        .merge(upload::router())
+        .merge(jobs::router())
+        .merge(batch::router())
+        .merge(history::router())
+        .merge(similar::router())
+        .merge(duplicates::router())
         .fallback(not_found)
 }
@@ -0,0 +1,57 @@
+use super::origin;
+use crate::{
+    history::{check, mutations, Journal},
+    server::Error,
+    Cindy,
+};
+use axum::{extract::State, routing::post, Extension, Json, Router};
+use cindy_common::{BatchOperation, BatchResponse, BatchResult, User};
+use tokio::task::spawn_blocking;
+
+/// Apply operations in order within one transaction, which is only committed if all succeed.
+async fn batch(
+    State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
+    Json(operations): Json<Vec<BatchOperation>>,
+) -> Result<Json<BatchResponse>, Error> {
+    let mut database = cindy.database().await;
+    let response = spawn_blocking(move || {
+        let transaction = database.transaction()?;
+        let mut journal = Journal::new(&transaction, origin(user));
+        let mut response = BatchResponse::default();
+        let mut failed = false;
+        for operation in &operations {
+            if failed {
+                response.results.push(BatchResult::Skipped);
+                continue;
+            }
+            match check(&transaction, operation).and_then(|()| journal.apply(operation)) {
+                Ok(()) => response.results.push(BatchResult::Applied),
+                Err(error) => {
+                    failed = true;
+                    response.results.push(BatchResult::Failed {
+                        error: format!("{error:#}"),
+                    });
+                }
+            }
+        }
+
+        // dropping the transaction rolls back everything
+        if !failed {
+            transaction.commit()?;
+            response.committed = true;
+            response.mutations = mutations(&operations);
+        }
+        Ok::<_, Error>(response)
+    })
+    .await??;
+
+    for mutation in &response.mutations {
+        cindy.mutation(mutation.clone());
+    }
+    Ok(Json(response))
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new().route("/batch", post(batch))
+}
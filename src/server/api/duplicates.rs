@@ -0,0 +1,10 @@
+use crate::{common::Duplicate, server::Error, Cindy};
+use axum::{extract::State, routing::get, Json, Router};
+
+async fn duplicates(State(cindy): State<Cindy>) -> Result<Json<Vec<Duplicate>>, Error> {
+    Ok(Json(cindy.duplicates().await?))
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new().route("/duplicates", get(duplicates))
+}
@@ -0,0 +1,32 @@
+use crate::Cindy;
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    BoxError, Router,
+};
+use futures::stream::{self, Stream};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Name of the event sent when a subscriber was too slow and missed mutations.
+const EVENT_LAGGED: &str = "lagged";
+
+/// Stream of mutations as server-sent events.
+///
+/// Clients that fall behind get a `lagged` event instead of the mutations they missed, and should
+/// treat everything as changed.
+async fn events(State(cindy): State<Cindy>) -> Sse<impl Stream<Item = Result<Event, BoxError>>> {
+    let stream = stream::unfold(cindy.mutations(), |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(mutation) => Event::default().json_data(mutation).map_err(Into::into),
+            Err(RecvError::Lagged(_)) => Ok(Event::default().event(EVENT_LAGGED).data("")),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event, receiver))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new().route("/events", get(events))
+}
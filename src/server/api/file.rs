@@ -1,51 +1,411 @@
-use crate::{hash::ArcHash, server::Error, Cindy};
+use super::origin;
+use crate::{
+    hash::{ArcHash, BoxHash, Hash},
+    history::Journal,
+    server::{range::Ranges, Error},
+    similar::{DEFAULT_DISTANCE, MAX_DISTANCE},
+    Cindy, TagFilter,
+};
 use axum::{
-    body::StreamBody,
+    body::{Bytes, StreamBody},
     extract::{Path, Query, State},
-    http::{header, HeaderValue},
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, patch},
+    Extension, Json, Router,
+};
+use cindy_common::{api::*, AttributesEdit, BatchOperation, Mutation, User};
+#[cfg(feature = "ffmpeg")]
+use cindy_common::{Point, Rectangle, Sequence};
+use futures::{
+    future::ready,
+    stream::{self, StreamExt},
+};
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, Take},
+    task::spawn_blocking,
 };
-use cindy_common::api::*;
-use std::path::PathBuf;
-use tokio::{fs::File, task::spawn_blocking};
 use tokio_util::io::ReaderStream;
 
+/// Files are content-addressed and never change, so they can be cached indefinitely.
+const CACHE_CONTROL_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Path to read the contents of a file from, files which are not in the index are not found.
+async fn file_path(cindy: &Cindy, hash: &Hash) -> Result<PathBuf, Error> {
+    let database = cindy.database_read().await?;
+    let hash_clone = BoxHash::from(hash);
+    if !spawn_blocking(move || database.hash_exists(&hash_clone)).await?? {
+        return Err(Error::NotFound);
+    }
+    cindy.file_path(hash).await?.ok_or(Error::NotFound)
+}
+
 async fn stream_file(
     State(cindy): State<Cindy>,
     Path(hash): Path<ArcHash>,
-) -> Result<impl IntoResponse, Error> {
-    // get filenames
-    let database = cindy.database().await;
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    // the hash identifies the content, so it makes for a strong etag
+    let etag = HeaderValue::from_str(&format!("\"{hash}\"")).unwrap();
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(CACHE_CONTROL_IMMUTABLE),
+        ),
+    ];
+
+    // get detected mime type and filenames, unknown files are not found even if the client
+    // claims to have them cached
+    let database = cindy.database_read().await?;
     let hash_clone = hash.clone();
-    let tags =
-        spawn_blocking(move || database.hash_tags(&hash_clone, Some("filename"), None)).await??;
+    let tags = spawn_blocking(move || match database.hash_exists(&hash_clone)? {
+        true => database.hash_tags(&hash_clone, None, None).map(Some),
+        false => Ok(None),
+    })
+    .await??
+    .ok_or(Error::NotFound)?;
+    let path = file_path(&cindy, &hash).await?;
+
+    // client already has this file cached
+    let if_none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if let Some(if_none_match) = if_none_match {
+        let matches = if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/").as_bytes() == etag.as_bytes());
+        if matches {
+            return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+        }
+    }
 
-    // pick content type based on whatever filename the file is tagged with,
-    // defaulting to application/octet-stream.
+    // prefer the mime type detected when scanning, falling back to guessing it from whatever
+    // filename the file is tagged with, defaulting to application/octet-stream.
     let content_type = tags
-        .into_iter()
-        .map(|name| PathBuf::from(name.value()))
-        .find(|path| path.extension().is_some())
-        .and_then(|path| {
-            mime_guess::from_path(path)
-                .first_raw()
-                .map(HeaderValue::from_static)
+        .iter()
+        .filter(|tag| tag.name() == "mime")
+        .find_map(|tag| HeaderValue::from_str(tag.value()).ok())
+        .or_else(|| {
+            tags.iter()
+                .filter(|tag| tag.name() == "filename")
+                .map(|tag| PathBuf::from(tag.value()))
+                .find(|path| path.extension().is_some())
+                .and_then(|path| {
+                    mime_guess::from_path(path)
+                        .first_raw()
+                        .map(HeaderValue::from_static)
+                })
         })
         .unwrap_or_else(|| HeaderValue::from_str(mime::APPLICATION_OCTET_STREAM.as_ref()).unwrap());
 
-    // get path to file on disk
-    let path = cindy.hash_path(&hash);
+    let response = stream_path(&path, content_type, &headers).await?;
+    Ok((cache_headers, response).into_response())
+}
+
+/// Stream a file from disk with the given content type.
+///
+/// Supports single and multiple byte ranges, if requested by the `Range` header.
+async fn stream_path(
+    path: &std::path::Path,
+    content_type: HeaderValue,
+    headers: &HeaderMap,
+) -> Result<Response, Error> {
+    let file = File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+
+    let response = match Ranges::parse(range, size) {
+        Ranges::Full => {
+            let headers = [
+                (header::CONTENT_TYPE, content_type),
+                (header::CONTENT_LENGTH, HeaderValue::from(size)),
+            ];
+            (headers, StreamBody::new(ReaderStream::new(file))).into_response()
+        }
+        Ranges::Unsatisfiable => {
+            let content_range = HeaderValue::from_str(&format!("bytes */{size}")).unwrap();
+            (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, content_range)],
+            )
+                .into_response()
+        }
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            let content_range = format!("bytes {}-{}/{size}", range.start, range.end - 1);
+            let headers = [
+                (header::CONTENT_TYPE, content_type),
+                (
+                    header::CONTENT_LENGTH,
+                    HeaderValue::from(range.end - range.start),
+                ),
+                (
+                    header::CONTENT_RANGE,
+                    HeaderValue::from_str(&content_range).unwrap(),
+                ),
+            ];
+            let body = StreamBody::new(range_stream(file, range).await?);
+            (StatusCode::PARTIAL_CONTENT, headers, body).into_response()
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = multipart_boundary();
+            let mut parts = Vec::new();
+            let mut length = 0;
+            for range in ranges {
+                let part_header = format!(
+                    "\r\n--{boundary}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{size}\r\n\r\n",
+                    content_type.to_str().unwrap_or_default(),
+                    range.start,
+                    range.end - 1
+                );
+                length += part_header.len() as u64 + range.end - range.start;
+                parts.push(stream::once(ready(Ok(Bytes::from(part_header)))).boxed());
+                let file = File::open(path).await?;
+                parts.push(range_stream(file, range).await?.boxed());
+            }
+            let trailer = format!("\r\n--{boundary}--\r\n");
+            length += trailer.len() as u64;
+            parts.push(stream::once(ready(Ok(Bytes::from(trailer)))).boxed());
+
+            let content_type = format!("multipart/byteranges; boundary={boundary}");
+            let headers = [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_str(&content_type).unwrap(),
+                ),
+                (header::CONTENT_LENGTH, HeaderValue::from(length)),
+            ];
+            let body = StreamBody::new(stream::iter(parts).flatten());
+            (StatusCode::PARTIAL_CONTENT, headers, body).into_response()
+        }
+    };
+
+    let headers = [(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"))];
+    Ok((headers, response).into_response())
+}
+
+/// Stream a byte range of a file.
+async fn range_stream(
+    mut file: File,
+    range: Range<u64>,
+) -> Result<ReaderStream<Take<File>>, Error> {
+    file.seek(SeekFrom::Start(range.start)).await?;
+    Ok(ReaderStream::new(file.take(range.end - range.start)))
+}
+
+/// Generate a boundary for multipart responses.
+fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("cindy-{nanos:032x}")
+}
+
+#[cfg(feature = "ffmpeg")]
+async fn file_crop(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+    Query(query): Query<CropQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let rectangle = Rectangle {
+        start: Point::new(query.x1, query.y1),
+        end: Point::new(query.x2, query.y2),
+    };
+    let mut name = format!(
+        "{hash}-crop-{}-{}-{}-{}",
+        query.x1, query.y1, query.x2, query.y2
+    );
+    if let Some(time) = query.time {
+        name.push_str(&format!("-{time}"));
+    }
+    let output = cindy.thumbs_path().join(format!("{name}.png"));
+    let input = file_path(&cindy, &hash).await?;
+
+    // only generate crop if it is not cached yet, checking again once we get a slot because
+    // another request might have generated it while we were waiting.
+    if !tokio::fs::try_exists(&output).await? {
+        let _permit = cindy.crop_permit().await;
+        if !tokio::fs::try_exists(&output).await? {
+            let output = output.clone();
+            spawn_blocking(move || {
+                crate::media::image_crop(&input, &output, &rectangle, query.time)
+            })
+            .await??;
+        }
+    }
+
+    stream_path(&output, HeaderValue::from_static("image/png"), &headers).await
+}
+
+#[cfg(feature = "ffmpeg")]
+async fn file_clip(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+    Query(query): Query<ClipQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let sequence = Sequence {
+        start: query.start,
+        end: query.end,
+    };
+    let output = cindy
+        .thumbs_path()
+        .join(format!("{hash}-clip-{}-{}.mkv", query.start, query.end));
+    let input = file_path(&cindy, &hash).await?;
+
+    // only generate clip if it is not cached yet, checking again once we get a slot
+    if !tokio::fs::try_exists(&output).await? {
+        let _permit = cindy.crop_permit().await;
+        if !tokio::fs::try_exists(&output).await? {
+            let output = output.clone();
+            spawn_blocking(move || crate::media::video_clip(&input, &output, &sequence)).await??;
+        }
+    }
+
+    stream_path(
+        &output,
+        HeaderValue::from_static("video/x-matroska"),
+        &headers,
+    )
+    .await
+}
+
+#[cfg(feature = "ffmpeg")]
+async fn file_transcode(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+    Query(query): Query<TranscodeQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let profile = query.profile;
+    let output = cindy
+        .thumbs_path()
+        .join(format!("{hash}-transcode-{0}.{0}", profile.name()));
+    let input = file_path(&cindy, &hash).await?;
+
+    // only transcode if it is not cached yet, checking again once we get a slot because another
+    // request might have transcoded it while we were waiting.
+    if !tokio::fs::try_exists(&output).await? {
+        let _permit = cindy.transcode_permit().await;
+        if !tokio::fs::try_exists(&output).await? {
+            let output = output.clone();
+            spawn_blocking(move || crate::media::video_transcode(&input, &output, profile))
+                .await??;
+        }
+    }
 
-    // open file and turn into stream
-    let file = File::open(&path).await?;
-    let stream = ReaderStream::new(file);
-    let body = StreamBody::new(stream);
+    stream_path(&output, HeaderValue::from_static(profile.mime()), &headers).await
+}
 
-    // return headers and stream
-    let headers = [(header::CONTENT_TYPE, content_type)];
-    Ok((headers, body))
+/// Dimensions and duration of a video, which are only probed once.
+#[cfg(feature = "ffmpeg")]
+async fn stream_info(
+    cindy: &Cindy,
+    hash: &ArcHash,
+    input: &std::path::Path,
+) -> Result<crate::media::StreamInfo, Error> {
+    let (cindy, hash, input) = (cindy.clone(), hash.clone(), input.to_path_buf());
+    let info = spawn_blocking(move || {
+        cindy
+            .hls()
+            .stream_info(&hash, || crate::media::stream_info(&input))
+    })
+    .await??;
+    Ok(info)
+}
+
+#[cfg(feature = "ffmpeg")]
+async fn file_hls_master(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+) -> Result<impl IntoResponse, Error> {
+    let input = file_path(&cindy, &hash).await?;
+    let info = stream_info(&cindy, &hash, &input).await?;
+    let renditions = crate::hls::renditions(&cindy.config().hls.renditions, &info);
+    Ok((
+        [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+        crate::hls::master_playlist(&renditions),
+    ))
+}
+
+/// Serves the playlist (`index.m3u8`) and the segments (`{index}.ts`) of one rendition.
+#[cfg(feature = "ffmpeg")]
+async fn file_hls(
+    State(cindy): State<Cindy>,
+    Path((hash, height, file)): Path<(ArcHash, u32, String)>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let input = file_path(&cindy, &hash).await?;
+    let info = stream_info(&cindy, &hash, &input).await?;
+    let config = &cindy.config().hls;
+    let renditions = crate::hls::renditions(&config.renditions, &info);
+    if !renditions
+        .iter()
+        .any(|rendition| rendition.height == height)
+    {
+        return Err(Error::NotFound);
+    }
+
+    let segment = config.segment * 1000;
+    if file == "index.m3u8" {
+        let headers = [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")];
+        let playlist = crate::hls::media_playlist(info.duration, segment);
+        return Ok((headers, playlist).into_response());
+    }
+
+    let index: u64 = file
+        .strip_suffix(".ts")
+        .and_then(|index| index.parse().ok())
+        .ok_or(Error::NotFound)?;
+    if index >= crate::hls::segment_count(info.duration, segment) {
+        return Err(Error::NotFound);
+    }
+
+    let output = cindy
+        .hls_path()
+        .join(hash.to_string())
+        .join(height.to_string())
+        .join(format!("{index}.ts"));
+
+    // only generate segment if it is not cached yet, checking again once we get a slot because
+    // another request might have generated it while we were waiting.
+    if tokio::fs::try_exists(&output).await? {
+        let output = output.clone();
+        spawn_blocking(move || crate::hls::touch(&output)).await??;
+    } else {
+        let _permit = cindy.transcode_permit().await;
+        if !tokio::fs::try_exists(&output).await? {
+            let (cindy, cache) = (cindy.clone(), cindy.hls_path());
+            let limit = config.cache_size;
+            let output = output.clone();
+            let sequence = Sequence {
+                start: index * segment,
+                end: ((index + 1) * segment).min(info.duration),
+            };
+            spawn_blocking(move || {
+                std::fs::create_dir_all(output.parent().unwrap())?;
+                crate::media::video_segment(&input, &output, height, &sequence)?;
+                cindy.hls().added(&cache, limit, &output)?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .await??;
+        }
+    }
+
+    stream_path(&output, HeaderValue::from_static("video/mp2t"), &headers).await
 }
 
 async fn file_tags(
@@ -54,7 +414,7 @@ async fn file_tags(
     Query(query): Query<TagQuery<String>>,
 ) -> Result<impl IntoResponse, Error> {
     // get filenames
-    let database = cindy.database().await;
+    let database = cindy.database_read().await?;
     let tags = spawn_blocking(move || {
         database.hash_tags(&hash, query.name.as_deref(), query.value.as_deref())
     })
@@ -65,45 +425,81 @@ async fn file_tags(
 
 async fn file_tag_create(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Path(hash): Path<ArcHash>,
     Json(request): Json<FileTagCreateBody<'static>>,
 ) -> Result<(), Error> {
-    // get filenames
-    let database = cindy.database().await;
-    spawn_blocking(move || database.hash_tag_add(&hash, &request.name, &request.value)).await??;
+    let mut database = cindy.database().await;
+    let mutation = Mutation::FileTags {
+        file: Some((&*hash).into()),
+        name: Some(request.name.to_string()),
+        value: Some(request.value.to_string()),
+    };
+    let operation = BatchOperation::FileTagAdd {
+        file: (&*hash).into(),
+        name: request.name.into(),
+        value: request.value.into(),
+    };
+    spawn_blocking(move || {
+        let transaction = database.transaction()?;
+        Journal::new(&transaction, origin(user)).apply(&operation)?;
+        transaction.commit()?;
+        Ok::<_, Error>(())
+    })
+    .await??;
 
+    cindy.mutation(mutation);
     Ok(())
 }
 
 async fn file_tag_delete(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Query(query): Query<TagQuery<String>>,
     Path(hash): Path<ArcHash>,
 ) -> Result<(), Error> {
-    // get filenames
-    let database = cindy.database().await;
-    let hash_clone = hash.clone();
+    let mut database = cindy.database().await;
+    let mutation = Mutation::FileTags {
+        file: Some((&*hash).into()),
+        name: query.name.clone(),
+        value: query.value.clone(),
+    };
+    let operation = BatchOperation::FileTagRemove {
+        file: (&*hash).into(),
+        name: query.name,
+        value: query.value,
+    };
     spawn_blocking(move || {
-        database.hash_tag_remove(&hash_clone, query.name.as_deref(), query.value.as_deref())
+        let transaction = database.transaction()?;
+        Journal::new(&transaction, origin(user)).apply(&operation)?;
+        transaction.commit()?;
+        Ok::<_, Error>(())
     })
     .await??;
 
+    cindy.mutation(mutation);
     Ok(())
 }
 
 async fn file_labels(
     State(cindy): State<Cindy>,
     Path(hash): Path<ArcHash>,
-    Query(query): Query<TagQuery<String>>,
+    Query(query): Query<LabelQuery<String>>,
 ) -> Result<impl IntoResponse, Error> {
-    // get filenames
-    let database = cindy.database().await;
+    // only filter by attribute if it was requested
+    let attribute = match (&query.attribute_name, &query.attribute_value) {
+        (None, None) => None,
+        (name, value) => Some(TagFilter::new(name.clone(), value.clone())),
+    };
+
+    let database = cindy.database_read().await?;
     let labels = spawn_blocking(move || {
         database.label_get(
             Some(&hash),
             query.name.as_deref(),
             query.value.as_deref(),
             None,
+            attribute.as_ref(),
         )
     })
     .await??;
@@ -111,6 +507,93 @@ async fn file_labels(
     Ok(Json(labels))
 }
 
+async fn file_label_attributes_edit(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+    Json(request): Json<LabelAttributesEditRequest>,
+) -> Result<(), Error> {
+    let mut database = cindy.database().await;
+    let mutation = Mutation::Labels {
+        file: Some((&*hash).into()),
+        name: Some(request.tag.name().to_string()),
+        value: Some(request.tag.value().to_string()),
+    };
+    spawn_blocking(move || {
+        let transaction = database.transaction()?;
+        let (name, value) = (request.tag.name(), request.tag.value());
+        for (attribute, attribute_value) in &request.attributes {
+            match attribute_value {
+                Some(attribute_value) => transaction.label_attribute_set(
+                    &hash,
+                    name,
+                    value,
+                    &request.label,
+                    attribute,
+                    attribute_value,
+                )?,
+                None => transaction.label_attribute_remove(
+                    &hash,
+                    name,
+                    value,
+                    &request.label,
+                    Some(attribute),
+                )?,
+            }
+        }
+        transaction.commit()?;
+        Ok::<_, Error>(())
+    })
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
+}
+
+async fn file_attributes(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+) -> Result<impl IntoResponse, Error> {
+    let database = cindy.database_read().await?;
+    let attributes = spawn_blocking(move || database.file_attributes(&hash)).await??;
+    Ok(Json(attributes))
+}
+
+async fn file_similar(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+    Query(query): Query<SimilarFiles>,
+) -> Result<Json<Vec<BoxHash>>, Error> {
+    let distance = query.distance.unwrap_or(DEFAULT_DISTANCE).min(MAX_DISTANCE);
+    Ok(Json(cindy.similar_to(&hash, distance).await?))
+}
+
+async fn file_attributes_edit(
+    State(cindy): State<Cindy>,
+    Path(hash): Path<ArcHash>,
+    Json(attributes): Json<AttributesEdit>,
+) -> Result<(), Error> {
+    let mut database = cindy.database().await;
+    let mutation = Mutation::FileAttributes {
+        file: Some((&*hash).into()),
+    };
+    spawn_blocking(move || {
+        let transaction = database.transaction()?;
+        if !transaction.hash_exists(&hash)? {
+            return Err(Error::NotFound);
+        }
+        for (name, value) in &attributes {
+            match value {
+                Some(value) => transaction.file_attribute_set(&hash, name, value)?,
+                None => transaction.file_attribute_remove(&hash, Some(name))?,
+            }
+        }
+        transaction.commit()?;
+        Ok::<_, Error>(())
+    })
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
+}
+
 async fn file_label_delete() {}
 
 async fn file_label_create() {}
@@ -128,4 +611,29 @@ pub fn router() -> Router<Cindy> {
                 .delete(file_label_delete)
                 .post(file_label_create),
         )
+        .route(
+            "/:hash/labels/attributes",
+            patch(file_label_attributes_edit),
+        )
+        .route(
+            "/:hash/attributes",
+            get(file_attributes).patch(file_attributes_edit),
+        )
+        .route("/:hash/similar", get(file_similar))
+        .merge(media_router())
+}
+
+#[cfg(feature = "ffmpeg")]
+fn media_router() -> Router<Cindy> {
+    Router::new()
+        .route("/:hash/crop", get(file_crop))
+        .route("/:hash/clip", get(file_clip))
+        .route("/:hash/transcode", get(file_transcode))
+        .route("/:hash/hls/index.m3u8", get(file_hls_master))
+        .route("/:hash/hls/:height/:file", get(file_hls))
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn media_router() -> Router<Cindy> {
+    Router::new()
 }
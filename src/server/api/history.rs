@@ -0,0 +1,77 @@
+use crate::{
+    history::{mutations, revert},
+    server::Error,
+    Cindy,
+};
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use cindy_common::{api::History, ChangeBatch};
+use serde_qs::axum::QsQuery as Query;
+use tokio::task::spawn_blocking;
+
+/// Batches returned if the request does not set a limit.
+const HISTORY_LIMIT: u64 = 100;
+
+async fn history(
+    State(cindy): State<Cindy>,
+    Query(query): Query<History>,
+) -> Result<Json<Vec<ChangeBatch>>, Error> {
+    let database = cindy.database_read().await?;
+    let limit = query.limit.unwrap_or(HISTORY_LIMIT);
+    spawn_blocking(move || database.journal_batches(limit, query.reverted))
+        .await?
+        .map(Json)
+        .map_err(Into::into)
+}
+
+/// Changes to revert, either a whole batch or a single change.
+enum Revert {
+    Batch(u64),
+    Change(u64),
+}
+
+/// Revert changes in one transaction and publish the mutations this caused.
+///
+/// Reverting fails with a conflict if later changes got in the way.
+async fn revert_changes(cindy: &Cindy, target: Revert) -> Result<(), Error> {
+    let mut database = cindy.database().await;
+    let applied = spawn_blocking(move || {
+        let transaction = database.transaction()?;
+        let changes = match target {
+            Revert::Batch(id) => transaction.journal_batch(id)?.changes,
+            Revert::Change(id) => vec![transaction.journal_change(id)?],
+        };
+        let applied = revert(&transaction, &changes).map_err(|_| Error::Conflict)?;
+        transaction.commit()?;
+        Ok::<_, Error>(applied)
+    })
+    .await??;
+    for mutation in mutations(&applied) {
+        cindy.mutation(mutation);
+    }
+    Ok(())
+}
+
+async fn history_batch_revert(
+    State(cindy): State<Cindy>,
+    Path(id): Path<u64>,
+) -> Result<(), Error> {
+    revert_changes(&cindy, Revert::Batch(id)).await
+}
+
+async fn history_change_revert(
+    State(cindy): State<Cindy>,
+    Path(id): Path<u64>,
+) -> Result<(), Error> {
+    revert_changes(&cindy, Revert::Change(id)).await
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new()
+        .route("/history", get(history))
+        .route("/history/:id/revert", post(history_batch_revert))
+        .route("/history/changes/:id/revert", post(history_change_revert))
+}
@@ -0,0 +1,42 @@
+use crate::{server::Error, Cindy};
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+use cindy_common::{JobInfo, JobStart, Mutation};
+
+async fn job_list(State(cindy): State<Cindy>) -> Json<Vec<JobInfo>> {
+    Json(cindy.jobs().list())
+}
+
+async fn job_create(State(cindy): State<Cindy>, Json(request): Json<JobStart>) -> Json<JobInfo> {
+    let job = match request {
+        JobStart::Rescan => cindy.job_spawn("rescan", {
+            let cindy = cindy.clone();
+            move |job| async move {
+                let root = cindy.root().to_path_buf();
+                cindy.add_files(&[root], true, false, &job).await?;
+                cindy.mutation(Mutation::Files);
+                cindy.mutation(Mutation::TagValues {
+                    name: None,
+                    value: None,
+                });
+                Ok(())
+            }
+        }),
+    };
+    Json(job.info())
+}
+
+async fn job_cancel(State(cindy): State<Cindy>, Path(id): Path<u64>) -> Result<(), Error> {
+    let job = cindy.jobs().get(id).ok_or(Error::NotFound)?;
+    job.cancel();
+    Ok(())
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new()
+        .route("/jobs", get(job_list).post(job_create))
+        .route("/jobs/:id", delete(job_cancel))
+}
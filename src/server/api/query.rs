@@ -1,5 +1,12 @@
-use crate::{common::api::*, hash::BoxHash, server::Error, Cindy, Tag};
-use axum::{extract::State, routing::get, Json, Router};
+use super::origin;
+use crate::{
+    common::{api::*, BatchOperation, Mutation, User},
+    hash::BoxHash,
+    history::Journal,
+    server::Error,
+    Cindy, Tag,
+};
+use axum::{extract::State, routing::get, Extension, Json, Router};
 use serde_qs::axum::QsQuery as Query;
 use std::collections::BTreeSet;
 use tokio::task::spawn_blocking;
@@ -8,7 +15,7 @@ async fn query(
     State(cindy): State<Cindy>,
     Query(query): Query<QueryFiles<'static>>,
 ) -> Result<Json<BTreeSet<BoxHash>>, Error> {
-    let database = cindy.database().await;
+    let database = cindy.database_read().await?;
     spawn_blocking(move || database.query_hashes(&mut query.query.iter()))
         .await?
         .map(Json)
@@ -17,41 +24,67 @@ async fn query(
 
 async fn query_tag_create(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Json(request): Json<QueryTagCreate<String>>,
 ) -> Result<(), Error> {
     let mut database = cindy.database().await;
+    let mutation = Mutation::FileTags {
+        file: None,
+        name: Some(request.name.clone()),
+        value: Some(request.value.clone()),
+    };
     spawn_blocking(move || {
         let transaction = database.transaction()?;
-        transaction.query_tag_add(&mut request.query.iter(), &request.name, &request.value)?;
+        let mut journal = Journal::new(&transaction, origin(user));
+        for file in transaction.query_hashes(&mut request.query.iter())? {
+            journal.apply(&BatchOperation::FileTagAdd {
+                file,
+                name: request.name.clone(),
+                value: request.value.clone(),
+            })?;
+        }
         transaction.commit()?;
-        Ok(())
+        Ok::<_, Error>(())
     })
-    .await?
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
 }
 
 async fn query_tag_delete(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Query(query): Query<QueryTagRemove<String>>,
 ) -> Result<(), Error> {
     let mut database = cindy.database().await;
+    let mutation = Mutation::FileTags {
+        file: None,
+        name: query.name.clone(),
+        value: query.value.clone(),
+    };
     spawn_blocking(move || {
         let transaction = database.transaction()?;
-        transaction.query_tag_remove(
-            &mut query.query.iter(),
-            query.name.as_deref(),
-            query.value.as_deref(),
-        )?;
+        let mut journal = Journal::new(&transaction, origin(user));
+        for file in transaction.query_hashes(&mut query.query.iter())? {
+            journal.apply(&BatchOperation::FileTagRemove {
+                file,
+                name: query.name.clone(),
+                value: query.value.clone(),
+            })?;
+        }
         transaction.commit()?;
-        Ok(())
+        Ok::<_, Error>(())
     })
-    .await?
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
 }
 
 async fn query_tags(
     State(cindy): State<Cindy>,
     Query(query): Query<QueryTags>,
 ) -> Result<Json<BTreeSet<Tag>>, Error> {
-    let database = cindy.database().await;
+    let database = cindy.database_read().await?;
     spawn_blocking(move || match query.mode {
         QueryTagsMode::Union => database.query_tag_union(
             &mut query.query.iter(),
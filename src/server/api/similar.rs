@@ -0,0 +1,21 @@
+use crate::{
+    hash::BoxHash,
+    server::Error,
+    similar::{DEFAULT_DISTANCE, MAX_DISTANCE},
+    Cindy,
+};
+use axum::{extract::State, routing::get, Json, Router};
+use cindy_common::api::SimilarFiles;
+use serde_qs::axum::QsQuery as Query;
+
+async fn similar(
+    State(cindy): State<Cindy>,
+    Query(query): Query<SimilarFiles>,
+) -> Result<Json<Vec<Vec<BoxHash>>>, Error> {
+    let distance = query.distance.unwrap_or(DEFAULT_DISTANCE).min(MAX_DISTANCE);
+    Ok(Json(cindy.similar_files(distance).await?))
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new().route("/similar", get(similar))
+}
@@ -1,12 +1,14 @@
-use crate::{server::Error, Cindy};
+use super::origin;
+use crate::{history::Journal, server::Error, Cindy};
 use axum::{
     extract::{Path, Query, State},
     routing::{get, patch},
-    Json, Router,
+    Extension, Json, Router,
 };
 use cindy_common::{
     api::*,
     tag::{Tag, TagNameInfo, TagValueInfo},
+    BatchOperation, Mutation, User,
 };
 use std::collections::BTreeMap;
 use tokio::task::spawn_blocking;
@@ -14,7 +16,7 @@ use tokio::task::spawn_blocking;
 async fn tag_name_list(
     State(cindy): State<Cindy>,
 ) -> Result<Json<BTreeMap<String, TagNameInfo>>, Error> {
-    let database = cindy.database().await;
+    let database = cindy.database_read().await?;
     spawn_blocking(move || database.tag_names().map(Json).map_err(Into::into)).await?
 }
 
@@ -22,7 +24,7 @@ async fn tag_value_list(
     State(cindy): State<Cindy>,
     Query(query): Query<TagQuery<String>>,
 ) -> Result<Json<BTreeMap<Tag, TagValueInfo>>, Error> {
-    let database = cindy.database().await;
+    let database = cindy.database_read().await?;
     spawn_blocking(move || {
         database
             .tag_list(query.name.as_deref(), query.value.as_deref())
@@ -34,62 +36,121 @@ async fn tag_value_list(
 
 async fn tag_value_create(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Json(query): Json<TagValueCreateRequest<'static>>,
 ) -> Result<(), Error> {
-    let database = cindy.database().await;
+    let mut database = cindy.database().await;
+    let mutation = Mutation::TagValues {
+        name: Some(query.name.to_string()),
+        value: Some(query.value.to_string()),
+    };
+    let operation = BatchOperation::TagValueCreate {
+        name: query.name.into(),
+        value: query.value.into(),
+        display: query.display.map(Into::into),
+    };
     spawn_blocking(move || {
-        database.tag_value_create(&query.name, &query.value)?;
-        if let Some(display) = &query.display {
-            database.tag_value_display(&query.name, &query.value, display)?;
-        }
-        Ok(())
+        let transaction = database.transaction()?;
+        Journal::new(&transaction, origin(user)).apply(&operation)?;
+        transaction.commit()?;
+        Ok::<_, Error>(())
     })
-    .await?
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
 }
 
 async fn tag_value_delete(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Query(query): Query<TagQuery<String>>,
 ) -> Result<(), Error> {
-    let database = cindy.database().await;
+    let mut database = cindy.database().await;
+    let mutation = Mutation::TagValues {
+        name: query.name.clone(),
+        value: query.value.clone(),
+    };
     spawn_blocking(move || {
-        database
-            .tag_delete(query.name.as_deref(), query.value.as_deref())
-            .map_err(Into::into)
+        let transaction = database.transaction()?;
+        let mut journal = Journal::new(&transaction, origin(user));
+        // system tags are never deleted
+        let tags = transaction.tag_list(query.name.as_deref(), query.value.as_deref())?;
+        for (tag, _) in tags.into_iter().filter(|(_, info)| !info.system) {
+            journal.apply(&BatchOperation::TagValueDelete {
+                name: tag.name().into(),
+                value: tag.value().into(),
+            })?;
+        }
+        transaction.commit()?;
+        Ok::<_, Error>(())
     })
-    .await?
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
 }
 
 async fn tag_name_create(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Json(query): Json<TagNameCreateRequest<'static>>,
 ) -> Result<(), Error> {
-    let database = cindy.database().await;
+    let mut database = cindy.database().await;
+    let mutation = Mutation::TagNames {
+        name: Some(query.name.to_string()),
+    };
+    let operation = BatchOperation::TagNameCreate {
+        name: query.name.into(),
+        display: query.display.map(Into::into),
+    };
     spawn_blocking(move || {
-        database.tag_name_create(&query.name, query.display.as_deref())?;
-        Ok(())
+        let transaction = database.transaction()?;
+        Journal::new(&transaction, origin(user)).apply(&operation)?;
+        transaction.commit()?;
+        Ok::<_, Error>(())
     })
-    .await?
+    .await??;
+    cindy.mutation(mutation);
+    Ok(())
 }
 
 async fn tag_name_edit(
     State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
     Path(name): Path<String>,
     Json(query): Json<TagNameEditRequest<'static>>,
 ) -> Result<(), Error> {
     let mut database = cindy.database().await;
+    let mut mutations = vec![Mutation::TagNames {
+        name: Some(name.clone()),
+    }];
+    if let Some(name_new) = &query.name {
+        mutations.push(Mutation::TagNames {
+            name: Some(name_new.to_string()),
+        });
+    }
     spawn_blocking(move || {
         let transaction = database.transaction()?;
-        if let Some(display) = &query.display {
-            transaction.tag_name_display(&name, &display)?;
+        let mut journal = Journal::new(&transaction, origin(user));
+        if let Some(display) = query.display {
+            journal.apply(&BatchOperation::TagNameDisplay {
+                name: name.clone(),
+                display: display.into(),
+            })?;
         }
-        if let Some(name_new) = &query.name {
-            transaction.tag_name_rename(&name, &name_new)?;
+        if let Some(new) = query.name {
+            journal.apply(&BatchOperation::TagNameRename {
+                name,
+                new: new.into(),
+            })?;
         }
         transaction.commit()?;
-        Ok(())
+        Ok::<_, Error>(())
     })
-    .await?
+    .await??;
+    for mutation in mutations {
+        cindy.mutation(mutation);
+    }
+    Ok(())
 }
 
 pub fn router() -> Router<Cindy> {
@@ -0,0 +1,232 @@
+use super::origin;
+use crate::{auth::token_generate, hash::BoxHash, history::Journal, server::Error, Cindy};
+use axum::{
+    extract::{BodyStream, Path, State},
+    routing::put,
+    Extension, Json, Router,
+};
+use cindy_common::{api::UploadQuery, BatchOperation, Mutation, Tag, User};
+use futures::StreamExt;
+use serde_qs::axum::QsQuery as Query;
+use std::{
+    io::ErrorKind,
+    path::{Component, Path as FsPath, PathBuf},
+};
+use tokio::{
+    fs::{canonicalize, create_dir_all, hard_link, remove_file, File},
+    io::AsyncWriteExt,
+    task::spawn_blocking,
+};
+
+/// Make sure the upload path stays inside the project and outside of the Cindy folder.
+fn upload_path(path: &str) -> Result<PathBuf, Error> {
+    let path = FsPath::new(path);
+    let normal = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    let hidden = path
+        .components()
+        .next()
+        .map(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        .unwrap_or(true);
+    if !normal || hidden {
+        return Err(Error::InvalidPath);
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Make sure a folder stays inside the project and outside of the Cindy folder once symbolic
+/// links are resolved, checking the closest ancestor that exists.
+async fn resolved_inside(cindy: &Cindy, folder: &FsPath) -> Result<(), Error> {
+    let mut existing = folder;
+    let resolved = loop {
+        match canonicalize(existing).await {
+            Ok(resolved) => break resolved,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                existing = existing.parent().ok_or(Error::InvalidPath)?;
+            }
+            Err(error) => return Err(error.into()),
+        }
+    };
+    let root = canonicalize(cindy.root()).await?;
+    let cindy_folder = canonicalize(cindy.cindy_folder()).await?;
+    if !resolved.starts_with(root) || resolved.starts_with(cindy_folder) {
+        return Err(Error::InvalidPath);
+    }
+    Ok(())
+}
+
+/// System tags are only set when indexing files, so they can't be given with an upload.
+async fn tags_check(cindy: &Cindy, tags: &[Tag]) -> Result<(), Error> {
+    let database = cindy.database_read().await?;
+    let names = spawn_blocking(move || database.tag_names()).await??;
+    match tags
+        .iter()
+        .find(|tag| names.get(tag.name()).is_some_and(|info| info.system))
+    {
+        Some(tag) => Err(Error::SystemTag(tag.name().into())),
+        None => Ok(()),
+    }
+}
+
+/// Tag the uploaded file, recording the changes in the history.
+async fn tags_add(
+    cindy: &Cindy,
+    user: Option<Extension<User>>,
+    hash: BoxHash,
+    tags: Vec<Tag>,
+) -> Result<(), Error> {
+    let mut database = cindy.database().await;
+    spawn_blocking(move || {
+        let transaction = database.transaction()?;
+        let mut journal = Journal::new(&transaction, origin(user));
+        for tag in tags {
+            let (name, value) = (tag.name().to_string(), tag.value().to_string());
+            journal.apply(&BatchOperation::TagNameCreate {
+                name: name.clone(),
+                display: None,
+            })?;
+            journal.apply(&BatchOperation::TagValueCreate {
+                name: name.clone(),
+                value: value.clone(),
+                display: None,
+            })?;
+            journal.apply(&BatchOperation::FileTagAdd {
+                file: hash.clone(),
+                name,
+                value,
+            })?;
+        }
+        transaction.commit()?;
+        Ok::<_, Error>(())
+    })
+    .await?
+}
+
+/// Uploaded file placed in the project, which is removed again when dropped unless the upload
+/// went through, so that failed uploads can be retried.
+struct Placed(Option<PathBuf>);
+
+impl Placed {
+    fn keep(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Placed {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Write the body into a file, hashing it along the way.
+async fn receive(cindy: &Cindy, mut body: BodyStream, path: &FsPath) -> Result<BoxHash, Error> {
+    let limit = cindy.config().upload.size_limit;
+    let mut file = File::create(path).await?;
+    let mut hasher = cindy.hasher().create();
+    let mut size = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(anyhow::Error::from)?;
+        size += chunk.len() as u64;
+        if size > limit {
+            return Err(Error::TooLarge);
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.sync_all().await?;
+    Ok(hasher.finalize().into())
+}
+
+async fn upload(
+    State(cindy): State<Cindy>,
+    user: Option<Extension<User>>,
+    Path(path): Path<String>,
+    Query(query): Query<UploadQuery>,
+    body: BodyStream,
+) -> Result<Json<BoxHash>, Error> {
+    let path = upload_path(&path)?;
+    let target = cindy.root().join(&path);
+    if target.exists() {
+        return Err(Error::Conflict);
+    }
+    if let Some(parent) = target.parent() {
+        resolved_inside(&cindy, parent).await?;
+    }
+    tags_check(&cindy, &query.tags).await?;
+
+    // receive into the cindy folder first, so partial uploads never show up in the project
+    let uploads = cindy.uploads_path();
+    create_dir_all(&uploads).await?;
+    let partial = uploads.join(token_generate());
+    let hash = match receive(&cindy, body, &partial).await {
+        Ok(hash) => hash,
+        Err(error) => {
+            remove_file(&partial).await?;
+            return Err(error);
+        }
+    };
+
+    // linking fails if the file was created in the meantime, unlike renaming
+    if let Some(parent) = target.parent() {
+        create_dir_all(parent).await?;
+    }
+    let linked = hard_link(&partial, &target).await;
+    remove_file(&partial).await?;
+    match linked {
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(Error::Conflict)
+        }
+        result => result?,
+    }
+    let placed = Placed(Some(target));
+
+    let job = cindy.jobs().create("upload");
+    let result = cindy.add_hashed_file(&path, &hash, &job).await;
+    job.finish(&result);
+    result?;
+    tags_add(&cindy, user, hash.clone(), query.tags).await?;
+    placed.keep();
+    cindy.mutation(Mutation::Files);
+    cindy.mutation(Mutation::TagValues {
+        name: None,
+        value: None,
+    });
+    Ok(Json(hash))
+}
+
+pub fn router() -> Router<Cindy> {
+    Router::new().route("/upload/*path", put(upload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_path_valid() {
+        assert_eq!(upload_path("file.jpg").unwrap(), PathBuf::from("file.jpg"));
+        assert_eq!(
+            upload_path("phone/camera/file.jpg").unwrap(),
+            PathBuf::from("phone/camera/file.jpg")
+        );
+    }
+
+    #[test]
+    fn upload_path_invalid() {
+        for path in [
+            "",
+            "/etc/passwd",
+            "../file.jpg",
+            "phone/../../file.jpg",
+            ".cindy/file",
+        ] {
+            assert!(
+                matches!(upload_path(path), Err(Error::InvalidPath)),
+                "{path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    auth::{password_dummy, password_verify, token_generate, token_hash},
+    server::Error,
+    Cindy,
+};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Json, Router,
+};
+use cindy_common::{api::LoginBody, User};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::task::spawn_blocking;
+
+/// Name of the cookie holding the session token.
+const SESSION_COOKIE: &str = "cindy_session";
+
+/// Current UNIX timestamp, used for expiring sessions.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Token sent with a request, either as bearer token or as session cookie.
+fn request_token(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer.or_else(|| {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, _)| *name == SESSION_COOKIE)
+            .map(|(_, token)| token)
+    })
+}
+
+fn session_cookie(token: &str, max_age: u64) -> HeaderValue {
+    let cookie =
+        format!("{SESSION_COOKIE}={token}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Strict");
+    HeaderValue::from_str(&cookie).unwrap()
+}
+
+/// Makes sure requests come from a user that is allowed to make them.
+///
+/// Read-only users can only make safe requests (such as `GET`), which don't change anything. The
+/// user is added to the request extensions.
+pub async fn authenticate<B>(
+    State(cindy): State<Cindy>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, Error> {
+    if !cindy.config().auth.enabled {
+        return Ok(next.run(request).await);
+    }
+
+    let token = request_token(request.headers())
+        .map(token_hash)
+        .ok_or(Error::Unauthorized)?;
+    let database = cindy.database_read().await?;
+    let user = spawn_blocking(move || database.user_token_get(&token, now()))
+        .await??
+        .ok_or(Error::Unauthorized)?;
+    if !request.method().is_safe() && !user.role.can_write() {
+        return Err(Error::Forbidden);
+    }
+
+    request.extensions_mut().insert(user);
+    Ok(next.run(request).await)
+}
+
+async fn login(
+    State(cindy): State<Cindy>,
+    Json(request): Json<LoginBody<'static>>,
+) -> Result<impl IntoResponse, Error> {
+    let name = request.name.into_owned();
+    let password_hash = {
+        let database = cindy.database_read().await?;
+        let name = name.clone();
+        spawn_blocking(move || database.user_password(&name)).await??
+    };
+
+    // verifying is slow on purpose, so don't hold the database while doing it
+    let password = request.password.into_owned();
+    let exists = password_hash.is_some();
+    let valid = spawn_blocking(move || {
+        let hash = match &password_hash {
+            Some(hash) => hash,
+            None => password_dummy(),
+        };
+        password_verify(&password, hash)
+    })
+    .await?;
+    if !(exists && valid) {
+        return Err(Error::Unauthorized);
+    }
+
+    let token = token_generate();
+    let session = cindy.config().auth.session;
+    let database = cindy.database().await;
+    let hash = token_hash(&token);
+    spawn_blocking(move || {
+        let now = now();
+        database.user_tokens_expire(now)?;
+        database.user_token_add(&name, &hash, "session", Some(now + session as i64))
+    })
+    .await??;
+
+    Ok([(header::SET_COOKIE, session_cookie(&token, session))])
+}
+
+async fn logout(
+    State(cindy): State<Cindy>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    if let Some(token) = request_token(&headers).map(token_hash) {
+        let database = cindy.database().await;
+        spawn_blocking(move || database.user_token_remove(&token)).await??;
+    }
+
+    Ok([(header::SET_COOKIE, session_cookie("", 0))])
+}
+
+/// User making the request, `None` if authentication is disabled.
+pub async fn current_user(user: Option<Extension<User>>) -> Json<Option<User>> {
+    Json(user.map(|Extension(user)| user))
+}
+
+/// Routes which don't need authentication.
+pub fn router() -> Router<Cindy> {
+    Router::new()
+        .route("/auth/login", post(login))
+        .route("/auth/logout", post(logout))
+}
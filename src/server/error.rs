@@ -17,6 +17,18 @@ pub enum Error {
     IO(#[from] std::io::Error),
     #[error("not found")]
     NotFound,
+    #[error("not logged in")]
+    Unauthorized,
+    #[error("not allowed")]
+    Forbidden,
+    #[error("invalid path")]
+    InvalidPath,
+    #[error("already exists")]
+    Conflict,
+    #[error("tag {0} is a system tag")]
+    SystemTag(String),
+    #[error("too large")]
+    TooLarge,
     #[error(transparent)]
     Sqlite(#[from] rusqlite::Error),
 }
@@ -25,6 +37,13 @@ impl Error {
     fn status(&self) -> StatusCode {
         match self {
             Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::InvalidPath => StatusCode::BAD_REQUEST,
+            Error::Conflict => StatusCode::CONFLICT,
+            Error::SystemTag(_) => StatusCode::BAD_REQUEST,
+            Error::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::Sqlite(rusqlite::Error::QueryReturnedNoRows) => StatusCode::NOT_FOUND,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -46,6 +65,12 @@ mod tests {
     fn errors() -> Vec<Error> {
         vec![
             Error::NotFound,
+            Error::Unauthorized,
+            Error::Forbidden,
+            Error::InvalidPath,
+            Error::Conflict,
+            Error::SystemTag("path".into()),
+            Error::TooLarge,
             Error::Other(anyhow::anyhow!("Anyhow error")),
         ]
     }
@@ -69,6 +94,10 @@ mod tests {
     #[test]
     fn test_status() {
         assert_eq!(Error::NotFound.status(), StatusCode::NOT_FOUND);
+        assert_eq!(Error::Unauthorized.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(Error::Forbidden.status(), StatusCode::FORBIDDEN);
+        assert_eq!(Error::InvalidPath.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(Error::Conflict.status(), StatusCode::CONFLICT);
         assert_eq!(
             Error::Other(anyhow!("Error")).status(),
             StatusCode::INTERNAL_SERVER_ERROR
@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+/// Most ranges served in one response, requests for more get the entire file instead.
+pub const MAX_RANGES: usize = 16;
+
+/// Byte ranges requested by a `Range` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ranges {
+    /// No (valid) range requested, serve the entire file.
+    Full,
+    /// Serve only these byte ranges of the file.
+    Partial(Vec<Range<u64>>),
+    /// None of the requested ranges overlap the file.
+    Unsatisfiable,
+}
+
+impl Ranges {
+    /// Parse the value of a `Range` header for a file of the given size.
+    ///
+    /// Malformed headers are ignored, as required by RFC 9110. Overlapping and adjacent ranges
+    /// are merged, and if more than [`MAX_RANGES`] remain the entire file is served.
+    pub fn parse(header: Option<&str>, size: u64) -> Self {
+        let Some(header) = header else {
+            return Ranges::Full;
+        };
+        let Some(specs) = header.trim().strip_prefix("bytes=") else {
+            return Ranges::Full;
+        };
+
+        let mut ranges = Vec::new();
+        for spec in specs.split(',').map(str::trim) {
+            match parse_spec(spec, size) {
+                Spec::Invalid => return Ranges::Full,
+                Spec::Unsatisfiable => {}
+                Spec::Range(range) => ranges.push(range),
+            }
+        }
+
+        let ranges = merge(ranges);
+        match ranges.len() {
+            0 => Ranges::Unsatisfiable,
+            1..=MAX_RANGES => Ranges::Partial(ranges),
+            _ => Ranges::Full,
+        }
+    }
+}
+
+/// Merge overlapping and adjacent ranges, sorting them by where they start.
+fn merge(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+enum Spec {
+    Invalid,
+    Unsatisfiable,
+    Range(Range<u64>),
+}
+
+/// Parse a single range, such as `0-499`, `500-` or `-200`.
+fn parse_spec(spec: &str, size: u64) -> Spec {
+    let Some((start, end)) = spec.split_once('-') else {
+        return Spec::Invalid;
+    };
+    let number = |value: &str| -> Option<u64> {
+        if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        value.parse().ok()
+    };
+
+    match (start, end) {
+        // suffix range, the last bytes of the file
+        ("", length) => match number(length) {
+            None => Spec::Invalid,
+            Some(0) => Spec::Unsatisfiable,
+            Some(_) if size == 0 => Spec::Unsatisfiable,
+            Some(length) => Spec::Range(size.saturating_sub(length)..size),
+        },
+        // open range, from start until the end of the file
+        (start, "") => match number(start) {
+            None => Spec::Invalid,
+            Some(start) if start >= size => Spec::Unsatisfiable,
+            Some(start) => Spec::Range(start..size),
+        },
+        (start, end) => match (number(start), number(end)) {
+            (Some(start), Some(end)) if start <= end => {
+                if start >= size {
+                    Spec::Unsatisfiable
+                } else {
+                    Spec::Range(start..end.saturating_add(1).min(size))
+                }
+            }
+            _ => Spec::Invalid,
+        },
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_none() {
+        assert_eq!(Ranges::parse(None, 100), Ranges::Full);
+    }
+
+    #[test]
+    fn parse_single() {
+        assert_eq!(
+            Ranges::parse(Some("bytes=0-49"), 100),
+            Ranges::Partial(vec![0..50])
+        );
+        assert_eq!(
+            Ranges::parse(Some("bytes=50-"), 100),
+            Ranges::Partial(vec![50..100])
+        );
+        assert_eq!(
+            Ranges::parse(Some("bytes=-10"), 100),
+            Ranges::Partial(vec![90..100])
+        );
+        assert_eq!(
+            Ranges::parse(Some("bytes=90-200"), 100),
+            Ranges::Partial(vec![90..100])
+        );
+        assert_eq!(
+            Ranges::parse(Some("bytes=-200"), 100),
+            Ranges::Partial(vec![0..100])
+        );
+    }
+
+    #[test]
+    fn parse_multiple() {
+        assert_eq!(
+            Ranges::parse(Some("bytes=0-9, 20-29,-5"), 100),
+            Ranges::Partial(vec![0..10, 20..30, 95..100])
+        );
+        assert_eq!(
+            Ranges::parse(Some("bytes=0-9,200-300"), 100),
+            Ranges::Partial(vec![0..10])
+        );
+    }
+
+    #[test]
+    fn parse_merged() {
+        assert_eq!(
+            Ranges::parse(Some("bytes=20-29,0-9,5-14,15-19"), 100),
+            Ranges::Partial(vec![0..30])
+        );
+        let repeated = format!("bytes={}", vec!["0-0"; 10_000].join(","));
+        assert_eq!(
+            Ranges::parse(Some(&repeated), 100),
+            Ranges::Partial(vec![0..1])
+        );
+    }
+
+    #[test]
+    fn parse_too_many() {
+        let specs: Vec<String> = (0..=MAX_RANGES)
+            .map(|i| format!("{0}-{0}", i * 2))
+            .collect();
+        let header = format!("bytes={}", specs.join(","));
+        assert_eq!(Ranges::parse(Some(&header), 100), Ranges::Full);
+        let header = format!("bytes={}", specs[..MAX_RANGES].join(","));
+        assert!(matches!(
+            Ranges::parse(Some(&header), 100),
+            Ranges::Partial(ranges) if ranges.len() == MAX_RANGES
+        ));
+    }
+
+    #[test]
+    fn parse_unsatisfiable() {
+        assert_eq!(
+            Ranges::parse(Some("bytes=100-"), 100),
+            Ranges::Unsatisfiable
+        );
+        assert_eq!(
+            Ranges::parse(Some("bytes=200-300,-0"), 100),
+            Ranges::Unsatisfiable
+        );
+        assert_eq!(Ranges::parse(Some("bytes=-5"), 0), Ranges::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(Ranges::parse(Some("items=0-9"), 100), Ranges::Full);
+        assert_eq!(Ranges::parse(Some("bytes=9-0"), 100), Ranges::Full);
+        assert_eq!(Ranges::parse(Some("bytes=a-b"), 100), Ranges::Full);
+        assert_eq!(Ranges::parse(Some("bytes=0-9,"), 100), Ranges::Full);
+        assert_eq!(Ranges::parse(Some("bytes=-"), 100), Ranges::Full);
+        assert_eq!(Ranges::parse(Some("bytes=+1-5"), 100), Ranges::Full);
+    }
+}
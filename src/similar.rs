@@ -0,0 +1,380 @@
+use std::{borrow::Borrow, collections::BTreeMap};
+
+/// Width of the grayscale image a perceptual hash is computed from.
+pub const DHASH_WIDTH: usize = 9;
+
+/// Height of the grayscale image a perceptual hash is computed from.
+pub const DHASH_HEIGHT: usize = 8;
+
+/// Difference hash of a small grayscale image, one bit per pair of horizontally adjacent pixels
+/// which is set if the left one is brighter.
+///
+/// Resized, recompressed and re-encoded copies of an image keep most of these bits, so their
+/// hashes are only a small Hamming distance apart.
+pub fn difference_hash(pixels: &[[u8; DHASH_WIDTH]; DHASH_HEIGHT]) -> u64 {
+    let mut hash = 0;
+    for row in pixels {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | u64::from(pair[0] > pair[1]);
+        }
+    }
+    hash
+}
+
+/// Distance within which images are considered similar unless another one is given.
+pub const DEFAULT_DISTANCE: u32 = 10;
+
+/// Largest distance there is, perceptual hashes can't differ in more bits than they have.
+pub const MAX_DISTANCE: u32 = u64::BITS;
+
+/// Amount of bits in which two perceptual hashes differ.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    phash: u64,
+    items: Vec<usize>,
+    children: BTreeMap<u32, usize>,
+}
+
+/// Index of perceptual hashes by Hamming distance, as BK-tree.
+///
+/// Every child of a node is keyed by its distance to that node, so by the triangle inequality a
+/// lookup only needs to descend into children whose key is within the wanted distance of the
+/// distance to the node.
+#[derive(Clone, Debug, Default)]
+pub struct SimilarIndex {
+    nodes: Vec<Node>,
+}
+
+impl SimilarIndex {
+    /// Add an item with the given perceptual hash.
+    pub fn insert(&mut self, phash: u64, item: usize) {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node {
+                phash,
+                items: vec![item],
+                children: BTreeMap::new(),
+            });
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let child = self.nodes.len();
+            let node = &mut self.nodes[current];
+            let distance = distance(node.phash, phash);
+            if distance == 0 {
+                node.items.push(item);
+                return;
+            }
+            match node.children.get(&distance) {
+                Some(child) => current = *child,
+                None => {
+                    node.children.insert(distance, child);
+                    self.nodes.push(Node {
+                        phash,
+                        items: vec![item],
+                        children: BTreeMap::new(),
+                    });
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Items whose perceptual hash is at most `max` bits away from `phash`.
+    pub fn find(&self, phash: u64, max: u32) -> Vec<usize> {
+        let max = max.min(MAX_DISTANCE);
+        let mut found = vec![];
+        let mut pending = match self.nodes.is_empty() {
+            true => vec![],
+            false => vec![0],
+        };
+        while let Some(current) = pending.pop() {
+            let node = &self.nodes[current];
+            let distance = distance(node.phash, phash);
+            if distance <= max {
+                found.extend_from_slice(&node.items);
+            }
+            let range = distance.saturating_sub(max)..=distance + max;
+            pending.extend(node.children.range(range).map(|(_, child)| *child));
+        }
+        found
+    }
+}
+
+fn root(parents: &mut [usize], mut item: usize) -> usize {
+    while parents[item] != item {
+        parents[item] = parents[parents[item]];
+        item = parents[item];
+    }
+    item
+}
+
+fn join(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (root(parents, a), root(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+/// Items which were joined, for the groups with more than one item.
+fn groups(parents: &mut [usize]) -> Vec<Vec<usize>> {
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for item in 0..parents.len() {
+        let root = root(parents, item);
+        groups.entry(root).or_default().push(item);
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// Group items whose perceptual hashes are at most `max` bits apart, returning the indices of
+/// the groups with more than one item.
+///
+/// Groups are linked transitively, so two items can be in the same group while being further
+/// apart if there is a chain of close items between them.
+pub fn clusters(phashes: &[u64], max: u32) -> Vec<Vec<usize>> {
+    let mut index = SimilarIndex::default();
+    for (item, phash) in phashes.iter().enumerate() {
+        index.insert(*phash, item);
+    }
+
+    let mut parents: Vec<usize> = (0..phashes.len()).collect();
+    for (item, phash) in phashes.iter().enumerate() {
+        for other in index.find(*phash, max) {
+            join(&mut parents, item, other);
+        }
+    }
+    groups(&mut parents)
+}
+
+/// Share of the frames of the shorter of two videos which need to match for them to be grouped.
+const FINGERPRINT_MATCH: f64 = 0.5;
+
+/// Fewest frames which need to match for two videos to be grouped, so that short videos are not
+/// grouped with every video that has a single similar frame.
+const FINGERPRINT_MIN_FRAMES: usize = 3;
+
+/// Index of the frames of video fingerprints, the perceptual hashes of frames sampled at a fixed
+/// interval.
+#[derive(Clone, Debug, Default)]
+pub struct FingerprintIndex {
+    index: SimilarIndex,
+    /// Video and position of every indexed frame.
+    frames: Vec<(usize, usize)>,
+    /// Amount of frames of every video.
+    lengths: Vec<usize>,
+}
+
+impl FingerprintIndex {
+    pub fn new(fingerprints: &[Vec<u64>]) -> Self {
+        let mut index = Self::default();
+        for (video, fingerprint) in fingerprints.iter().enumerate() {
+            for (position, phash) in fingerprint.iter().enumerate() {
+                index.index.insert(*phash, index.frames.len());
+                index.frames.push((video, position));
+            }
+            index.lengths.push(fingerprint.len());
+        }
+        index
+    }
+
+    /// Videos which match the fingerprint, meaning that enough frames of the shorter one are at
+    /// most `max` bits away from the frames of the other one at a fixed offset.
+    pub fn find(&self, fingerprint: &[u64], max: u32) -> Vec<usize> {
+        // matching frames by video and offset from the frames of the fingerprint
+        let mut matches: BTreeMap<(usize, isize), usize> = BTreeMap::new();
+        for (position, phash) in fingerprint.iter().enumerate() {
+            for frame in self.index.find(*phash, max) {
+                let (video, video_position) = self.frames[frame];
+                let offset = video_position as isize - position as isize;
+                *matches.entry((video, offset)).or_default() += 1;
+            }
+        }
+        let mut found: Vec<usize> = matches
+            .into_iter()
+            .filter(|((video, _), count)| {
+                let shorter = fingerprint.len().min(self.lengths[*video]);
+                *count >= FINGERPRINT_MIN_FRAMES
+                    && *count as f64 >= shorter as f64 * FINGERPRINT_MATCH
+            })
+            .map(|((video, _), _)| video)
+            .collect();
+        found.dedup();
+        found
+    }
+}
+
+/// Group videos by their fingerprints, returning the indices of the groups with more than one
+/// video.
+///
+/// Two videos are grouped if their fingerprints match at some offset, so trimmed copies are
+/// grouped too.
+pub fn fingerprint_clusters(fingerprints: &[Vec<u64>], max: u32) -> Vec<Vec<usize>> {
+    let index = FingerprintIndex::new(fingerprints);
+    let mut parents: Vec<usize> = (0..fingerprints.len()).collect();
+    for (video, fingerprint) in fingerprints.iter().enumerate() {
+        for other in index.find(fingerprint, max) {
+            join(&mut parents, video, other);
+        }
+    }
+    groups(&mut parents)
+}
+
+/// Position of an item in a list sorted by items.
+fn position<T: Borrow<Q>, Q: Ord + ?Sized, V>(items: &[(T, V)], item: &Q) -> Option<usize> {
+    items
+        .binary_search_by(|(other, _)| other.borrow().cmp(item))
+        .ok()
+}
+
+/// Images and videos indexed by their perceptual hashes and fingerprints, to look up the ones
+/// which are similar to one of them without grouping all of them.
+#[derive(Clone, Debug)]
+pub struct SimilarLookup<T> {
+    images: Vec<(T, u64)>,
+    image_index: SimilarIndex,
+    videos: Vec<(T, Vec<u64>)>,
+    video_index: FingerprintIndex,
+}
+
+impl<T: Ord> SimilarLookup<T> {
+    pub fn new(mut images: Vec<(T, u64)>, mut videos: Vec<(T, Vec<u64>)>) -> Self {
+        images.sort_by(|(left, _), (right, _)| left.cmp(right));
+        videos.sort_by(|(left, _), (right, _)| left.cmp(right));
+        let mut image_index = SimilarIndex::default();
+        for (item, (_, phash)) in images.iter().enumerate() {
+            image_index.insert(*phash, item);
+        }
+        let fingerprints: Vec<Vec<u64>> = videos
+            .iter()
+            .map(|(_, fingerprint)| fingerprint.clone())
+            .collect();
+        Self {
+            images,
+            image_index,
+            video_index: FingerprintIndex::new(&fingerprints),
+            videos,
+        }
+    }
+
+    /// Other images whose perceptual hash is at most `max` bits away from that of `item`, or other
+    /// videos whose fingerprint matches that of `item` with frames at most that far apart.
+    pub fn find<Q: Ord + ?Sized>(&self, item: &Q, max: u32) -> Vec<&T>
+    where
+        T: Borrow<Q>,
+    {
+        let mut found = match position(&self.images, item) {
+            Some(image) => self.image_index.find(self.images[image].1, max),
+            None => vec![],
+        };
+        found.sort();
+        found.retain(|other| self.images[*other].0.borrow() != item);
+        let mut found: Vec<&T> = found
+            .into_iter()
+            .map(|other| &self.images[other].0)
+            .collect();
+        if let Some(video) = position(&self.videos, item) {
+            let fingerprint = &self.videos[video].1;
+            found.extend(
+                self.video_index
+                    .find(fingerprint, max)
+                    .into_iter()
+                    .filter(|other| *other != video)
+                    .map(|other| &self.videos[other].0),
+            );
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_difference_hash() {
+        let mut pixels = [[0; DHASH_WIDTH]; DHASH_HEIGHT];
+        assert_eq!(difference_hash(&pixels), 0);
+        pixels[0] = [8, 7, 6, 5, 4, 3, 2, 1, 0];
+        assert_eq!(difference_hash(&pixels), 0xff << 56);
+        pixels[7][7] = 1;
+        assert_eq!(difference_hash(&pixels), 0xff << 56 | 1);
+    }
+
+    #[test]
+    fn test_clusters() {
+        let phashes = [0b0000, 0b1111 << 32, 0b0011, u64::MAX, 0b0111 << 32, 0b0110];
+        assert_eq!(clusters(&phashes, 0), Vec::<Vec<usize>>::new());
+        assert_eq!(clusters(&phashes, 1), vec![vec![1, 4]]);
+        assert_eq!(clusters(&phashes, 2), vec![vec![0, 2, 5], vec![1, 4]]);
+    }
+
+    #[test]
+    fn test_fingerprint_clusters() {
+        let video: Vec<u64> = (0..20).map(|frame| frame * 0x0101_0101_0101_0101).collect();
+        let reencoded: Vec<u64> = video.iter().map(|phash| phash ^ 1).collect();
+        let trimmed = video[5..].to_vec();
+        let reordered: Vec<u64> = video.iter().rev().cloned().collect();
+        let other: Vec<u64> = (0..20)
+            .map(|frame| !(frame * 0x0101_0101_0101_0101))
+            .collect();
+        let fingerprints = [video, other, reencoded, trimmed, reordered];
+        assert_eq!(fingerprint_clusters(&fingerprints, 0), vec![vec![0, 3]]);
+        assert_eq!(fingerprint_clusters(&fingerprints, 1), vec![vec![0, 2, 3]]);
+
+        // a single similar frame is not enough, even if it is all of a short video
+        let fingerprints = [fingerprints[0].clone(), fingerprints[0][3..5].to_vec()];
+        assert!(fingerprint_clusters(&fingerprints, 0).is_empty());
+    }
+
+    #[test]
+    fn test_similar_lookup() {
+        let video: Vec<u64> = (0..20).map(|frame| frame * 0x0101_0101_0101_0101).collect();
+        let trimmed = video[5..].to_vec();
+        let lookup = SimilarLookup::new(
+            vec![("a", 0b0000), ("b", 0b0011), ("c", u64::MAX), ("d", 0b0111)],
+            vec![("v", video), ("w", trimmed), ("x", vec![1, 2, 3])],
+        );
+        assert_eq!(lookup.find("a", 2), [&"b"]);
+        assert_eq!(lookup.find("b", 1), [&"d"]);
+        assert_eq!(lookup.find("c", 2), Vec::<&&str>::new());
+        assert_eq!(lookup.find("v", 0), [&"w"]);
+        assert_eq!(lookup.find("x", 0), Vec::<&&str>::new());
+        assert_eq!(lookup.find("z", 64), Vec::<&&str>::new());
+    }
+
+    #[test]
+    fn find_any_distance() {
+        let mut index = SimilarIndex::default();
+        index.insert(0, 0);
+        index.insert(u64::MAX, 1);
+        let mut found = index.find(1, u32::MAX);
+        found.sort();
+        assert_eq!(found, [0, 1]);
+    }
+
+    proptest! {
+        #[test]
+        fn find_matches_linear_scan(phashes: Vec<u64>, phash: u64, max in 0u32..72) {
+            let mut index = SimilarIndex::default();
+            for (item, phash) in phashes.iter().enumerate() {
+                index.insert(*phash, item);
+            }
+            let mut found = index.find(phash, max);
+            found.sort();
+            let expected: Vec<usize> = phashes
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| distance(phash, **other) <= max)
+                .map(|(item, _)| item)
+                .collect();
+            prop_assert_eq!(found, expected);
+        }
+    }
+}
@@ -4,9 +4,18 @@ use axum::{
     http::{Method, Request},
     Router,
 };
-use cindy::{cli::AddCommand, hash::DataHasher, Cindy, Command, Config};
-use cindy_common::{api::*, tag::*, ErrorResponse};
+use cindy::{
+    cli::{AddCommand, UsersAddCommand, UsersCommand},
+    config::DataMode,
+    hash::{BoxHash, DataHasher},
+    Cindy, Command, Config,
+};
+use cindy_common::{
+    api::*, cache::Invalidatable, tag::*, BatchOperation, BatchResponse, BatchResult, Duplicate,
+    ErrorResponse, JobInfo, JobStatus, Label, Mutation, Origin, Point, Rectangle, Role, User,
+};
 use hyper::{Body, StatusCode};
+use proptest::prelude::*;
 use restless::{clients::HyperRequest, Request as HttpRequest};
 use std::{fs::*, path::PathBuf};
 use tempfile::tempdir;
@@ -56,6 +65,7 @@ async fn test_file_list_tags() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -117,6 +127,34 @@ async fn tag_create() {
     );
 }
 
+#[tokio::test]
+async fn tag_list_during_write() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(&dir.path(), &config).await.unwrap();
+    let router = cindy.router();
+    router
+        .send(TagNameCreate {
+            name: "name",
+            display: None,
+        })
+        .await
+        .unwrap();
+
+    // hold an uncommitted write transaction open
+    let mut writer = cindy.database().await;
+    let transaction = writer.transaction().unwrap();
+    transaction.tag_name_create("other", None).unwrap();
+
+    let tags = tokio::time::timeout(std::time::Duration::from_secs(5), router.send(TagNames))
+        .await
+        .expect("reading is not blocked by the writer")
+        .unwrap();
+    assert!(tags.contains_key("name"));
+    assert!(!tags.contains_key("other"));
+    transaction.commit().unwrap();
+}
+
 #[tokio::test]
 async fn tag_delete_one() {
     let dir = tempdir().unwrap();
@@ -250,6 +288,7 @@ async fn test_file_content() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -276,6 +315,7 @@ async fn test_query_empty() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -310,6 +350,7 @@ async fn test_query_filename() {
         .command(&Command::Add(AddCommand {
             paths: vec![dir.path().into()],
             recursive: true,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -348,6 +389,7 @@ async fn test_list_tag_names() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -380,6 +422,7 @@ async fn test_list_tags() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -468,6 +511,7 @@ async fn file_tag_create() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -521,6 +565,7 @@ async fn file_tag_delete() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -577,3 +622,1483 @@ async fn api_notfound() {
     assert_eq!(response.error, "not found");
     assert_eq!(response.cause, None);
 }
+
+#[tokio::test]
+async fn file_attributes_edit() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(&dir.path(), &config).await.unwrap();
+
+    // create file
+    let content = "hello";
+    let file_path = dir.path().join("file.txt");
+    write(&file_path, content).unwrap();
+
+    // add single file
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![file_path],
+            recursive: false,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let router = cindy.router();
+    let hash = cindy.hasher().hash_data(&content.as_bytes());
+
+    router
+        .send(FileAttributesEdit {
+            hash: hash.clone(),
+            attributes: [
+                ("comment".into(), Some("greeting".into())),
+                ("source".into(), Some("keyboard".into())),
+            ]
+            .into(),
+        })
+        .await
+        .unwrap();
+    router
+        .send(FileAttributesEdit {
+            hash: hash.clone(),
+            attributes: [("source".into(), None)].into(),
+        })
+        .await
+        .unwrap();
+
+    let attributes = router
+        .send(FileAttributes { hash: hash.clone() })
+        .await
+        .unwrap();
+    assert_eq!(
+        attributes,
+        [("comment".to_string(), "greeting".to_string())].into()
+    );
+
+    // attributes of unknown files cannot be set
+    let (status, _) = send_with(
+        &router,
+        FileAttributesEdit {
+            hash: cindy.hasher().hash_data(b"unknown"),
+            attributes: [("comment".into(), Some("greeting".into()))].into(),
+        },
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn label_attributes_edit() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(&dir.path(), &config).await.unwrap();
+
+    // create file
+    let content = "hello";
+    let file_path = dir.path().join("file.txt");
+    write(&file_path, content).unwrap();
+
+    // add single file
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![file_path],
+            recursive: false,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let hash = cindy.hasher().hash_data(&content.as_bytes());
+    let tag = Tag::new("filename".into(), "file.txt".into());
+    let label: Label = Rectangle {
+        start: Point::new(0, 0),
+        end: Point::new(10, 10),
+    }
+    .into();
+    let database = cindy.database().await;
+    database
+        .label_add(&hash, tag.name(), tag.value(), &label)
+        .unwrap();
+    drop(database);
+
+    let router = cindy.router();
+    router
+        .send(LabelAttributesEdit {
+            hash: hash.clone(),
+            tag: tag.clone(),
+            label,
+            attributes: [("occluded".into(), Some("true".into()))].into(),
+        })
+        .await
+        .unwrap();
+
+    let labels = router
+        .send(FileLabels {
+            hash: hash.clone(),
+            name: None,
+            value: None,
+            attribute_name: Some("occluded"),
+            attribute_value: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        labels,
+        [(
+            tag,
+            label,
+            [("occluded".to_string(), "true".to_string())].into()
+        )]
+        .into()
+    );
+}
+
+/// Create a Cindy project with a single text file, returning it and the hash of the file.
+async fn cindy_with_file(dir: &std::path::Path, content: &str) -> (Cindy, String) {
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir, &config).await.unwrap();
+    let file_path = dir.join("file.txt");
+    write(&file_path, content).unwrap();
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![file_path],
+            recursive: false,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let hash = cindy.hasher().hash_data(content.as_bytes()).to_string();
+    (cindy, hash)
+}
+
+#[tokio::test]
+async fn file_stream_headers() {
+    let dir = tempdir().unwrap();
+    let (cindy, hash) = cindy_with_file(dir.path(), "hello").await;
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["content-length"], "5");
+    assert_eq!(headers["accept-ranges"], "bytes");
+    assert_eq!(headers["etag"], format!("\"{hash}\"").as_str());
+    assert!(headers["cache-control"]
+        .to_str()
+        .unwrap()
+        .contains("immutable"));
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"hello");
+}
+
+#[tokio::test]
+async fn file_stream_reference() {
+    let dir = tempdir().unwrap();
+    let mut config = Config::default();
+    config.data.mode = DataMode::Reference;
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    let file_path = dir.path().join("file.txt");
+    write(&file_path, "hello").unwrap();
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![file_path.clone()],
+            recursive: false,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let hash = cindy.hasher().hash_data(b"hello");
+    let router = cindy.router();
+    assert_eq!(
+        router
+            .send(FileContent { hash: hash.clone() })
+            .await
+            .unwrap(),
+        "hello"
+    );
+
+    // served from where it was added, so it is gone once the file is
+    remove_file(&file_path).unwrap();
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn file_stream_not_modified() {
+    let dir = tempdir().unwrap();
+    let (cindy, hash) = cindy_with_file(dir.path(), "hello").await;
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .header("if-none-match", format!("\"other\", \"{hash}\""))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["etag"], format!("\"{hash}\"").as_str());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert!(body.is_empty());
+
+    // unknown files are not found, even if the client claims to have them cached
+    let unknown = cindy.hasher().hash_data(b"unknown");
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{unknown}"))
+                .header("if-none-match", "*")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(feature = "ffmpeg")]
+#[tokio::test]
+async fn file_media_unknown() {
+    let dir = tempdir().unwrap();
+    let (cindy, _) = cindy_with_file(dir.path(), "hello").await;
+    let unknown = cindy.hasher().hash_data(b"unknown");
+    for path in [
+        "crop?x1=0&y1=0&x2=1&y2=1",
+        "clip?start=0&end=1000",
+        "transcode?profile=webm",
+        "hls/index.m3u8",
+        "hls/360/index.m3u8",
+        "hls/360/0.ts",
+    ] {
+        let response = cindy
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/v1/file/{unknown}/{path}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn file_stream_range() {
+    let dir = tempdir().unwrap();
+    let (cindy, hash) = cindy_with_file(dir.path(), "hello world").await;
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .header("range", "bytes=6-")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let headers = response.headers();
+    assert_eq!(headers["content-range"], "bytes 6-10/11");
+    assert_eq!(headers["content-length"], "5");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"world");
+}
+
+#[tokio::test]
+async fn file_stream_multiple_ranges() {
+    let dir = tempdir().unwrap();
+    let (cindy, hash) = cindy_with_file(dir.path(), "hello world").await;
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .header("range", "bytes=0-1,-2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = response.headers()["content-type"].to_str().unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap()
+        .to_string();
+    let length: usize = response.headers()["content-length"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body.len(), length);
+    let expected = format!(
+        "\r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/11\r\n\r\nhe\
+        \r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 9-10/11\r\n\r\nld\
+        \r\n--{boundary}--\r\n"
+    );
+    assert_eq!(std::str::from_utf8(&body).unwrap(), expected);
+}
+
+#[tokio::test]
+async fn file_stream_range_unsatisfiable() {
+    let dir = tempdir().unwrap();
+    let (cindy, hash) = cindy_with_file(dir.path(), "hello").await;
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .header("range", "bytes=10-20")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()["content-range"], "bytes */5");
+}
+
+#[tokio::test]
+async fn file_stream_detected_mime() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+
+    // png file without an extension
+    let file_path = dir.path().join("image");
+    copy("samples/image1.png", &file_path).unwrap();
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![file_path.clone()],
+            recursive: false,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let hash = cindy.hasher().hash_data(&read(&file_path).unwrap());
+
+    let response = cindy
+        .router()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/v1/file/{hash}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+
+    let hashes = cindy
+        .router()
+        .send(QueryFiles {
+            query: vec!["mime:image/*".parse().unwrap()].into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(hashes, vec![hash]);
+}
+
+async fn cindy_with_users(dir: &std::path::Path) -> Cindy {
+    let mut config = Config::default();
+    config.auth.enabled = true;
+    let cindy = Cindy::initialize(dir, &config).await.unwrap();
+    for (name, role) in [("alice", Role::ReadWrite), ("bob", Role::ReadOnly)] {
+        cindy
+            .command(&Command::Users(UsersCommand::Add(UsersAddCommand {
+                name: name.into(),
+                role,
+                password: Some(format!("{name}-password")),
+            })))
+            .await
+            .unwrap();
+    }
+    cindy
+}
+
+/// Send request with the given header, returning the response status and headers.
+async fn send_with<R: HttpRequest>(
+    router: &Router,
+    request: R,
+    header: Option<(&str, &str)>,
+) -> (StatusCode, axum::http::HeaderMap) {
+    let mut request = request.to_hyper_request().unwrap();
+    if let Some((name, value)) = header {
+        request.headers_mut().insert(
+            axum::http::HeaderName::try_from(name).unwrap(),
+            value.parse().unwrap(),
+        );
+    }
+    let response = router.clone().oneshot(request).await.unwrap();
+    (response.status(), response.headers().clone())
+}
+
+#[tokio::test]
+async fn auth_disabled() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let user = cindy.router().send(CurrentUser).await.unwrap();
+    assert_eq!(user, None);
+}
+
+#[tokio::test]
+async fn auth_login_session() {
+    let dir = tempdir().unwrap();
+    let cindy = cindy_with_users(dir.path()).await;
+    let router = cindy.router();
+
+    let (status, _) = send_with(&router, CurrentUser, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let login = Login {
+        name: "alice",
+        password: "wrong",
+    };
+    let (status, _) = send_with(&router, login, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let login = Login {
+        name: "alice",
+        password: "alice-password",
+    };
+    let (status, headers) = send_with(&router, login, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let cookie = headers["set-cookie"].to_str().unwrap();
+    assert!(cookie.contains("HttpOnly"));
+    let cookie = cookie.split(';').next().unwrap().to_string();
+
+    let mut request = CurrentUser.to_hyper_request().unwrap();
+    request
+        .headers_mut()
+        .insert("cookie", cookie.parse().unwrap());
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let user: Option<User> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        user,
+        Some(User {
+            name: "alice".into(),
+            role: Role::ReadWrite
+        })
+    );
+
+    let (status, _) = send_with(&router, Logout, Some(("cookie", &cookie))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send_with(&router, CurrentUser, Some(("cookie", &cookie))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn auth_token_roles() {
+    let dir = tempdir().unwrap();
+    let cindy = cindy_with_users(dir.path()).await;
+    let router = cindy.router();
+    let create = TagNameCreate {
+        name: "name",
+        display: None,
+    };
+
+    let token = cindy.user_token_create("bob", "test").await.unwrap();
+    let bearer = format!("Bearer {token}");
+    let (status, _) = send_with(&router, TagNames, Some(("authorization", &bearer))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send_with(&router, create.clone(), Some(("authorization", &bearer))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let token = cindy.user_token_create("alice", "test").await.unwrap();
+    let bearer = format!("Bearer {token}");
+    let (status, _) = send_with(&router, create, Some(("authorization", &bearer))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send_with(&router, TagNames, Some(("authorization", "Bearer nope"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn mutation_events() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+    let mut mutations = cindy.mutations();
+
+    router
+        .send(TagNameCreate {
+            name: "name",
+            display: None,
+        })
+        .await
+        .unwrap();
+    router
+        .send(TagValueCreate {
+            name: "name",
+            value: "value",
+            display: None,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(
+        mutations.recv().await.unwrap(),
+        Mutation::TagNames {
+            name: Some("name".into())
+        }
+    );
+    assert_eq!(
+        mutations.recv().await.unwrap(),
+        Mutation::TagValues {
+            name: Some("name".into()),
+            value: Some("value".into())
+        }
+    );
+}
+
+#[tokio::test]
+async fn mutation_events_stream() {
+    use hyper::body::HttpBody;
+
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+
+    let request = Request::get("/api/v1/events").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    router
+        .send(TagNameCreate {
+            name: "name",
+            display: None,
+        })
+        .await
+        .unwrap();
+
+    let mut body = response.into_body();
+    let data = body.data().await.unwrap().unwrap();
+    assert_eq!(
+        std::str::from_utf8(&data).unwrap(),
+        "data:{\"table\":\"tag_names\",\"name\":\"name\"}\n\n"
+    );
+}
+
+/// Upload a file with the given tags, returning the response.
+async fn upload(
+    router: &Router,
+    path: &str,
+    tags: &str,
+    content: &str,
+) -> axum::response::Response {
+    let request = Request::put(format!("/api/v1/upload/{path}?{tags}"))
+        .body(Body::from(content.to_string()))
+        .unwrap();
+    router.clone().oneshot(request).await.unwrap()
+}
+
+#[tokio::test]
+async fn file_upload() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+    let content = "uploaded";
+
+    let response = upload(&router, "phone/file.txt", "tags[0]=album:holiday", content).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let hash: BoxHash = serde_json::from_slice(&body).unwrap();
+    assert_eq!(hash, cindy.hasher().hash_data(content.as_bytes()));
+
+    // file is placed in the project and in the data index
+    assert_eq!(
+        read_to_string(dir.path().join("phone/file.txt")).unwrap(),
+        content
+    );
+    assert_eq!(read_to_string(cindy.hash_path(&hash)).unwrap(), content);
+    assert_eq!(read_dir(cindy.uploads_path()).unwrap().count(), 0);
+
+    let tags = router
+        .send(FileTags {
+            hash: hash.clone(),
+            name: None,
+            value: None::<String>,
+        })
+        .await
+        .unwrap();
+    assert!(tags.contains(&Tag::new("album".into(), "holiday".into())));
+    assert!(tags.contains(&Tag::new("path".into(), "/phone/file.txt".into())));
+    assert!(tags.contains(&Tag::new("filesize".into(), content.len().to_string())));
+
+    // initial tags are recorded in the history
+    let history = router.send(History::default()).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].changes.len(), 3);
+
+    // existing files are not overwritten
+    let response = upload(&router, "phone/file.txt", "", "other").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        read_to_string(dir.path().join("phone/file.txt")).unwrap(),
+        content
+    );
+}
+
+#[tokio::test]
+async fn file_upload_invalid_path() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+
+    for path in ["../file.txt", ".cindy/file.txt"] {
+        let response = upload(&router, path, "", "content").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+
+    // symbolic links can't be used to leave the project or to reach the Cindy folder
+    let outside = tempdir().unwrap();
+    std::os::unix::fs::symlink(outside.path(), dir.path().join("outside")).unwrap();
+    std::os::unix::fs::symlink(cindy.cindy_folder(), dir.path().join("inside")).unwrap();
+    for path in [
+        "outside/file.txt",
+        "outside/folder/file.txt",
+        "inside/file.txt",
+    ] {
+        let response = upload(&router, path, "", "content").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+    assert_eq!(read_dir(outside.path()).unwrap().count(), 0);
+    assert!(!cindy.cindy_folder().join("file.txt").exists());
+}
+
+#[tokio::test]
+async fn file_upload_system_tag() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+
+    let response = upload(&router, "file.txt", "tags[0]=path:/other.txt", "content").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!dir.path().join("file.txt").exists());
+}
+
+#[tokio::test]
+async fn file_upload_too_large() {
+    let dir = tempdir().unwrap();
+    let mut config = Config::default();
+    config.upload.size_limit = 4;
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    let router = cindy.router();
+
+    let response = upload(&router, "file.txt", "", "content").await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(!dir.path().join("file.txt").exists());
+    assert_eq!(read_dir(cindy.uploads_path()).unwrap().count(), 0);
+
+    let response = upload(&router, "file.txt", "", "data").await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn file_upload_failed() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+
+    // adding fails when the data store can't be written to
+    remove_dir(cindy.data_path()).unwrap();
+    write(cindy.data_path(), "blocked").unwrap();
+    let response = upload(&router, "phone/file.txt", "", "content").await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(!dir.path().join("phone/file.txt").exists());
+    assert_eq!(read_dir(cindy.uploads_path()).unwrap().count(), 0);
+
+    // so the upload can be retried
+    remove_file(cindy.data_path()).unwrap();
+    let response = upload(&router, "phone/file.txt", "", "content").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        read_to_string(dir.path().join("phone/file.txt")).unwrap(),
+        "content"
+    );
+}
+
+#[tokio::test]
+async fn job_rescan() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    create_dir(dir.path().join("folder")).unwrap();
+    write(dir.path().join("folder").join("file.txt"), "rescanned").unwrap();
+    let router = cindy.router();
+
+    let request = Request::post("/api/v1/jobs")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"kind":"rescan"}"#))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let job: JobInfo = serde_json::from_slice(&body).unwrap();
+    assert_eq!(job.name, "rescan");
+
+    // wait for the job to finish
+    let mut progress = cindy.jobs().get(job.id).unwrap().subscribe();
+    progress
+        .wait_for(|info| info.status.finished())
+        .await
+        .unwrap();
+
+    let jobs = router.send(Jobs).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].status, JobStatus::Done);
+    assert_eq!(jobs[0].errors, Vec::<String>::new());
+
+    let hash = cindy.hasher().hash_data("rescanned".as_bytes());
+    let files = router
+        .send(QueryFiles {
+            query: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(files, vec![hash]);
+}
+
+#[tokio::test]
+async fn job_cancel_missing() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let (status, _) = send_with(&cindy.router(), JobCancel { id: 7 }, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn batch(router: &Router, operations: &[BatchOperation]) -> BatchResponse {
+    let request = Request::post("/api/v1/batch")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(operations).unwrap()))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn batch_commit() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let mut hashes = vec![];
+    for content in ["first", "second"] {
+        let path = dir.path().join(content);
+        write(&path, content).unwrap();
+        cindy
+            .command(&Command::Add(AddCommand {
+                paths: vec![path],
+                recursive: false,
+                verify: false,
+            }))
+            .await
+            .unwrap();
+        hashes.push(cindy.hasher().hash_data(content.as_bytes()));
+    }
+    let router = cindy.router();
+    router
+        .send(TagNameCreate {
+            name: "person",
+            display: None,
+        })
+        .await
+        .unwrap();
+
+    let mut operations = vec![
+        BatchOperation::TagValueCreate {
+            name: "person".into(),
+            value: "alice".into(),
+            display: None,
+        },
+        BatchOperation::TagValueDisplay {
+            name: "person".into(),
+            value: "alice".into(),
+            display: "Alice".into(),
+        },
+    ];
+    for hash in &hashes {
+        operations.push(BatchOperation::FileTagAdd {
+            file: hash.clone(),
+            name: "person".into(),
+            value: "alice".into(),
+        });
+    }
+    operations.push(BatchOperation::LabelAdd {
+        file: hashes[0].clone(),
+        tag: Tag::new("person".into(), "alice".into()),
+        label: Label::Rectangle(Rectangle {
+            start: Point { x: 1, y: 2 },
+            end: Point { x: 3, y: 4 },
+        }),
+    });
+
+    let mut mutations = cindy.mutations();
+    let response = batch(&router, &operations).await;
+    assert!(response.committed);
+    assert_eq!(
+        response.results,
+        vec![BatchResult::Applied; operations.len()]
+    );
+    assert_eq!(
+        response.mutations,
+        [
+            Mutation::TagValues {
+                name: Some("person".into()),
+                value: Some("alice".into()),
+            },
+            operations[2].mutation(),
+            operations[3].mutation(),
+            operations[4].mutation(),
+        ]
+    );
+    assert_eq!(mutations.recv().await.unwrap(), response.mutations[0]);
+
+    let files = router
+        .send(QueryFiles {
+            query: vec![TagPredicate::Exists(TagFilter::new(
+                Some("person"),
+                Some("alice"),
+            ))]
+            .into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(files.len(), 2);
+    let tags = router
+        .send(TagList {
+            name: Some("person"),
+            value: Some("alice"),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        tags[&Tag::new("person".into(), "alice".into())].display,
+        "Alice"
+    );
+}
+
+#[tokio::test]
+async fn batch_rollback() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let router = cindy.router();
+    router
+        .send(TagNameCreate {
+            name: "person",
+            display: None,
+        })
+        .await
+        .unwrap();
+
+    let missing = cindy.hasher().hash_data("missing".as_bytes());
+    let operations = [
+        BatchOperation::TagValueCreate {
+            name: "person".into(),
+            value: "alice".into(),
+            display: None,
+        },
+        BatchOperation::FileTagAdd {
+            file: missing,
+            name: "person".into(),
+            value: "alice".into(),
+        },
+        BatchOperation::TagNameDisplay {
+            name: "person".into(),
+            display: "Person".into(),
+        },
+    ];
+    let response = batch(&router, &operations).await;
+    assert!(!response.committed);
+    assert!(response.mutations.is_empty());
+    assert_eq!(response.results[0], BatchResult::Applied);
+    assert!(matches!(response.results[1], BatchResult::Failed { .. }));
+    assert_eq!(response.results[2], BatchResult::Skipped);
+
+    // the tag value created by the first operation was rolled back
+    let tags = router
+        .send(TagList {
+            name: Some("person"),
+            value: None::<&str>,
+        })
+        .await
+        .unwrap();
+    assert!(tags.is_empty());
+}
+
+#[tokio::test]
+async fn history_revert() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let mut hashes = vec![];
+    for content in ["first", "second"] {
+        let path = dir.path().join(content);
+        write(&path, content).unwrap();
+        cindy
+            .command(&Command::Add(AddCommand {
+                paths: vec![path],
+                recursive: false,
+                verify: false,
+            }))
+            .await
+            .unwrap();
+        hashes.push(cindy.hasher().hash_data(content.as_bytes()));
+    }
+    let router = cindy.router();
+    router
+        .send(TagNameCreate {
+            name: "person",
+            display: None,
+        })
+        .await
+        .unwrap();
+    router
+        .send(TagValueCreate {
+            name: "person",
+            value: "alice",
+            display: None,
+        })
+        .await
+        .unwrap();
+    router
+        .send(QueryTagCreate {
+            query: vec![],
+            name: "person",
+            value: "alice",
+        })
+        .await
+        .unwrap();
+
+    let history = router.send(History::default()).await.unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[0].origin, Origin::Api);
+    assert_eq!(history[0].changes.len(), 2);
+    let alice = TagPredicate::Exists(TagFilter::new(Some("person"), Some("alice")));
+    let query = QueryFiles {
+        query: vec![alice].into(),
+    };
+    assert_eq!(router.send(query.clone()).await.unwrap().len(), 2);
+
+    // revert tagging only the first file
+    let change = history[0]
+        .changes
+        .iter()
+        .find(|change| change.operation.mutation().affects_file(&hashes[0]))
+        .unwrap();
+    router
+        .send(HistoryChangeRevert { id: change.id })
+        .await
+        .unwrap();
+    assert_eq!(router.send(query.clone()).await.unwrap().len(), 1);
+
+    // revert the rest of the batch
+    router
+        .send(HistoryBatchRevert { id: history[0].id })
+        .await
+        .unwrap();
+    assert!(router.send(query).await.unwrap().is_empty());
+    let history = router.send(History::default()).await.unwrap();
+    assert_eq!(history.len(), 2);
+    let history = router
+        .send(History {
+            limit: Some(1),
+            reverted: true,
+        })
+        .await
+        .unwrap();
+    assert!(history[0].reverted());
+
+    let (status, _) = send_with(&router, HistoryBatchRevert { id: 1000 }, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn similar_files() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let mut hashes = vec![];
+    for content in ["first", "second", "third"] {
+        let path = dir.path().join(content);
+        write(&path, content).unwrap();
+        cindy
+            .command(&Command::Add(AddCommand {
+                paths: vec![path],
+                recursive: false,
+                verify: false,
+            }))
+            .await
+            .unwrap();
+        hashes.push(cindy.hasher().hash_data(content.as_bytes()));
+    }
+    let database = cindy.database().await;
+    for (hash, phash) in hashes.iter().zip([0, 1 << 63 | 1, u64::MAX]) {
+        database.hash_phash_set(hash, phash).unwrap();
+    }
+    drop(database);
+
+    let router = cindy.router();
+    let groups = router.send(SimilarFiles::default()).await.unwrap();
+    assert_eq!(groups, [&hashes[..2]]);
+    let groups = router
+        .send(SimilarFiles { distance: Some(1) })
+        .await
+        .unwrap();
+    assert!(groups.is_empty());
+    let groups = router
+        .send(SimilarFiles {
+            distance: Some(u32::MAX),
+        })
+        .await
+        .unwrap();
+    assert_eq!(groups, [&hashes[..]]);
+
+    let similar = router
+        .send(FileSimilar {
+            hash: hashes[0].clone(),
+            distance: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(similar, [hashes[1].clone()]);
+    let similar = router
+        .send(FileSimilar {
+            hash: hashes[2].clone(),
+            distance: None,
+        })
+        .await
+        .unwrap();
+    assert!(similar.is_empty());
+    let similar = router
+        .send(FileSimilar {
+            hash: hashes[2].clone(),
+            distance: Some(u32::MAX),
+        })
+        .await
+        .unwrap();
+    assert_eq!(similar.len(), hashes.len() - 1);
+
+    // changed perceptual hashes are picked up
+    let database = cindy.database().await;
+    database.hash_phash_set(&hashes[2], 0b11).unwrap();
+    drop(database);
+    let mut similar = router
+        .send(FileSimilar {
+            hash: hashes[2].clone(),
+            distance: None,
+        })
+        .await
+        .unwrap();
+    similar.sort();
+    let mut expected = hashes[..2].to_vec();
+    expected.sort();
+    assert_eq!(similar, expected);
+}
+
+#[tokio::test]
+async fn similar_videos() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    let mut hashes = vec![];
+    for content in ["video", "trimmed", "other"] {
+        let path = dir.path().join(content);
+        write(&path, content).unwrap();
+        cindy
+            .command(&Command::Add(AddCommand {
+                paths: vec![path],
+                recursive: false,
+                verify: false,
+            }))
+            .await
+            .unwrap();
+        hashes.push(cindy.hasher().hash_data(content.as_bytes()));
+    }
+    let video: Vec<u64> = (0..8).map(|frame| frame * 0x0101_0101_0101_0101).collect();
+    let other: Vec<u64> = video.iter().map(|phash| !phash).collect();
+    let database = cindy.database().await;
+    for (hash, fingerprint) in hashes.iter().zip([&video[..], &video[2..], &other[..]]) {
+        database.hash_fingerprint_set(hash, fingerprint).unwrap();
+    }
+    drop(database);
+
+    let router = cindy.router();
+    let groups = router.send(SimilarFiles::default()).await.unwrap();
+    assert_eq!(groups, [&hashes[..2]]);
+    let similar = router
+        .send(FileSimilar {
+            hash: hashes[1].clone(),
+            distance: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(similar, [hashes[0].clone()]);
+}
+
+#[tokio::test]
+async fn duplicate_files() {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    for (name, content) in [("file", "data"), ("copy", "data"), ("other", "other")] {
+        write(dir.path().join(name), content).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+
+    let router = cindy.router();
+    let duplicates = router.send(Duplicates).await.unwrap();
+    assert_eq!(
+        duplicates,
+        [Duplicate::new(
+            cindy.hasher().hash_data(b"data"),
+            4,
+            vec!["copy".into(), "file".into()]
+        )]
+    );
+    assert_eq!(duplicates[0].reclaimable, 4);
+}
+
+/// Tag names and values used when checking cache invalidation.
+const NAMES: [&str; 3] = ["a", "b", "c"];
+const VALUES: [&str; 2] = ["x", "y"];
+
+/// Change made through the API, which may fail if it does not apply.
+#[derive(Clone, Debug)]
+enum Change {
+    TagNameCreate(&'static str),
+    TagNameRename(&'static str, &'static str),
+    TagValueCreate(&'static str, &'static str),
+    TagValueDelete(Option<&'static str>, Option<&'static str>),
+    FileTagCreate(usize, &'static str, &'static str),
+    FileTagDelete(usize, Option<&'static str>, Option<&'static str>),
+    QueryTagCreate(&'static str, &'static str, &'static str),
+    QueryTagRemove(&'static str, Option<&'static str>, Option<&'static str>),
+}
+
+/// Files which have a tag with the given name.
+fn tagged(name: &'static str) -> Vec<TagPredicate<'static>> {
+    vec![TagFilter::new(Some(name), None).exists()]
+}
+
+impl Change {
+    async fn apply(&self, router: &Router, hashes: &[BoxHash]) {
+        match *self {
+            Change::TagNameCreate(name) => {
+                send_with(
+                    router,
+                    TagNameCreate {
+                        name,
+                        display: None,
+                    },
+                    None,
+                )
+                .await
+            }
+            Change::TagNameRename(name, new) => {
+                let request = TagNameEdit {
+                    name,
+                    name_new: Some(new),
+                    display_new: None,
+                };
+                send_with(router, request, None).await
+            }
+            Change::TagValueCreate(name, value) => {
+                let request = TagValueCreate {
+                    name,
+                    value,
+                    display: None,
+                };
+                send_with(router, request, None).await
+            }
+            Change::TagValueDelete(name, value) => {
+                send_with(router, TagDelete { name, value }, None).await
+            }
+            Change::FileTagCreate(file, name, value) => {
+                let request = FileTagCreate {
+                    hash: hashes[file].clone(),
+                    name,
+                    value,
+                };
+                send_with(router, request, None).await
+            }
+            Change::FileTagDelete(file, name, value) => {
+                let request = FileTagDelete {
+                    hash: hashes[file].clone(),
+                    name,
+                    value,
+                };
+                send_with(router, request, None).await
+            }
+            Change::QueryTagCreate(query, name, value) => {
+                let request = QueryTagCreate {
+                    query: tagged(query),
+                    name,
+                    value,
+                };
+                send_with(router, request, None).await
+            }
+            Change::QueryTagRemove(query, name, value) => {
+                let request = QueryTagRemove {
+                    query: tagged(query),
+                    name,
+                    value,
+                };
+                send_with(router, request, None).await
+            }
+        };
+    }
+}
+
+/// Request whose response the frontend caches.
+#[derive(Clone, Debug)]
+enum Observed {
+    FileTags(usize, Option<&'static str>, Option<&'static str>),
+    TagNames,
+    TagList(TagList<&'static str, &'static str>),
+    QueryFiles(QueryFiles<'static>),
+    QueryTags(QueryTags),
+}
+
+impl Observed {
+    fn file_tags(
+        hashes: &[BoxHash],
+        file: usize,
+        name: Option<&'static str>,
+        value: Option<&'static str>,
+    ) -> FileTags<BoxHash, &'static str> {
+        FileTags {
+            hash: hashes[file].clone(),
+            name,
+            value,
+        }
+    }
+
+    fn invalidated_by(&self, mutation: &Mutation, hashes: &[BoxHash]) -> bool {
+        match *self {
+            Observed::FileTags(file, name, value) => {
+                Self::file_tags(hashes, file, name, value).invalidated_by(mutation)
+            }
+            Observed::TagNames => TagNames.invalidated_by(mutation),
+            Observed::TagList(ref request) => request.invalidated_by(mutation),
+            Observed::QueryFiles(ref request) => request.invalidated_by(mutation),
+            Observed::QueryTags(ref request) => request.invalidated_by(mutation),
+        }
+    }
+
+    /// Fetch the response, ignoring the order of lists.
+    async fn fetch(&self, router: &Router, hashes: &[BoxHash]) -> serde_json::Value {
+        let value = match *self {
+            Observed::FileTags(file, name, value) => {
+                let request = Self::file_tags(hashes, file, name, value);
+                let mut tags = router.send(request).await.unwrap();
+                tags.sort();
+                serde_json::to_value(tags)
+            }
+            Observed::TagNames => serde_json::to_value(router.send(TagNames).await.unwrap()),
+            Observed::TagList(ref request) => {
+                serde_json::to_value(router.send(request.clone()).await.unwrap())
+            }
+            Observed::QueryFiles(ref request) => {
+                let mut files = router.send(request.clone()).await.unwrap();
+                files.sort();
+                serde_json::to_value(files)
+            }
+            Observed::QueryTags(ref request) => {
+                serde_json::to_value(router.send(request.clone()).await.unwrap())
+            }
+        };
+        value.unwrap()
+    }
+}
+
+fn arb_name() -> impl Strategy<Value = &'static str> {
+    proptest::sample::select(&NAMES[..])
+}
+
+fn arb_value() -> impl Strategy<Value = &'static str> {
+    proptest::sample::select(&VALUES[..])
+}
+
+fn arb_file() -> impl Strategy<Value = usize> {
+    0..3usize
+}
+
+fn arb_change() -> impl Strategy<Value = Change> {
+    let name = || proptest::option::of(arb_name());
+    let value = || proptest::option::of(arb_value());
+    prop_oneof![
+        arb_name().prop_map(Change::TagNameCreate),
+        (arb_name(), arb_name()).prop_map(|(n, new)| Change::TagNameRename(n, new)),
+        (arb_name(), arb_value()).prop_map(|(n, v)| Change::TagValueCreate(n, v)),
+        (name(), value()).prop_map(|(n, v)| Change::TagValueDelete(n, v)),
+        (arb_file(), arb_name(), arb_value()).prop_map(|(f, n, v)| Change::FileTagCreate(f, n, v)),
+        (arb_file(), name(), value()).prop_map(|(f, n, v)| Change::FileTagDelete(f, n, v)),
+        (arb_name(), arb_name(), arb_value()).prop_map(|(q, n, v)| Change::QueryTagCreate(q, n, v)),
+        (arb_name(), name(), value()).prop_map(|(q, n, v)| Change::QueryTagRemove(q, n, v)),
+    ]
+}
+
+fn arb_observed() -> impl Strategy<Value = Observed> {
+    let name = || proptest::option::of(arb_name());
+    let value = || proptest::option::of(arb_value());
+    prop_oneof![
+        (arb_file(), name(), value()).prop_map(|(f, n, v)| Observed::FileTags(f, n, v)),
+        Just(Observed::TagNames),
+        (name(), value()).prop_map(|(name, value)| Observed::TagList(TagList { name, value })),
+        arb_name().prop_map(|query| Observed::QueryFiles(QueryFiles {
+            query: tagged(query).into()
+        })),
+        (arb_name(), name(), value(), any::<bool>()).prop_map(|(query, name, value, union)| {
+            Observed::QueryTags(QueryTags {
+                name: name.map(Into::into),
+                value: value.map(Into::into),
+                query: tagged(query),
+                mode: match union {
+                    true => QueryTagsMode::Union,
+                    false => QueryTagsMode::Intersection,
+                },
+            })
+        }),
+    ]
+}
+
+/// Contents of the files used when checking cache invalidation.
+const CONTENTS: [&str; 3] = ["first", "second", "third"];
+
+/// Apply changes through the API and check that every cached response which changed was
+/// invalidated by one of the mutations the server published.
+async fn check_invalidation(
+    changes: Vec<Change>,
+    observed: Vec<Observed>,
+) -> Result<(), TestCaseError> {
+    let dir = tempdir().unwrap();
+    let cindy = Cindy::initialize(dir.path(), &Config::default())
+        .await
+        .unwrap();
+    for content in CONTENTS {
+        write(dir.path().join(content), content).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+
+    let router = cindy.router();
+    let hashes: Vec<BoxHash> = CONTENTS
+        .iter()
+        .map(|content| cindy.hasher().hash_data(content.as_bytes()))
+        .collect();
+    for name in NAMES {
+        Change::TagNameCreate(name).apply(&router, &hashes).await;
+        for value in VALUES {
+            Change::TagValueCreate(name, value)
+                .apply(&router, &hashes)
+                .await;
+        }
+    }
+
+    let mut mutations = cindy.mutations();
+    let mut cached = vec![];
+    for request in &observed {
+        cached.push(request.fetch(&router, &hashes).await);
+    }
+
+    for change in changes {
+        change.apply(&router, &hashes).await;
+        let mut published = vec![];
+        while let Ok(mutation) = mutations.try_recv() {
+            published.push(mutation);
+        }
+
+        for (request, cached) in observed.iter().zip(&mut cached) {
+            let fresh = request.fetch(&router, &hashes).await;
+            if fresh != *cached {
+                prop_assert!(
+                    published
+                        .iter()
+                        .any(|mutation| request.invalidated_by(mutation, &hashes)),
+                    "{request:?} changed by {change:?} but not invalidated by {published:?}"
+                );
+            }
+            *cached = fresh;
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+    #[test]
+    fn invalidation_no_stale_responses(
+        changes in proptest::collection::vec(arb_change(), 1..16),
+        observed in proptest::collection::vec(arb_observed(), 1..16))
+    {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(check_invalidation(changes, observed))?;
+    }
+}
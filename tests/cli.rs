@@ -1,5 +1,16 @@
-use cindy::{cli::*, hash::DataHasher, Cindy, Command, Config, Tag, TagFilter};
-use std::{fs::*, path::Path};
+use cindy::{
+    cli::*,
+    common::{BatchOperation, Label, Origin, Sequence},
+    config::{DataMode, HashAlgorithm},
+    fsck, gc,
+    hash::DataHasher,
+    Cindy, Command, Config, Tag, TagFilter,
+};
+use std::{
+    fs::*,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tempfile::tempdir;
 
 fn assert_file(path: &Path) {
@@ -106,6 +117,7 @@ async fn test_add_file() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -149,6 +161,7 @@ async fn test_add_files_recursively() {
         .command(&Command::Add(AddCommand {
             paths: vec![dir.path().join("folder")],
             recursive: true,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -217,6 +230,7 @@ async fn test_media_info_tags() {
         .command(&Command::Add(AddCommand {
             paths: vec![dir.path().into()],
             recursive: true,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -246,6 +260,7 @@ async fn test_query() {
         .command(&Command::Add(AddCommand {
             paths: vec![file_path],
             recursive: false,
+            verify: false,
         }))
         .await
         .unwrap();
@@ -303,3 +318,783 @@ async fn test_tags_create() {
     assert!(tags.contains_key(&tag1));
     assert!(tags.contains_key(&tag2));
 }
+
+#[tokio::test]
+async fn test_history_undo() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(&dir.path(), &config).await.unwrap();
+    let tag = Tag::new("name".into(), "value".into());
+
+    cindy
+        .command(&Command::Tags(TagsCommand::Create(TagsCreateCommand {
+            tags: vec![tag.clone()],
+        })))
+        .await
+        .unwrap();
+    cindy
+        .command(&Command::History(HistoryCommand {
+            limit: 10,
+            all: false,
+        }))
+        .await
+        .unwrap();
+    let database = cindy.database().await;
+    let batches = database.journal_batches(10, false).unwrap();
+    drop(database);
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].changes.len(), 2);
+
+    cindy
+        .command(&Command::Undo(UndoCommand { count: 1 }))
+        .await
+        .unwrap();
+    let database = cindy.database().await;
+    assert!(!database.tag_list(None, None).unwrap().contains_key(&tag));
+    assert!(database.journal_batches(10, false).unwrap().is_empty());
+    drop(database);
+
+    // nothing left to undo
+    cindy
+        .command(&Command::Undo(UndoCommand { count: 1 }))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_rehash() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+
+    let content = "hello";
+    let file_path = dir.path().join("file.txt");
+    write(&file_path, content).unwrap();
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![file_path],
+            recursive: false,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let old = cindy.hasher().hash_data(content.as_bytes());
+
+    // label and journal entry referencing the file
+    let tag = Tag::new("filename".into(), "file.txt".into());
+    let label = Label::Sequence(Sequence { start: 1, end: 2 });
+    let operation = BatchOperation::LabelAdd {
+        file: old.clone(),
+        tag: tag.clone(),
+        label,
+    };
+    let database = cindy.database().await;
+    database
+        .label_add(&old, tag.name(), tag.value(), &label)
+        .unwrap();
+    let batch = database.journal_batch_create(0, &Origin::Cli).unwrap();
+    database.journal_change_add(batch, &operation, &[]).unwrap();
+    drop(database);
+
+    cindy
+        .command(&Command::Rehash(RehashCommand {
+            algorithm: HashAlgorithm::Blake3,
+        }))
+        .await
+        .unwrap();
+
+    let cindy = Cindy::load(dir.path()).await.unwrap();
+    assert_eq!(cindy.config().data.hash, HashAlgorithm::Blake3);
+    let new = HashAlgorithm::Blake3.hash_data(content.as_bytes());
+    assert_eq!(read_to_string(cindy.hash_path(&new)).unwrap(), content);
+    assert!(!cindy.hash_path(&old).exists());
+    assert_dir(&cindy.thumbs_path());
+
+    // tags, labels and history now refer to the new hash
+    let database = cindy.database().await;
+    assert!(!database.hash_exists(&old).unwrap());
+    assert!(database.hash_tags(&new, None, None).unwrap().contains(&tag));
+    let labels = database
+        .label_get(Some(&new), None, None, None, None)
+        .unwrap();
+    assert_eq!(labels.len(), 1);
+    let batches = database.journal_batches(10, false).unwrap();
+    assert_eq!(
+        batches[0].changes[0].operation,
+        BatchOperation::LabelAdd {
+            file: new.clone(),
+            tag,
+            label,
+        }
+    );
+    drop(database);
+
+    // switching to the same algorithm fails
+    let result = cindy
+        .command(&Command::Rehash(RehashCommand {
+            algorithm: HashAlgorithm::Blake3,
+        }))
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_rehash_recover() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    let staging = cindy.cindy_folder().join("data.rehash");
+    let pending = cindy.cindy_folder().join("config.toml.rehash");
+    let mut rehashed = config.clone();
+    rehashed.data.hash = HashAlgorithm::Sha256;
+
+    // not committed, so it is discarded
+    create_dir(&staging).unwrap();
+    write(&pending, toml::to_string(&rehashed).unwrap()).unwrap();
+    let cindy = Cindy::load(dir.path()).await.unwrap();
+    assert_eq!(cindy.config().data.hash, config.data.hash);
+    assert!(!staging.exists());
+    assert!(!pending.exists());
+
+    // committed, so it is finished
+    create_dir(&staging).unwrap();
+    write(staging.join("marker"), "").unwrap();
+    write(&pending, toml::to_string(&rehashed).unwrap()).unwrap();
+    let database = cindy.database().await;
+    database
+        .execute(
+            "INSERT INTO config(name, value) VALUES ('hash', 'sha256')",
+            [],
+        )
+        .unwrap();
+    drop(database);
+    let cindy = Cindy::load(dir.path()).await.unwrap();
+    assert_eq!(cindy.config().data.hash, HashAlgorithm::Sha256);
+    assert_file(&cindy.data_path().join("marker"));
+    assert!(!staging.exists());
+    assert!(!pending.exists());
+}
+
+#[tokio::test]
+async fn test_add_unchanged() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    let file_path = dir.path().join("file.txt");
+    let add = |verify| {
+        Command::Add(AddCommand {
+            paths: vec![file_path.clone()],
+            recursive: false,
+            verify,
+        })
+    };
+
+    write(&file_path, "hello").unwrap();
+    cindy.command(&add(false)).await.unwrap();
+    let old = cindy.hasher().hash_data(b"hello");
+
+    // change the contents without changing the size or modification time
+    let modified = metadata(&file_path).unwrap().modified().unwrap();
+    write(&file_path, "world").unwrap();
+    File::options()
+        .write(true)
+        .open(&file_path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let new = cindy.hasher().hash_data(b"world");
+
+    // trusts the recorded hash, unless verifying
+    cindy.command(&add(false)).await.unwrap();
+    assert!(!cindy.database().await.hash_exists(&new).unwrap());
+    cindy.command(&add(true)).await.unwrap();
+    let database = cindy.database().await;
+    assert!(database.hash_exists(&old).unwrap());
+    assert!(database.hash_exists(&new).unwrap());
+    assert_eq!(
+        database
+            .file_stat(Path::new("file.txt"))
+            .unwrap()
+            .unwrap()
+            .1,
+        new
+    );
+}
+
+#[tokio::test]
+async fn test_add_mime_missing() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    copy("samples/image1.png", dir.path().join("image.png")).unwrap();
+    let add = Command::Add(AddCommand {
+        paths: vec![dir.path().join("image.png")],
+        recursive: false,
+        verify: false,
+    });
+    cindy.command(&add).await.unwrap();
+    let hash = cindy.hash_file(Path::new("image.png")).unwrap();
+    let mime = Tag::new("mime".into(), "image/png".into());
+
+    // as if it was indexed before MIME types were detected
+    let database = cindy.database().await;
+    database.hash_tag_remove(&hash, Some("mime"), None).unwrap();
+    database
+        .execute(
+            "INSERT INTO mime_pending(file_id) SELECT id FROM files WHERE hash = ?",
+            [hash.as_slice()],
+        )
+        .unwrap();
+    drop(database);
+
+    // detected when adding files again, even though this one is unchanged
+    cindy.command(&add).await.unwrap();
+    let database = cindy.database().await;
+    assert!(database
+        .hash_tags(&hash, None, None)
+        .unwrap()
+        .contains(&mime));
+    assert!(database.hashes_mime_pending().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_transcode_concurrency_zero() {
+    let dir = tempdir().unwrap();
+    let mut config = Config::default();
+    config.transcode.concurrency = 0;
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+
+    // there is still one slot, rather than waiting forever for one
+    let _permit = tokio::time::timeout(Duration::from_secs(1), cindy.transcode_permit())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_add_resume() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config)
+        .await
+        .unwrap()
+        .with_threads(1);
+    let folder = dir.path().join("folder");
+    create_dir(&folder).unwrap();
+    for index in 0..2000 {
+        write(folder.join(format!("{index}.txt")), index.to_string()).unwrap();
+    }
+
+    // cancel once the first files have been committed
+    let job = cindy.jobs().create("add");
+    let mut progress = job.subscribe();
+    let paths = [folder];
+    let add = cindy.add_files(&paths, true, false, &job);
+    let cancel = async {
+        progress.wait_for(|info| info.done > 0).await.unwrap();
+        job.cancel();
+    };
+    let (_, cancelled) = tokio::join!(cancel, add);
+    let done = job.info().done;
+    assert!(done > 0);
+
+    // files committed before cancelling were kept
+    let hashes = cindy
+        .database_read()
+        .await
+        .unwrap()
+        .query_hashes(&mut [].iter())
+        .unwrap();
+    assert!(hashes.len() as u64 >= done);
+    assert!(cancelled.is_err() || hashes.len() == 2000);
+
+    // adding again skips the ones that were committed
+    let job = cindy.jobs().create("add");
+    cindy.add_files(&paths, true, false, &job).await.unwrap();
+    let hashes = cindy
+        .database_read()
+        .await
+        .unwrap()
+        .query_hashes(&mut [].iter())
+        .unwrap();
+    assert_eq!(hashes.len(), 2000);
+    for index in [0, 999, 1999] {
+        let hash = cindy.hasher().hash_data(index.to_string().as_bytes());
+        assert!(hashes.contains(&hash));
+    }
+}
+
+#[tokio::test]
+async fn test_data_modes() {
+    use std::os::unix::fs::MetadataExt;
+
+    for mode in [
+        DataMode::Auto,
+        DataMode::Hardlink,
+        DataMode::Copy,
+        DataMode::Reference,
+    ] {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.data.mode = mode;
+        let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+        let file_path = dir.path().join("file.txt");
+        write(&file_path, "hello").unwrap();
+        cindy
+            .command(&Command::Add(AddCommand {
+                paths: vec![file_path.clone()],
+                recursive: false,
+                verify: false,
+            }))
+            .await
+            .unwrap();
+
+        let hash = cindy.hasher().hash_data(b"hello");
+        let path = cindy.file_path(&hash).await.unwrap().unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "hello");
+        let tags = cindy.database().await.hash_tags(&hash, None, None).unwrap();
+        assert!(tags.contains(&Tag::new("filesize".into(), "5".into())));
+
+        let inode = metadata(&file_path).unwrap().ino();
+        match mode {
+            DataMode::Hardlink => assert_eq!(metadata(&path).unwrap().ino(), inode),
+            DataMode::Copy => assert_ne!(metadata(&path).unwrap().ino(), inode),
+            DataMode::Auto => assert_eq!(path, cindy.hash_path(&hash)),
+            _ => {
+                assert_eq!(path, file_path);
+                assert!(!cindy.hash_path(&hash).exists());
+
+                // rehashing reads files from where they are
+                let job = cindy.jobs().create("rehash");
+                cindy.rehash(&HashAlgorithm::Sha256, &job).await.unwrap();
+                let cindy = Cindy::load(dir.path()).await.unwrap();
+                let hash = cindy.hasher().hash_data(b"hello");
+                assert_eq!(
+                    cindy.file_path(&hash).await.unwrap(),
+                    Some(file_path.clone())
+                );
+
+                // nothing to read once the file is gone
+                remove_file(&file_path).unwrap();
+                assert_eq!(cindy.file_path(&hash).await.unwrap(), None);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_fsck() {
+    let dir = tempdir().unwrap();
+    let mut config = Config::default();
+    config.data.mode = DataMode::Copy;
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    for name in ["a", "b", "c"] {
+        write(dir.path().join(name), name).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let [a, b, c] = ["a", "b", "c"].map(|name| cindy.hasher().hash_data(name.as_bytes()));
+    let job = cindy.jobs().create("fsck");
+    assert_eq!(
+        cindy.fsck(false, None, Duration::ZERO, &job).await.unwrap(),
+        []
+    );
+
+    // lose data, let it rot, leave something behind and remove a tag name without its values
+    remove_file(cindy.hash_path(&a)).unwrap();
+    write(cindy.hash_path(&b), "rotten").unwrap();
+    let orphan = cindy.data_path().join("00").join("orphan");
+    create_dir_all(orphan.parent().unwrap()).unwrap();
+    write(&orphan, "orphan").unwrap();
+    let temporary = cindy.data_path().join("00").join(".tmpcopy");
+    write(&temporary, "copy").unwrap();
+    let database = cindy.database().await;
+    database.tag_name_create("person", None).unwrap();
+    database.tag_value_create("person", "alice").unwrap();
+    database.hash_tag_add(&c, "person", "alice").unwrap();
+    database
+        .execute_batch(
+            "PRAGMA foreign_keys=OFF;
+            DELETE FROM tag_names WHERE name = 'person';
+            PRAGMA foreign_keys=ON;",
+        )
+        .unwrap();
+    drop(database);
+
+    let problems = |findings: Vec<fsck::Finding>, repaired| {
+        assert!(findings.iter().all(|finding| finding.repaired == repaired));
+        findings
+            .into_iter()
+            .map(|finding| finding.problem)
+            .collect::<Vec<_>>()
+    };
+    let found = problems(
+        cindy.fsck(false, None, Duration::ZERO, &job).await.unwrap(),
+        false,
+    );
+    assert_eq!(found.len(), 5);
+    assert!(found.contains(&fsck::Problem::Missing(a.clone())));
+    assert!(found.contains(&fsck::Problem::Mismatch {
+        hash: b.clone(),
+        actual: cindy.hasher().hash_data(b"rotten"),
+    }));
+    assert!(found.contains(&fsck::Problem::Orphan(orphan.clone())));
+    assert!(found.iter().any(
+        |problem| matches!(problem, fsck::Problem::TagValue { value, .. } if value == "alice")
+    ));
+    assert!(found
+        .iter()
+        .any(|problem| matches!(problem, fsck::Problem::FileTag(_))));
+
+    // data is restored from the files it was added from
+    assert_eq!(
+        problems(
+            cindy.fsck(true, None, Duration::ZERO, &job).await.unwrap(),
+            true
+        ),
+        found
+    );
+    assert_eq!(
+        cindy.fsck(false, None, Duration::ZERO, &job).await.unwrap(),
+        []
+    );
+    assert_eq!(read_to_string(cindy.hash_path(&a)).unwrap(), "a");
+    assert_eq!(read_to_string(cindy.hash_path(&b)).unwrap(), "b");
+    assert!(!orphan.exists());
+    assert!(temporary.exists());
+
+    // data files which were just written might belong to files being added
+    write(&orphan, "orphan").unwrap();
+    let recent = cindy.fsck(true, None, Duration::from_secs(3600), &job);
+    assert_eq!(recent.await.unwrap(), []);
+    assert!(orphan.exists());
+}
+
+#[tokio::test]
+async fn test_fsck_scrub() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    for name in ["a", "b", "c"] {
+        write(dir.path().join(name), name).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let order = || async {
+        cindy
+            .database_read()
+            .await
+            .unwrap()
+            .hashes_by_verified()
+            .unwrap()
+    };
+    let before = order().await;
+
+    // hashes a third of the files each time, the one verified longest ago first
+    let job = cindy.jobs().create("fsck");
+    cindy
+        .fsck(false, Some(3), Duration::ZERO, &job)
+        .await
+        .unwrap();
+    let after = order().await;
+    assert_eq!(after[..2], before[1..]);
+    assert_eq!(after[2], before[0]);
+}
+
+#[tokio::test]
+async fn test_gc() {
+    let dir = tempdir().unwrap();
+    let mut config = Config::default();
+    config.data.mode = DataMode::Hardlink;
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    for name in ["a", "b"] {
+        write(dir.path().join(name), name).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let [a, b] = ["a", "b"].map(|name| cindy.hasher().hash_data(name.as_bytes()));
+    for hash in [&a, &b] {
+        write(
+            cindy.thumbs_path().join(format!("{hash}-crop-0-0-1-1.png")),
+            "thumb",
+        )
+        .unwrap();
+        let segments = cindy.hls_path().join(hash.to_string()).join("480");
+        create_dir_all(&segments).unwrap();
+        write(segments.join("0.ts"), "segment").unwrap();
+    }
+
+    // remove b from the index
+    let database = cindy.database().await;
+    database
+        .execute(
+            "DELETE FROM file_tag_values WHERE file_id = (SELECT id FROM files WHERE hash = ?)",
+            [b.as_slice()],
+        )
+        .unwrap();
+    database.hash_remove(&b).unwrap();
+    drop(database);
+
+    // the data of b is still linked from where it was added, so only the rest frees space
+    let job = cindy.jobs().create("gc");
+    let expected = gc::Report {
+        files: 3,
+        freed: 2,
+        bytes: 12,
+    };
+    assert_eq!(
+        cindy.gc(true, Duration::ZERO, &job).await.unwrap(),
+        expected
+    );
+    assert!(cindy.hash_path(&b).exists());
+    let recent = cindy.gc(false, Duration::from_secs(3600), &job).await;
+    assert_eq!(recent.unwrap(), gc::Report::default());
+    assert_eq!(
+        cindy.gc(false, Duration::ZERO, &job).await.unwrap(),
+        expected
+    );
+
+    assert!(!cindy.hash_path(&b).exists());
+    assert!(!cindy.hls_path().join(b.to_string()).exists());
+    assert_eq!(read_dir(cindy.thumbs_path()).unwrap().count(), 1);
+    assert!(cindy.hash_path(&a).exists());
+    assert!(cindy
+        .hls_path()
+        .join(a.to_string())
+        .join("480")
+        .join("0.ts")
+        .exists());
+}
+
+#[tokio::test]
+async fn test_migrate_layout() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    for name in ["a", "b"] {
+        write(dir.path().join(name), name).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let hashes = ["a", "b"].map(|name| cindy.hasher().hash_data(name.as_bytes()));
+    let thumb = format!("{}-crop-0-0-1-1.png", hashes[0]);
+    write(cindy.thumbs_path().join(&thumb), "thumb").unwrap();
+
+    let job = cindy.jobs().create("migrate-layout");
+    let unchanged = cindy
+        .migrate_layout(
+            &config.data.path,
+            &config.data.prefix,
+            &config.thumbs.path,
+            &job,
+        )
+        .await;
+    assert!(unchanged.is_err());
+    let invalid = cindy
+        .migrate_layout(&config.data.path, &[0], &config.thumbs.path, &job)
+        .await;
+    assert!(invalid.is_err());
+    for path in [config.data.path.join("nested"), PathBuf::new()] {
+        let nested = cindy
+            .migrate_layout(&path, &config.data.prefix, &config.thumbs.path, &job)
+            .await;
+        assert!(nested.is_err());
+    }
+    let segments = cindy.hls_path().join(hashes[0].to_string()).join("480");
+    create_dir_all(&segments).unwrap();
+    write(segments.join("0.ts"), "segment").unwrap();
+    cindy
+        .migrate_layout(Path::new("store"), &[1], Path::new("previews"), &job)
+        .await
+        .unwrap();
+
+    let mut expected = config.clone();
+    expected.data.path = "store".into();
+    expected.data.prefix = vec![1];
+    expected.thumbs.path = "previews".into();
+    assert_config(&cindy.config_path(), &expected);
+    assert!(!cindy.data_path().exists());
+    assert!(!cindy.thumbs_path().exists());
+    assert!(!cindy.hls_path().exists());
+
+    let cindy = Cindy::load(dir.path()).await.unwrap();
+    assert_eq!(cindy.data_path(), cindy.cindy_folder().join("store"));
+    for (hash, name) in hashes.iter().zip(["a", "b"]) {
+        let path = cindy.hash_path(hash);
+        assert_eq!(path.parent().unwrap().parent().unwrap(), cindy.data_path());
+        assert_eq!(read_to_string(path).unwrap(), name);
+    }
+    assert_file(&cindy.thumbs_path().join(&thumb));
+}
+
+#[tokio::test]
+async fn test_migrate_layout_recover() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    for name in ["a", "b"] {
+        write(dir.path().join(name), name).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let [a, b] = ["a", "b"].map(|name| cindy.hasher().hash_data(name.as_bytes()));
+
+    // interrupted after moving one of the files
+    let mut migrated = config.clone();
+    migrated.data.prefix = vec![3];
+    let pending = cindy.cindy_folder().join("config.toml.layout");
+    write(&pending, toml::to_string(&migrated).unwrap()).unwrap();
+    let target = cindy.cindy_folder().join(migrated.data.data_path(&a));
+    create_dir_all(target.parent().unwrap()).unwrap();
+    rename(cindy.hash_path(&a), &target).unwrap();
+
+    let cindy = Cindy::load(dir.path()).await.unwrap();
+    assert_eq!(cindy.config().data.prefix, vec![3]);
+    assert!(!pending.exists());
+    assert_eq!(read_to_string(cindy.hash_path(&a)).unwrap(), "a");
+    assert_eq!(read_to_string(cindy.hash_path(&b)).unwrap(), "b");
+}
+
+#[tokio::test]
+async fn test_duplicates() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    for (name, content) in [("a", "a"), ("copy", "a"), ("b", "b"), ("c", "c")] {
+        write(dir.path().join(name), content).unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let [a, b, c] = ["a", "b", "c"].map(|name| cindy.hasher().hash_data(name.as_bytes()));
+
+    let duplicates = cindy.duplicates().await.unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].hash, a);
+    assert_eq!(duplicates[0].paths, ["a", "copy"].map(PathBuf::from));
+    assert_eq!(duplicates[0].reclaimable, 1);
+
+    // perceptual hashes are only computed for images
+    assert!(cindy.similar_files(64).await.unwrap().is_empty());
+    let database = cindy.database().await;
+    for (hash, phash) in [(&a, 0), (&b, 0b111), (&c, u64::MAX)] {
+        database.hash_phash_set(hash, phash).unwrap();
+    }
+    drop(database);
+
+    let mut groups = cindy.similar_files(3).await.unwrap();
+    groups.iter_mut().for_each(|group| group.sort());
+    let mut expected = vec![a.clone(), b.clone()];
+    expected.sort();
+    assert_eq!(groups, [expected]);
+    assert!(cindy.similar_files(2).await.unwrap().is_empty());
+    assert_eq!(cindy.similar_files(64).await.unwrap()[0].len(), 3);
+
+    for (similar, distance) in [(false, 3), (true, 3), (true, u32::MAX)] {
+        cindy
+            .command(&Command::Duplicates(DuplicatesCommand {
+                similar,
+                distance,
+                keep: None,
+                dry_run: false,
+            }))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_duplicates_keep() {
+    let dir = tempdir().unwrap();
+    let config = Config::default();
+    let cindy = Cindy::initialize(dir.path(), &config).await.unwrap();
+    create_dir(dir.path().join("a")).unwrap();
+    create_dir(dir.path().join("b")).unwrap();
+    for name in ["a/file", "b/file", "copy"] {
+        write(dir.path().join(name), "data").unwrap();
+    }
+    cindy
+        .command(&Command::Add(AddCommand {
+            paths: vec![dir.path().into()],
+            recursive: true,
+            verify: false,
+        }))
+        .await
+        .unwrap();
+    let hash = cindy.hasher().hash_data(b"data");
+
+    let job = cindy.jobs().create("duplicates");
+    let removed = cindy
+        .duplicates_remove(&KeepPolicy::Shortest, true, &job)
+        .await
+        .unwrap();
+    let paths: Vec<_> = removed.into_iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, ["a/file", "b/file"].map(PathBuf::from));
+    assert_file(&dir.path().join("a/file"));
+
+    // copies which changed since they were added are left alone
+    write(dir.path().join("copy"), "changed").unwrap();
+    let removed = cindy
+        .duplicates_remove(&KeepPolicy::Directory("b".into()), false, &job)
+        .await
+        .unwrap();
+    assert_eq!(removed, [(PathBuf::from("a/file"), 4)]);
+    assert!(!dir.path().join("a/file").exists());
+    assert_file(&dir.path().join("b/file"));
+    assert_file(&dir.path().join("copy"));
+
+    let database = cindy.database_read().await.unwrap();
+    let tags = database.hash_tags(&hash, None, None).unwrap();
+    assert!(!tags.contains(&Tag::new("path".into(), "/a/file".into())));
+    assert!(!tags.contains(&Tag::new("ancestor".into(), "/a".into())));
+    assert!(tags.contains(&Tag::new("ancestor".into(), "/".into())));
+    assert!(tags.contains(&Tag::new("filename".into(), "file".into())));
+    assert_eq!(
+        database.file_stat_paths(&hash).unwrap(),
+        ["b/file", "copy"].map(PathBuf::from)
+    );
+    drop(database);
+    assert_eq!(cindy.duplicates().await.unwrap()[0].paths.len(), 2);
+
+    // nothing is removed when no copy satisfies the policy
+    let removed = cindy
+        .duplicates_remove(&KeepPolicy::Directory("c".into()), false, &job)
+        .await
+        .unwrap();
+    assert!(removed.is_empty());
+    assert!(cindy.file_path(&hash).await.unwrap().unwrap().is_file());
+}
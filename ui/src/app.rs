@@ -6,6 +6,9 @@ use home::*;
 mod file;
 use file::*;
 
+mod login;
+use login::*;
+
 mod settings;
 use settings::*;
 
@@ -15,6 +18,8 @@ pub enum Route {
     Home,
     #[at("/file/:hash")]
     File { hash: RcHash },
+    #[at("/login")]
+    Login,
     #[at("/settings")]
     SettingsRoot,
     #[at("/settings/*")]
@@ -34,6 +39,7 @@ impl Route {
         match self {
             Route::Home => html! { <HomeView /> },
             Route::File { hash } => html! { <FileView {hash} /> },
+            Route::Login => html! { <LoginView /> },
             Route::SettingsRoot | Route::SettingsChild => html! { <SettingsView />  },
         }
     }
@@ -11,6 +11,7 @@ pub fn HomeView() -> Html {
                 <SidebarLayout>
                     <SidebarLayoutSidebar>
                         <QuerySidebar />
+                        <UploadArea />
                     </SidebarLayoutSidebar>
                     <SidebarLayoutContent>
                         <FilesGrid />
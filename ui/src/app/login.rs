@@ -0,0 +1,65 @@
+use crate::prelude::*;
+
+#[function_component]
+pub fn LoginView() -> Html {
+    let name = use_state(String::new);
+    let password = use_state(String::new);
+    let failed = use_state(|| false);
+    let navigator = use_navigator().unwrap();
+
+    let request = use_request_then(
+        Login {
+            name: (*name).clone(),
+            password: (*password).clone(),
+        },
+        {
+            let failed = failed.clone();
+            move |result| {
+                failed.set(result.is_err());
+                if result.is_ok() {
+                    navigator.push(&Route::home());
+                }
+            }
+        },
+    );
+
+    let name_oninput = {
+        let name = name.clone();
+        move |event: InputEvent| {
+            let target: HtmlInputElement = event.target_dyn_into().unwrap();
+            name.set(target.value());
+        }
+    };
+
+    let password_oninput = {
+        let password = password.clone();
+        move |event: InputEvent| {
+            let target: HtmlInputElement = event.target_dyn_into().unwrap();
+            password.set(target.value());
+        }
+    };
+
+    let onsubmit = {
+        let request = request.clone();
+        move |event: SubmitEvent| {
+            event.prevent_default();
+            request.run();
+        }
+    };
+
+    html! {
+        <div class="flex items-center justify-center min-h-screen bg-gray-50 dark:bg-gray-900">
+            <form {onsubmit} class="w-full max-w-sm p-6 space-y-4 bg-white border border-gray-200 rounded-lg shadow dark:bg-gray-800 dark:border-gray-700">
+                <h1 class="text-xl font-bold text-gray-900 dark:text-white">{"Sign in"}</h1>
+                <input type="text" oninput={name_oninput} class="w-full p-2 border border-gray-300 rounded-lg" placeholder="Name" autocomplete="username" />
+                <input type="password" oninput={password_oninput} class="w-full p-2 border border-gray-300 rounded-lg" placeholder="Password" autocomplete="current-password" />
+                if *failed {
+                    <p class="text-sm text-red-600 dark:text-red-500">{"Invalid name or password"}</p>
+                }
+                <button type="submit" class="w-full p-2 font-medium text-white bg-blue-600 rounded-lg hover:bg-blue-700">
+                    {"Sign in"}
+                </button>
+            </form>
+        </div>
+    }
+}
@@ -1,7 +1,12 @@
-use cindy_common::cache::{CacheKey, RcValue};
+use cindy_common::{
+    cache::{CacheKey, Invalidatable, RcValue},
+    Mutation,
+};
 use prokio::time::sleep;
 use restless::clients::gloo::GlooRequest;
 use std::{any::Any, collections::BTreeMap, fmt::Debug, rc::Rc, sync::Mutex, time::Duration};
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{EventSource, MessageEvent};
 use yew::{
     functional::{UseStateHandle, UseStateSetter},
     prelude::*,
@@ -10,6 +15,9 @@ use yew::{
 const DELAY_INITIAL: Duration = Duration::from_millis(100);
 const DELAY_MULTIPLIER: f64 = 1.5;
 
+/// Server-sent events stream of mutations.
+const EVENTS_PATH: &str = "/api/v1/events";
+
 #[derive(Clone, Default, Debug)]
 pub struct Entry {
     /// Delay to use for next request
@@ -61,15 +69,15 @@ impl Entry {
     }
 }
 
-pub trait CacheItem: CacheKey + Clone + Ord {
+pub trait CacheItem: CacheKey<Mutation> + Clone + Ord {
     //type Target: Clone + Debug + PartialEq + 'static;
 }
 
-impl<T: Debug + Clone + Ord + 'static> CacheItem for T {}
+impl<T: Debug + Clone + Ord + Invalidatable + 'static> CacheItem for T {}
 
 #[derive(Clone, Default)]
 pub struct BTreeCache {
-    pub entries: BTreeMap<Box<dyn CacheKey>, Entry>,
+    pub entries: BTreeMap<Box<dyn CacheKey<Mutation>>, Entry>,
 }
 
 #[derive(Clone, Default)]
@@ -85,12 +93,12 @@ impl PartialEq for Cache {
 
 impl BTreeCache {
     /// Unsubscribe to the value of this data.
-    pub fn mutate<T: CacheKey, R, F: FnOnce(&mut Entry) -> R>(
+    pub fn mutate<T: CacheKey<Mutation>, R, F: FnOnce(&mut Entry) -> R>(
         &mut self,
         data: &T,
         mutate: F,
     ) -> Option<R> {
-        if let Some(entry) = self.entries.get_mut(data as &dyn CacheKey) {
+        if let Some(entry) = self.entries.get_mut(data as &dyn CacheKey<Mutation>) {
             Some(mutate(entry))
         } else {
             None
@@ -98,20 +106,20 @@ impl BTreeCache {
     }
 
     /// Unsubscribe to the value of this data.
-    pub fn mutate_all<F: Fn(&Box<dyn CacheKey>, &mut Entry)>(&mut self, mutate: F) {
+    pub fn mutate_all<F: Fn(&Box<dyn CacheKey<Mutation>>, &mut Entry)>(&mut self, mutate: F) {
         for (key, entry) in &mut self.entries {
             mutate(key, entry);
         }
     }
 
     /// Unsubscribe to the value of this data.
-    pub fn insert<T: CacheKey>(&mut self, data: T, entry: Entry) {
+    pub fn insert<T: CacheKey<Mutation>>(&mut self, data: T, entry: Entry) {
         let key = Box::new(data);
         self.entries.insert(key, entry);
     }
 
-    pub fn get<T: CacheKey>(&self, data: &T) -> Option<&Entry> {
-        self.entries.get(data as &dyn CacheKey)
+    pub fn get<T: CacheKey<Mutation>>(&self, data: &T) -> Option<&Entry> {
+        self.entries.get(data as &dyn CacheKey<Mutation>)
     }
 }
 
@@ -233,6 +241,47 @@ impl Cache {
             entry.broadcast();
         });
     }
+
+    /// Invalidate the data that is affected by this mutation.
+    pub fn invalidate_mutation(&self, mutation: &Mutation) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        cache.mutate_all(|key, entry| {
+            if (**key).invalidated_by(mutation) {
+                entry.value.invalidate();
+                entry.broadcast();
+            }
+        });
+    }
+
+    /// Subscribe to mutations made on the server, to invalidate data when it changes.
+    ///
+    /// Returns the event source, which stays subscribed until it is closed.
+    fn subscribe_mutations(&self) -> Option<EventSource> {
+        let source = EventSource::new(EVENTS_PATH)
+            .map_err(|error| log::error!("Cannot subscribe to events: {error:?}"))
+            .ok()?;
+
+        let cache = self.clone();
+        let onmessage = Closure::<dyn Fn(MessageEvent)>::new(move |event: MessageEvent| {
+            let data = event.data().as_string().unwrap_or_default();
+            match serde_json::from_str::<Mutation>(&data) {
+                Ok(mutation) => cache.invalidate_mutation(&mutation),
+                Err(error) => log::error!("Cannot parse mutation {data:?}: {error}"),
+            }
+        });
+        source.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        // missed some mutations, so anything might have changed
+        let cache = self.clone();
+        let onlagged = Closure::<dyn Fn(MessageEvent)>::new(move |_| cache.invalidate_all());
+        source
+            .add_event_listener_with_callback("lagged", onlagged.as_ref().unchecked_ref())
+            .ok()?;
+        onlagged.forget();
+
+        Some(source)
+    }
 }
 
 #[derive(Properties, PartialEq)]
@@ -245,6 +294,17 @@ pub fn CacheProvider(props: &CacheProviderProps) -> Html {
     log::debug!("Creating new cache");
     let state = use_state(Cache::default);
     let context: Cache = (*state).clone();
+    use_effect_with_deps(
+        |cache: &Cache| {
+            let source = cache.subscribe_mutations();
+            move || {
+                if let Some(source) = source {
+                    source.close();
+                }
+            }
+        },
+        context.clone(),
+    );
     html! {
         <ContextProvider<Cache> {context}>
         { for props.children.iter() }
@@ -15,3 +15,6 @@ pub use icons::*;
 
 mod form;
 pub use form::*;
+
+mod upload;
+pub use upload::*;
@@ -454,6 +454,30 @@ pub fn QuerySidebar() -> Html {
     }
 }
 
+#[derive(Properties, PartialEq)]
+pub struct FileSimilarListProps {
+    pub file: RcHash,
+}
+
+#[function_component]
+pub fn FileSimilarList(props: &FileSimilarListProps) -> Html {
+    let similar = use_cached(FileSimilar {
+        hash: props.file.clone(),
+        distance: None,
+    });
+    html! {
+        <ul class="py-2 text-sm">
+        {
+            similar.data().iter().flat_map(|files| files.iter()).cloned().map(|hash| html! {
+                <li class="truncate">
+                    <Link to={Route::file(hash.clone().into())} classes="hover:text-blue-600">{hash.to_string()}</Link>
+                </li>
+            }).collect::<Html>()
+        }
+        </ul>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct FileSidebarProps {
     pub file: RcHash,
@@ -475,6 +499,9 @@ pub fn FileSidebar(props: &FileSidebarProps) -> Html {
                 <FileTagsList file={props.file.clone()} tags={vec![]} />
             }
 
+            <SidebarHeading>{"Similar"}</SidebarHeading>
+            <FileSimilarList file={props.file.clone()} />
+
             <SidebarHeading>{"Settings"}</SidebarHeading>
             <div class="py-2">
                 <ToggleEntry text="Show labels" />
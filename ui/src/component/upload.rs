@@ -0,0 +1,98 @@
+use crate::prelude::*;
+use gloo_net::http::Request as HttpRequest;
+use js_sys::encode_uri_component;
+use web_sys::File;
+
+/// Upload a file into the given folder of the project.
+async fn upload(folder: &str, file: File) -> Result<(), gloo_net::Error> {
+    let path: String = folder
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .chain([file.name().as_str()])
+        .map(|segment| String::from(encode_uri_component(segment)))
+        .collect::<Vec<_>>()
+        .join("/");
+    let response = HttpRequest::put(&format!("/api/v1/upload/{path}"))
+        .body(file)
+        .send()
+        .await?;
+    if !response.ok() {
+        log::error!("Uploading {path} failed: {}", response.status());
+    }
+    Ok(())
+}
+
+#[function_component]
+pub fn UploadArea() -> Html {
+    let folder = use_state(|| String::from("uploads"));
+    let active = use_state(|| false);
+    let pending = use_state(|| 0usize);
+
+    let folder_oninput = {
+        let folder = folder.clone();
+        move |event: InputEvent| {
+            let target: HtmlInputElement = event.target_dyn_into().unwrap();
+            folder.set(target.value());
+        }
+    };
+
+    let ondragover = {
+        let active = active.clone();
+        move |event: DragEvent| {
+            event.prevent_default();
+            active.set(true);
+        }
+    };
+
+    let ondragleave = {
+        let active = active.clone();
+        move |_: DragEvent| active.set(false)
+    };
+
+    let ondrop = {
+        let active = active.clone();
+        let pending = pending.clone();
+        let folder = folder.clone();
+        move |event: DragEvent| {
+            event.prevent_default();
+            active.set(false);
+            let Some(files) = event.data_transfer().and_then(|data| data.files()) else {
+                return;
+            };
+            let files: Vec<File> = (0..files.length()).filter_map(|i| files.get(i)).collect();
+            let folder = (*folder).clone();
+            let pending = pending.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let total = files.len();
+                for (index, file) in files.into_iter().enumerate() {
+                    pending.set(total - index);
+                    if let Err(error) = upload(&folder, file).await {
+                        log::error!("Error uploading: {error:?}");
+                    }
+                }
+                pending.set(0);
+            });
+        }
+    };
+
+    let border = if *active {
+        "border-blue-600 bg-blue-50 dark:bg-gray-700"
+    } else {
+        "border-gray-300 dark:border-gray-600"
+    };
+
+    html! {
+        <div class="p-2">
+            <div {ondragover} {ondragleave} {ondrop} class={classes!("flex", "flex-col", "items-center", "justify-center", "p-4", "border-2", "border-dashed", "rounded-lg", border)}>
+                <span class="text-sm text-gray-500 dark:text-gray-400">
+                    if *pending > 0 {
+                        {format!("Uploading {} files...", *pending)}
+                    } else {
+                        {"Drop files to upload"}
+                    }
+                </span>
+                <input type="text" value={(*folder).clone()} oninput={folder_oninput} class="mt-2 w-full p-1 text-sm border border-gray-300 rounded-lg" placeholder="Folder" />
+            </div>
+        </div>
+    }
+}